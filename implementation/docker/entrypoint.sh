@@ -0,0 +1,23 @@
+#!/bin/sh
+# Starts the server of the container image with its data in $USERS_DATA_DIR
+# (/data by default, a volume). On first start it generates the receipt and
+# pseudonym keys and an admin API key, whose secret is printed once.
+set -eu
+
+app=$(cd "$(dirname "$0")" && pwd)
+data=${USERS_DATA_DIR:-/data}
+export USERS_CONFIG="${USERS_CONFIG:-$app/config.toml}"
+
+mkdir -p "$data/keys"
+cd "$data"
+for key in keys/signing.key keys/pseudonym.key; do
+    if [ ! -s "$key" ]; then
+        (umask 077 && head -c 32 /dev/urandom > "$key.tmp")
+        mv "$key.tmp" "$key"
+    fi
+done
+if [ ! -e api-keys.json ]; then
+    "$app/admin" keys --file api-keys.json create admin --scope admin
+fi
+
+exec "$app/implementation" "$@"
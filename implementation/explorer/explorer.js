@@ -0,0 +1,226 @@
+"use strict";
+
+const METHODS = ["get", "post", "put", "delete", "patch"];
+const STREAMING = ["text/event-stream"];
+
+function element(tag, attributes, ...children) {
+  const node = document.createElement(tag);
+  for (const [name, value] of Object.entries(attributes || {})) {
+    if (name === "class") {
+      node.className = value;
+    } else {
+      node.setAttribute(name, value);
+    }
+  }
+  for (const child of children) {
+    node.append(child);
+  }
+  return node;
+}
+
+function resolve(spec, schema) {
+  while (schema && schema.$ref) {
+    schema = schema.$ref
+      .replace(/^#\//, "")
+      .split("/")
+      .reduce((node, key) => node[key], spec);
+  }
+  if (schema && schema.allOf) {
+    return Object.assign({}, ...schema.allOf.map((s) => resolve(spec, s)), { allOf: undefined });
+  }
+  return schema || {};
+}
+
+function newId() {
+  if (window.crypto && crypto.randomUUID) {
+    return crypto.randomUUID();
+  }
+  return "00000000-0000-4000-8000-000000000000".replace(/0/g, () => Math.floor(Math.random() * 16).toString(16));
+}
+
+/** An example value for a schema: its example, default or first enum value, else made up from its type. */
+function example(spec, schema, depth = 0) {
+  schema = resolve(spec, schema);
+  if (schema.example !== undefined) return schema.example;
+  if (schema.default !== undefined) return schema.default;
+  if (schema.enum) return schema.enum[0];
+  if (schema.oneOf) return example(spec, schema.oneOf[0], depth);
+  if (depth > 8) return null;
+  switch (schema.type) {
+    case "object": {
+      const value = {};
+      for (const [name, property] of Object.entries(schema.properties || {})) {
+        if ((schema.required || []).includes(name) || depth === 0) {
+          value[name] = example(spec, property, depth + 1);
+        }
+      }
+      return value;
+    }
+    case "array":
+      return [example(spec, schema.items, depth + 1)];
+    case "integer":
+    case "number":
+      return schema.minimum || 0;
+    case "boolean":
+      return false;
+    case "string":
+      if (schema.format === "uuid") return newId();
+      if (schema.format === "date-time") return new Date().toISOString();
+      return "";
+    default:
+      return null;
+  }
+}
+
+function parameterInput(spec, parameter) {
+  const schema = resolve(spec, parameter.schema);
+  let input;
+  if (schema.enum || schema.type === "boolean") {
+    input = element("select", { name: parameter.name });
+    if (!parameter.required) input.append(element("option", { value: "" }, ""));
+    for (const value of schema.enum || [true, false]) {
+      input.append(element("option", { value: String(value) }, String(value)));
+    }
+  } else {
+    input = element("input", { name: parameter.name, size: 40 });
+    if (parameter.required && parameter.in === "path") {
+      input.placeholder = schema.format || schema.type || "";
+    }
+  }
+  input.dataset.in = parameter.in;
+  const label = element("label", {}, `${parameter.name}${parameter.required ? " *" : ""} (${parameter.in})`);
+  if (parameter.description) label.title = parameter.description;
+  return [label, input];
+}
+
+async function send(method, path, form, output) {
+  const query = new URLSearchParams();
+  const headers = {};
+  for (const input of form.querySelectorAll("[data-in]")) {
+    if (input.value === "") continue;
+    switch (input.dataset.in) {
+      case "path":
+        path = path.replace(`{${input.name}}`, encodeURIComponent(input.value));
+        break;
+      case "query":
+        query.append(input.name, input.value);
+        break;
+      case "header":
+        headers[input.name] = input.value;
+        break;
+    }
+  }
+  const apiKey = document.getElementById("api-key").value;
+  if (apiKey) headers.Authorization = `Bearer ${apiKey}`;
+  const body = form.querySelector("textarea");
+  const init = { method: method.toUpperCase(), headers };
+  if (body) {
+    headers["Content-Type"] = form.querySelector("[name=content-type]").value;
+    init.body = body.value;
+  }
+  const url = path + (query.toString() ? `?${query}` : "");
+  output.replaceChildren(element("p", { class: "note" }, `${init.method} ${url} …`));
+  try {
+    const response = await fetch(url, init);
+    const text = await response.text();
+    let shown = text;
+    if ((response.headers.get("Content-Type") || "").startsWith("application/json") && text) {
+      shown = JSON.stringify(JSON.parse(text), null, 2);
+    }
+    const responseHeaders = [...response.headers].map(([name, value]) => `${name}: ${value}`).join("\n");
+    output.replaceChildren(
+      element("p", {}, `${response.status} ${response.statusText}`),
+      element("pre", {}, responseHeaders),
+      element("pre", {}, shown)
+    );
+  } catch (error) {
+    output.replaceChildren(element("p", { class: "error" }, String(error)));
+  }
+}
+
+function operationPanel(spec, path, pathItem, method, operation) {
+  const form = element("form", { class: "operation" });
+  if (operation.description) form.append(element("p", {}, operation.description));
+  const parameters = [...(pathItem.parameters || []), ...(operation.parameters || [])].map((p) => resolve(spec, p));
+  for (const parameter of parameters) {
+    form.append(...parameterInput(spec, parameter));
+  }
+  const responseTypes = Object.values(operation.responses || {}).flatMap((r) => Object.keys(resolve(spec, r).content || {}));
+  const output = element("div", {});
+  if (operation.operationId === "SubscribeUserChanges") {
+    form.append(element("p", { class: "note" }, "A WebSocket endpoint; connect with a WebSocket client."));
+    return form;
+  }
+  if (responseTypes.some((type) => STREAMING.includes(type))) {
+    form.append(element("p", { class: "note" }, "The response is a stream; the explorer shows it once the server closes it."));
+  }
+  if (operation.requestBody) {
+    const content = resolve(spec, operation.requestBody).content || {};
+    const contentType = element("select", { name: "content-type" });
+    for (const type of Object.keys(content)) {
+      contentType.append(element("option", { value: type }, type));
+    }
+    const body = element("textarea", { rows: 14, spellcheck: "false" });
+    const fill = () => {
+      const media = content[contentType.value];
+      body.value = contentType.value === "application/json" && media ? JSON.stringify(example(spec, media.schema), null, 2) : "";
+    };
+    contentType.addEventListener("change", fill);
+    fill();
+    form.append(element("label", {}, "Content-Type"), contentType, element("label", {}, "body"), body);
+  }
+  const button = element("button", { type: "submit" }, "Send");
+  form.append(button, output);
+  form.addEventListener("submit", (event) => {
+    event.preventDefault();
+    send(method, path, form, output);
+  });
+  return form;
+}
+
+function render(spec) {
+  document.getElementById("title").textContent = spec.info.title;
+  document.getElementById("version").textContent = spec.info.version;
+  const byTag = new Map((spec.tags || []).map((tag) => [tag.name, []]));
+  for (const [path, pathItem] of Object.entries(spec.paths)) {
+    for (const method of METHODS) {
+      const operation = pathItem[method];
+      if (!operation) continue;
+      const tag = (operation.tags || ["Other"])[0];
+      if (!byTag.has(tag)) byTag.set(tag, []);
+      byTag.get(tag).push({ path, pathItem, method, operation });
+    }
+  }
+  const main = document.getElementById("operations");
+  main.replaceChildren();
+  for (const [tag, operations] of byTag) {
+    if (operations.length === 0) continue;
+    main.append(element("h2", {}, tag));
+    for (const { path, pathItem, method, operation } of operations) {
+      const details = element(
+        "details",
+        {},
+        element(
+          "summary",
+          {},
+          element("span", { class: `method ${method}` }, method),
+          element("span", { class: "path" }, path),
+          element("span", { class: "summary" }, operation.summary || operation.operationId)
+        )
+      );
+      details.addEventListener("toggle", () => {
+        if (details.open && details.children.length === 1) {
+          details.append(operationPanel(spec, path, pathItem, method, operation));
+        }
+      }, { once: true });
+      main.append(details);
+    }
+  }
+}
+
+fetch("openapi.json")
+  .then((response) => response.json())
+  .then(render)
+  .catch((error) => {
+    document.getElementById("operations").replaceChildren(element("p", { class: "error" }, `Cannot load the OpenAPI document: ${error}`));
+  });
@@ -0,0 +1,167 @@
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::StorageConfig;
+use crate::journal::Journal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Created,
+    Updated,
+    Deleted,
+    Restored,
+    Purged,
+    Erased,
+    Merged,
+}
+
+pub const DEFAULT_LIMIT: usize = 1000;
+pub const MAX_LIMIT: usize = 10_000;
+
+/// `prev_hash` of the first entry.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// A single audit record. Users are referenced only by their pseudonym, never by
+/// id or personal data, so the log can be kept after the user is erased.
+///
+/// Entries are chained: `hash` covers the entry and the hash of the one before,
+/// so an entry changed, removed or inserted after the fact breaks the chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    pub actor: String,
+    pub action: AuditAction,
+    pub subject_ref: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// Hex SHA-256 of the entry's JSON without `hash`.
+    pub fn compute_hash(&self) -> String {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Hashed<'a> {
+            seq: u64,
+            at: &'a DateTime<Utc>,
+            actor: &'a str,
+            action: AuditAction,
+            subject_ref: &'a str,
+            prev_hash: &'a str,
+        }
+
+        let json = serde_json::to_vec(&Hashed {
+            seq: self.seq,
+            at: &self.at,
+            actor: &self.actor,
+            action: self.action,
+            subject_ref: &self.subject_ref,
+            prev_hash: &self.prev_hash,
+        })
+        .expect("audit entries are serializable");
+        hex::encode(Sha256::digest(json))
+    }
+}
+
+/// Where an audit chain stops holding together.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainBreak {
+    pub seq: u64,
+    pub reason: &'static str,
+}
+
+/// Checks a whole log, from its first entry, as returned by `GET /api/audit` or
+/// `read` from the audit journal.
+pub fn verify(entries: &[AuditEntry]) -> Result<(), ChainBreak> {
+    let mut prev_hash = GENESIS_HASH;
+    for (index, entry) in entries.iter().enumerate() {
+        let broken = |reason| Err(ChainBreak { seq: entry.seq, reason });
+        if entry.seq != index as u64 + 1 {
+            return broken("sequence gap");
+        }
+        if entry.prev_hash != prev_hash {
+            return broken("previous hash does not match");
+        }
+        if entry.hash != entry.compute_hash() {
+            return broken("hash does not match the entry");
+        }
+        prev_hash = &entry.hash;
+    }
+    Ok(())
+}
+
+/// The audit log. With the journal backend every entry is appended to the audit
+/// journal before it is kept, and the chain goes on across restarts; with the
+/// memory backend it starts over from `GENESIS_HASH` with each process.
+#[derive(Default)]
+pub struct AuditLog {
+    entries: Vec<AuditEntry>,
+    journal: Option<Journal>,
+}
+
+impl AuditLog {
+    /// Opens the configured audit journal and continues its chain.
+    pub fn open(config: &StorageConfig) -> io::Result<Self> {
+        let Some(path) = config.audit_path() else {
+            return Ok(AuditLog::default());
+        };
+        let (journal, entries) = Journal::open(&path)?;
+        Ok(AuditLog {
+            entries,
+            journal: Some(journal),
+        })
+    }
+
+    /// Appends an entry. One the journal cannot take is logged and dropped, so
+    /// the chain on disk and in memory stay the same: the change it records has
+    /// been made already.
+    pub fn record(&mut self, actor: &str, action: AuditAction, subject_ref: String) {
+        let mut entry = AuditEntry {
+            seq: self.entries.len() as u64 + 1,
+            at: Utc::now(),
+            actor: actor.into(),
+            action,
+            subject_ref,
+            prev_hash: self
+                .entries
+                .last()
+                .map_or_else(|| GENESIS_HASH.into(), |e| e.hash.clone()),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+        if let Some(journal) = &mut self.journal {
+            if let Err(e) = journal.append([&entry]) {
+                tracing::error!(seq = entry.seq, ?action, "audit entry not written: {e}");
+                return;
+            }
+        }
+        self.entries.push(entry);
+    }
+
+    pub fn count_for(&self, subject_ref: &str) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| e.subject_ref == subject_ref)
+            .count()
+    }
+
+    /// Entries after sequence number `after`, oldest first.
+    pub fn entries_after(&self, after: u64, limit: usize) -> &[AuditEntry] {
+        let start = (after as usize).min(self.entries.len());
+        let end = start.saturating_add(limit).min(self.entries.len());
+        &self.entries[start..end]
+    }
+}
+
+/// Reads an audit journal, also one a running server is appending to.
+pub fn read(path: &Path) -> io::Result<Vec<AuditEntry>> {
+    Journal::read(path)
+}
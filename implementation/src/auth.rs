@@ -0,0 +1,191 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::AuthConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    Read,
+    Write,
+    Admin,
+    /// Unmasked personal data in user reads, exports and the change feed. Only
+    /// granted explicitly, never implied by `Admin`.
+    Pii,
+}
+
+/// Request header carrying the subject of the verified TLS client certificate.
+/// It is set by the listener, which drops any value sent by the client.
+pub const CLIENT_CERT_SUBJECT: &str = "x-client-cert-subject";
+
+/// Identity of the caller, resolved from the API key or client certificate sent with the request.
+#[derive(Debug, Clone)]
+pub struct Claims {
+    pub subject: String,
+    pub scopes: BTreeSet<Scope>,
+}
+
+impl Claims {
+    fn anonymous() -> Self {
+        Claims {
+            subject: "anonymous".into(),
+            scopes: [Scope::Read, Scope::Write, Scope::Admin].into(),
+        }
+    }
+
+    /// `Admin` implies every other scope but `Pii`.
+    pub fn has(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope) || (scope != Scope::Pii && self.scopes.contains(&Scope::Admin))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKey {
+    pub subject: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_sha256: Option<String>,
+    /// Grants the scopes to clients presenting a certificate with this subject.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate_subject: Option<String>,
+    pub scopes: BTreeSet<Scope>,
+    /// A revoked key is kept in the file, for the record, but no longer accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+}
+
+/// Reads an API keys file: a JSON array of keys.
+pub fn read_keys(path: &Path) -> Result<Vec<ApiKey>, String> {
+    let content = std::fs::read(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    serde_json::from_slice(&content).map_err(|e| format!("invalid API keys file {}: {e}", path.display()))
+}
+
+/// Replaces the API keys file, so a crash leaves either the old or the new one.
+pub fn write_keys(path: &Path, keys: &[ApiKey]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut content = serde_json::to_vec_pretty(keys)?;
+    content.push(b'\n');
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// A random 256-bit secret, hex encoded.
+pub fn generate_secret() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+/// The `secretSha256` of a key with this secret.
+pub fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+/// Who sent a request, as far as rate limiting is concerned.
+pub enum Identity {
+    /// No credentials were presented, or authentication is disabled.
+    Anonymous,
+    /// Credentials were presented and matched; carries the subject.
+    Authenticated(String),
+    /// Credentials were presented and did not match.
+    Rejected,
+}
+
+pub enum Authenticator {
+    /// `auth.open`: every caller is treated as an anonymous administrator.
+    Open,
+    Keys(Vec<ApiKey>),
+}
+
+impl Authenticator {
+    /// Reads API keys from the configured JSON file.
+    pub fn load(config: &AuthConfig) -> Result<Self, String> {
+        let Some(path) = &config.api_keys_file else {
+            if !config.open {
+                return Err("auth.api_keys_file: required unless auth.open is set".into());
+            }
+            tracing::warn!("auth.open is set, authentication is disabled");
+            return Ok(Authenticator::Open);
+        };
+        Ok(Authenticator::Keys(read_keys(path)?))
+    }
+
+    pub fn authenticate(&self, headers: &HeaderMap, key: &str) -> Option<Claims> {
+        let keys = match self {
+            Authenticator::Open => {
+                let mut claims = Claims::anonymous();
+                if let Some(subject) = certificate_subject(headers) {
+                    claims.subject = subject.into();
+                }
+                return Some(claims);
+            }
+            Authenticator::Keys(keys) => keys,
+        };
+        if let Some(secret) = presented_secret(headers, key) {
+            let digest = hash_secret(secret);
+            return keys
+                .iter()
+                .filter(|k| !k.is_revoked())
+                .find(|k| k.secret_sha256.as_ref().is_some_and(|s| s.eq_ignore_ascii_case(&digest)))
+                .map(|k| Claims {
+                    subject: k.subject.clone(),
+                    scopes: k.scopes.clone(),
+                });
+        }
+        // A verified client certificate identifies the caller; scopes come from a
+        // matching key entry, if any.
+        let subject = certificate_subject(headers)?;
+        let scopes = keys
+            .iter()
+            .filter(|k| !k.is_revoked())
+            .find(|k| k.certificate_subject.as_deref() == Some(subject))
+            .map(|k| k.scopes.clone())
+            .unwrap_or_default();
+        Some(Claims {
+            subject: subject.into(),
+            scopes,
+        })
+    }
+
+    /// Unlike `authenticate`, tells requests without credentials apart from
+    /// requests whose credentials were rejected.
+    pub fn identify(&self, headers: &HeaderMap, key: &str) -> Identity {
+        if matches!(self, Authenticator::Open) {
+            return Identity::Anonymous;
+        }
+        if presented_secret(headers, key).is_none() && certificate_subject(headers).is_none() {
+            return Identity::Anonymous;
+        }
+        match self.authenticate(headers, key) {
+            Some(claims) => Identity::Authenticated(claims.subject),
+            None => Identity::Rejected,
+        }
+    }
+}
+
+/// The secret is taken from the `key` header, or from `Authorization: Bearer ...`.
+fn presented_secret<'a>(headers: &'a HeaderMap, key: &str) -> Option<&'a str> {
+    if let Some(value) = headers.get(key) {
+        return value.to_str().ok();
+    }
+    headers
+        .get(http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+fn certificate_subject(headers: &HeaderMap) -> Option<&str> {
+    headers.get(CLIENT_CERT_SUBJECT)?.to_str().ok()
+}
@@ -0,0 +1,130 @@
+//! Mixed create, update and delete operations sent in one request.
+
+use http::StatusCode;
+use openapi::apis::users::{CreateUserResponse, DeleteUserResponse, UpdateUserResponse};
+use openapi::models::{CreateRequest, Error, RequestHeader, ResponseHeader, UpdateRequest, UserResponse};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    /// Every operation is applied, or none is
+    #[default]
+    Transactional,
+    /// Each operation is applied if it succeeds on its own
+    Independent,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchRequest {
+    pub request_header: RequestHeader,
+    #[serde(default)]
+    pub mode: BatchMode,
+    pub operations: Vec<BatchOperation>,
+}
+
+/// One operation, with the body its single-user endpoint takes.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "operation", rename_all = "snake_case")]
+pub enum BatchOperation {
+    Create(CreateRequest),
+    Update {
+        id: Uuid,
+        #[serde(flatten)]
+        request: UpdateRequest,
+    },
+    Delete {
+        id: Uuid,
+        #[serde(rename = "requestHeader")]
+        request_header: RequestHeader,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum ResultBody {
+    User(UserResponse),
+    Error(Error),
+}
+
+/// What the single-user endpoint would have answered, as status and body.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationResult {
+    pub index: usize,
+    /// From the operation's own request header.
+    pub request_id: Uuid,
+    pub operation: &'static str,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<ResultBody>,
+}
+
+impl OperationResult {
+    pub fn new(
+        index: usize,
+        request_id: Uuid,
+        operation: &'static str,
+        status: StatusCode,
+        body: Option<ResultBody>,
+    ) -> Self {
+        OperationResult {
+            index,
+            request_id,
+            operation,
+            status: status.as_u16(),
+            body,
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        StatusCode::from_u16(self.status).is_ok_and(|s| s.is_success())
+    }
+
+    /// Replaces the result of an operation that succeeded but was rolled back
+    /// because another operation of a transactional batch failed.
+    pub fn abort(&mut self) {
+        self.status = StatusCode::FAILED_DEPENDENCY.as_u16();
+        self.body = Some(ResultBody::Error(Error::new(
+            ResponseHeader::new(self.request_id, chrono::Utc::now()),
+            "BATCH_ABORTED".into(),
+        )));
+    }
+}
+
+pub fn create_result(index: usize, request_id: Uuid, response: CreateUserResponse) -> OperationResult {
+    use CreateUserResponse::*;
+    let (status, body) = match response {
+        Status201_UserCreatedSuccessfully(user) => (StatusCode::CREATED, ResultBody::User(user)),
+        Status400_BadRequest(e) => (StatusCode::BAD_REQUEST, ResultBody::Error(e)),
+        Status401_Unauthorized(e) => (StatusCode::UNAUTHORIZED, ResultBody::Error(e)),
+        Status422_UnprocessableEntity(e) => (StatusCode::UNPROCESSABLE_ENTITY, ResultBody::Error(e)),
+    };
+    OperationResult::new(index, request_id, "create", status, Some(body))
+}
+
+pub fn update_result(index: usize, request_id: Uuid, response: UpdateUserResponse) -> OperationResult {
+    use UpdateUserResponse::*;
+    let (status, body) = match response {
+        Status200_Success(user) => (StatusCode::OK, ResultBody::User(user)),
+        Status400_BadRequest(e) => (StatusCode::BAD_REQUEST, ResultBody::Error(e)),
+        Status401_Unauthorized(e) => (StatusCode::UNAUTHORIZED, ResultBody::Error(e)),
+        Status404_UserNotFound(e) => (StatusCode::NOT_FOUND, ResultBody::Error(e)),
+        Status422_UnprocessableEntity(e) => (StatusCode::UNPROCESSABLE_ENTITY, ResultBody::Error(e)),
+    };
+    OperationResult::new(index, request_id, "update", status, Some(body))
+}
+
+pub fn delete_result(index: usize, request_id: Uuid, response: DeleteUserResponse) -> OperationResult {
+    use DeleteUserResponse::*;
+    let (status, body) = match response {
+        Status204_NoContent => (StatusCode::NO_CONTENT, None),
+        Status400_BadRequest(e) => (StatusCode::BAD_REQUEST, Some(e)),
+        Status401_Unauthorized(e) => (StatusCode::UNAUTHORIZED, Some(e)),
+        Status404_UserNotFound(e) => (StatusCode::NOT_FOUND, Some(e)),
+        Status422_UnprocessableEntity(e) => (StatusCode::UNPROCESSABLE_ENTITY, Some(e)),
+    };
+    OperationResult::new(index, request_id, "delete", status, body.map(ResultBody::Error))
+}
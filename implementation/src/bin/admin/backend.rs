@@ -0,0 +1,337 @@
+//! The two ways of reaching users: the API of a running server, or the store
+//! opened in this process. User operations answer with the same responses
+//! either way.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use axum::extract::Host;
+use axum_extra::extract::CookieJar;
+use bytes::Bytes;
+use http::header::{AUTHORIZATION, CONTENT_TYPE};
+use http::{Method, StatusCode};
+use http_body_util::{BodyExt, Full};
+use openapi::apis::users::{
+    CreateUserResponse, DeleteUserResponse, GetAllUsersResponse, GetUserByIdResponse, UpdateUserResponse, Users,
+};
+use openapi::client::{http_client, Auth, HttpClient, UsersClient};
+use openapi::models::{
+    CreateRequest, DeleteUserPathParams, GetAllUsersQueryParams, GetUserByIdPathParams, RequestHeader, UpdateRequest,
+    UpdateUserPathParams, User,
+};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use implementation::audit::{self, AuditEntry, AuditLog};
+use implementation::auth::{Authenticator, Claims, Scope};
+use implementation::config::Config;
+use implementation::error::ApiError;
+use implementation::fixtures::{Encoder, Format};
+use implementation::import::{self, ImportOptions, ImportReport};
+use implementation::keys::KeyMaterial;
+use implementation::search::{SearchHit, SearchRequest};
+use implementation::server::ServerImpl;
+use implementation::store::UserStore;
+
+/// Subject recorded as the actor of offline changes.
+const ACTOR: &str = "admin-cli";
+/// Users read from the store per chunk of an offline export.
+const CHUNK: usize = 500;
+
+pub enum Backend {
+    Remote(Box<Remote>),
+    Offline(ServerImpl),
+}
+
+pub struct Remote {
+    base_url: String,
+    api_key: Option<String>,
+    users: UsersClient,
+    client: HttpClient,
+}
+
+impl Backend {
+    pub fn remote(url: &str, api_key: Option<&str>) -> Result<Self, String> {
+        let mut users = UsersClient::new(url).map_err(|e| e.to_string())?;
+        if let Some(key) = api_key {
+            users = users.with_auth(Auth::Bearer(key.to_owned()));
+        }
+        Ok(Backend::Remote(Box::new(Remote {
+            base_url: url.trim_end_matches('/').to_owned(),
+            api_key: api_key.map(str::to_owned),
+            users,
+            client: http_client(),
+        })))
+    }
+
+    /// Opens the configured journal in this process. Fails while a server has
+    /// it open.
+    pub fn offline(config: &Config) -> Result<Self, String> {
+        if config.storage.journal_path().is_none() {
+            return Err("the memory backend keeps users inside the server; pass --url, or a --config with the journal backend".into());
+        }
+        let keys = KeyMaterial::load(&config.auth)?;
+        let users = UserStore::open(&config.storage).map_err(|e| format!("storage: {e}"))?;
+        let audit = AuditLog::open(&config.storage).map_err(|e| format!("audit: {e}"))?;
+        Ok(Backend::Offline(ServerImpl::new(Authenticator::Open, keys, users, audit)))
+    }
+
+    pub async fn list(&self, include_deleted: bool) -> Result<GetAllUsersResponse, String> {
+        let query_params = GetAllUsersQueryParams {
+            include_deleted: Some(include_deleted),
+        };
+        match self {
+            Backend::Remote(remote) => remote.users.get_all_users(&query_params).await.map_err(|e| e.to_string()),
+            Backend::Offline(api_impl) => api_impl
+                .get_all_users(Method::GET, host(), CookieJar::new(), claims(), query_params)
+                .await
+                .map_err(|e| format!("list failed: {e:?}")),
+        }
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<GetUserByIdResponse, String> {
+        match self {
+            Backend::Remote(remote) => remote.users.get_user_by_id(id).await.map_err(|e| e.to_string()),
+            Backend::Offline(api_impl) => api_impl
+                .get_user_by_id(Method::GET, host(), CookieJar::new(), claims(), GetUserByIdPathParams { id })
+                .await
+                .map_err(|e| format!("get failed: {e:?}")),
+        }
+    }
+
+    pub async fn create(&self, user: User) -> Result<CreateUserResponse, String> {
+        match self {
+            Backend::Remote(remote) => remote.users.create_user(user).await.map_err(|e| e.to_string()),
+            Backend::Offline(api_impl) => {
+                let body = CreateRequest::new(request_header(), user);
+                let response = api_impl
+                    .create_user(Method::POST, host(), CookieJar::new(), claims(), body)
+                    .await;
+                flushed(api_impl, response).await
+            }
+        }
+    }
+
+    pub async fn update(&self, id: Uuid, user: User) -> Result<UpdateUserResponse, String> {
+        match self {
+            Backend::Remote(remote) => remote.users.update_user(id, user).await.map_err(|e| e.to_string()),
+            Backend::Offline(api_impl) => {
+                let body = UpdateRequest::new(request_header(), user);
+                let response = api_impl
+                    .update_user(Method::PUT, host(), CookieJar::new(), claims(), UpdateUserPathParams { id }, body)
+                    .await;
+                flushed(api_impl, response).await
+            }
+        }
+    }
+
+    pub async fn delete(&self, id: Uuid) -> Result<DeleteUserResponse, String> {
+        match self {
+            Backend::Remote(remote) => remote.users.delete_user(id).await.map_err(|e| e.to_string()),
+            Backend::Offline(api_impl) => {
+                let response = api_impl
+                    .delete_user(Method::DELETE, host(), CookieJar::new(), claims(), DeleteUserPathParams { id })
+                    .await;
+                flushed(api_impl, response).await
+            }
+        }
+    }
+
+    pub async fn import(&self, file: &Path, format: Format, options: ImportOptions) -> Result<ImportReport, String> {
+        #[derive(Deserialize)]
+        struct ImportUsersResponse {
+            report: ImportReport,
+        }
+
+        match self {
+            Backend::Remote(remote) => {
+                let body = std::fs::read(file).map_err(|e| format!("cannot read {}: {e}", file.display()))?;
+                let query = serde_urlencoded::to_string([
+                    ("mode", serde_json::to_value(options.mode).map_err(|e| e.to_string())?.as_str().unwrap_or_default()),
+                    ("dryRun", if options.dry_run { "true" } else { "false" }),
+                ])
+                .map_err(|e| e.to_string())?;
+                let (status, body) = remote
+                    .call(Method::POST, &format!("/api/users/import?{query}"), Some((format, body.into())))
+                    .await?;
+                // All or nothing with rejected rows answers 422, with the report.
+                if status != StatusCode::OK && status != StatusCode::UNPROCESSABLE_ENTITY {
+                    return Err(unexpected(status, &body));
+                }
+                Ok(decode::<ImportUsersResponse>(&body)?.report)
+            }
+            Backend::Offline(api_impl) => {
+                let reader = File::open(file).map_err(|e| format!("cannot read {}: {e}", file.display()))?;
+                let rows = import::read(reader, format);
+                let report = api_impl
+                    .import_users(ACTOR, rows, options)
+                    .await
+                    .map_err(|e| format!("import failed: {e:?}"))?;
+                flushed(api_impl, Ok(report)).await
+            }
+        }
+    }
+
+    /// Writes every user to standard output as it is read.
+    pub async fn export(&self, format: Format, include_deleted: bool) -> Result<(), String> {
+        let mut stdout = std::io::stdout().lock();
+        match self {
+            Backend::Remote(remote) => {
+                let format_name = serde_json::to_value(format).map_err(|e| e.to_string())?;
+                let path = format!(
+                    "/api/users/export?format={}&includeDeleted={include_deleted}",
+                    format_name.as_str().unwrap_or_default()
+                );
+                let response = remote.send(Method::GET, &path, None).await?;
+                if response.status() != StatusCode::OK {
+                    let status = response.status();
+                    let body = response.into_body().collect().await.map(|b| b.to_bytes()).unwrap_or_default();
+                    return Err(unexpected(status, &body));
+                }
+                let mut body = response.into_body();
+                while let Some(frame) = body.frame().await {
+                    let frame = frame.map_err(|e| format!("export interrupted: {e}"))?;
+                    if let Some(data) = frame.data_ref() {
+                        stdout.write_all(data).map_err(|e| e.to_string())?;
+                    }
+                }
+            }
+            Backend::Offline(api_impl) => {
+                let mut encoder = Encoder::new(format);
+                let mut after = None;
+                loop {
+                    let users = api_impl.users_page(after, CHUNK, include_deleted).await;
+                    let Some((last, _, _)) = users.last() else { break };
+                    after = Some(*last);
+                    let chunk = encoder
+                        .chunk_with_profiles(users.iter().map(|(_, user, profile)| (user, profile)))
+                        .map_err(|e| e.to_string())?;
+                    stdout.write_all(&chunk).map_err(|e| e.to_string())?;
+                }
+                stdout.write_all(&encoder.finish().map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            }
+        }
+        stdout.flush().map_err(|e| e.to_string())
+    }
+
+    pub async fn search(&self, request: &SearchRequest) -> Result<Vec<SearchHit>, String> {
+        #[derive(Deserialize)]
+        struct SearchResponse {
+            results: Vec<SearchHit>,
+        }
+
+        match self {
+            Backend::Remote(remote) => {
+                let body = serde_json::to_vec(request).map_err(|e| e.to_string())?;
+                let (status, body) = remote
+                    .call(Method::POST, "/api/users/search", Some((Format::Json, body.into())))
+                    .await?;
+                if status != StatusCode::OK {
+                    return Err(unexpected(status, &body));
+                }
+                Ok(decode::<SearchResponse>(&body)?.results)
+            }
+            Backend::Offline(api_impl) => Ok(api_impl.search_users(request, true).await),
+        }
+    }
+
+    /// The whole audit log, oldest entry first.
+    pub async fn audit_log(&self) -> Result<Vec<AuditEntry>, String> {
+        #[derive(Deserialize)]
+        struct AuditLogResponse {
+            entries: Vec<AuditEntry>,
+        }
+
+        let Backend::Remote(remote) = self else {
+            return Err("audit: an offline audit log is read from the audit journal, not the store".into());
+        };
+        let mut entries: Vec<AuditEntry> = Vec::new();
+        loop {
+            let after = entries.last().map_or(0, |e| e.seq);
+            let path = format!("/api/audit?after={after}&limit={}", audit::MAX_LIMIT);
+            let (status, body) = remote.call(Method::GET, &path, None).await?;
+            if status != StatusCode::OK {
+                return Err(unexpected(status, &body));
+            }
+            let page = decode::<AuditLogResponse>(&body)?.entries;
+            if page.is_empty() {
+                return Ok(entries);
+            }
+            entries.extend(page);
+        }
+    }
+}
+
+impl Remote {
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<(Format, Bytes)>,
+    ) -> Result<http::Response<hyper::body::Incoming>, String> {
+        let mut request = http::Request::builder().method(method).uri(format!("{}{path}", self.base_url));
+        if let Some(key) = &self.api_key {
+            request = request.header(AUTHORIZATION, format!("Bearer {key}"));
+        }
+        let body = match body {
+            Some((format, body)) => {
+                request = request.header(CONTENT_TYPE, format.media_type());
+                body
+            }
+            None => Bytes::new(),
+        };
+        let request = request.body(Full::new(body)).map_err(|e| e.to_string())?;
+        self.client
+            .request(request)
+            .await
+            .map_err(|e| format!("{}: {e}", self.base_url))
+    }
+
+    async fn call(&self, method: Method, path: &str, body: Option<(Format, Bytes)>) -> Result<(StatusCode, Bytes), String> {
+        let response = self.send(method, path, body).await?;
+        let status = response.status();
+        let body = response.into_body().collect().await.map_err(|e| e.to_string())?.to_bytes();
+        Ok((status, body))
+    }
+}
+
+/// Syncs the store after an offline change, so it is on disk before the tool exits.
+async fn flushed<T>(api_impl: &ServerImpl, response: Result<T, ApiError>) -> Result<T, String> {
+    let response = response.map_err(|e| format!("write failed: {e:?}"))?;
+    api_impl.flush().await.map_err(|e| format!("storage: {e}"))?;
+    Ok(response)
+}
+
+fn host() -> Host {
+    Host("localhost".into())
+}
+
+fn claims() -> Claims {
+    Claims {
+        subject: ACTOR.into(),
+        scopes: [Scope::Admin].into(),
+    }
+}
+
+fn request_header() -> RequestHeader {
+    RequestHeader::new(Uuid::new_v4(), chrono::Utc::now())
+}
+
+fn decode<T: DeserializeOwned>(body: &[u8]) -> Result<T, String> {
+    serde_json::from_slice(body).map_err(|e| format!("unexpected response: {e}"))
+}
+
+/// The error code of an error response, or the status when there is none.
+fn unexpected(status: StatusCode, body: &[u8]) -> String {
+    #[derive(Deserialize)]
+    struct ErrorBody {
+        code: String,
+    }
+
+    match serde_json::from_slice::<ErrorBody>(body) {
+        Ok(error) => format!("{} {}", status.as_u16(), error.code),
+        Err(_) => status.to_string(),
+    }
+}
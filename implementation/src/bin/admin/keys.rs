@@ -0,0 +1,133 @@
+//! Management of the API keys file. Only the SHA-256 of a secret is stored; the
+//! secret itself is printed once, when it is made.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use implementation::auth::{self, ApiKey, Scope};
+
+use crate::output::{self, Output};
+use crate::KeysCommand;
+
+/// A key as listed: everything but the secret's hash.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyView<'a> {
+    subject: &'a str,
+    scopes: &'a BTreeSet<Scope>,
+    has_secret: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    certificate_subject: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct IssuedSecret<'a> {
+    subject: &'a str,
+    secret: &'a str,
+}
+
+pub fn run(path: &Path, command: KeysCommand, output: Output) -> Result<(), String> {
+    let mut keys = if path.exists() || !matches!(command, KeysCommand::Create { .. }) {
+        auth::read_keys(path)?
+    } else {
+        Vec::new()
+    };
+    match command {
+        KeysCommand::List => list(&keys, output),
+        KeysCommand::Create {
+            subject,
+            scopes,
+            certificate_subject,
+        } => {
+            if active(&mut keys, &subject).is_some() {
+                return Err(format!("keys: {subject} already has a key; rotate or revoke it"));
+            }
+            let secret = auth::generate_secret();
+            keys.push(ApiKey {
+                subject: subject.clone(),
+                secret_sha256: Some(auth::hash_secret(&secret)),
+                certificate_subject,
+                scopes: scopes.into_iter().collect(),
+                revoked_at: None,
+            });
+            save(path, &keys)?;
+            issued(output, &subject, &secret)
+        }
+        KeysCommand::Revoke { subject } => {
+            let key = active(&mut keys, &subject).ok_or_else(|| no_key(&subject))?;
+            key.revoked_at = Some(Utc::now());
+            save(path, &keys)?;
+            output::message(output, &format!("revoked the key of {subject}"))
+        }
+        KeysCommand::Rotate { subject } => {
+            let key = active(&mut keys, &subject).ok_or_else(|| no_key(&subject))?;
+            let secret = auth::generate_secret();
+            key.secret_sha256 = Some(auth::hash_secret(&secret));
+            save(path, &keys)?;
+            issued(output, &subject, &secret)
+        }
+    }
+}
+
+fn list(keys: &[ApiKey], output: Output) -> Result<(), String> {
+    let views: Vec<KeyView> = keys
+        .iter()
+        .map(|k| KeyView {
+            subject: &k.subject,
+            scopes: &k.scopes,
+            has_secret: k.secret_sha256.is_some(),
+            certificate_subject: k.certificate_subject.as_deref(),
+            revoked_at: k.revoked_at,
+        })
+        .collect();
+    if output == Output::Json {
+        return output::json(&views);
+    }
+    let rows: Vec<Vec<String>> = views
+        .iter()
+        .map(|k| {
+            vec![
+                k.subject.to_owned(),
+                k.scopes
+                    .iter()
+                    .filter_map(|s| s.to_possible_value().map(|v| v.get_name().to_owned()))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                if k.has_secret { "yes" } else { "no" }.to_owned(),
+                k.certificate_subject.unwrap_or_default().to_owned(),
+                k.revoked_at.map_or_else(|| "active".to_owned(), |at| format!("revoked {}", at.to_rfc3339())),
+            ]
+        })
+        .collect();
+    output::table(&["SUBJECT", "SCOPES", "SECRET", "CERTIFICATE SUBJECT", "STATUS"], &rows);
+    Ok(())
+}
+
+fn active<'a>(keys: &'a mut [ApiKey], subject: &str) -> Option<&'a mut ApiKey> {
+    keys.iter_mut().find(|k| k.subject == subject && !k.is_revoked())
+}
+
+fn no_key(subject: &str) -> String {
+    format!("keys: {subject} has no active key")
+}
+
+fn save(path: &Path, keys: &[ApiKey]) -> Result<(), String> {
+    auth::write_keys(path, keys).map_err(|e| format!("cannot write {}: {e}", path.display()))
+}
+
+fn issued(output: Output, subject: &str, secret: &str) -> Result<(), String> {
+    match output {
+        Output::Json => output::json(&IssuedSecret { subject, secret }),
+        Output::Table => {
+            println!("{secret}");
+            eprintln!("secret of {subject}; it is not stored and cannot be shown again. Restart the server to apply.");
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! Administration of the users service, against a running server (`--url`) or
+//! directly on the store configured for it (`--config`), with the server stopped.
+
+mod backend;
+mod keys;
+mod output;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use openapi::apis::users::{
+    CreateUserResponse, DeleteUserResponse, GetAllUsersResponse, GetUserByIdResponse, UpdateUserResponse,
+};
+use openapi::models::{Error, User};
+use serde::Serialize;
+use uuid::Uuid;
+
+use implementation::audit::{self, AuditEntry, ChainBreak};
+use implementation::auth::Scope;
+use implementation::config::Config;
+use implementation::fixtures::Format;
+use implementation::import::{ImportMode, ImportOptions};
+use implementation::search::SearchRequest;
+use implementation::store::UserStore;
+
+use backend::Backend;
+use output::Output;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Administration of the users service")]
+struct Cli {
+    /// Base URL of a running server, e.g. http://localhost:8080
+    #[arg(long, env = "USERS_ADMIN_URL", global = true, conflicts_with = "config")]
+    url: Option<String>,
+
+    /// API key sent to the server with --url
+    #[arg(long, env = "USERS_ADMIN_API_KEY", global = true, hide_env_values = true)]
+    api_key: Option<String>,
+
+    /// Server configuration file; its store is opened directly. Stop the server
+    /// first: it does not see changes made by another process.
+    #[arg(long, env = "USERS_CONFIG", global = true)]
+    config: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = Output::Table, global = true)]
+    output: Output,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List users
+    List {
+        #[arg(long)]
+        include_deleted: bool,
+    },
+    /// Show a user
+    Get { id: Uuid },
+    /// Create a user from a JSON file, or standard input with `-`
+    Create { file: PathBuf },
+    /// Replace a user with the one in a JSON file, or standard input with `-`
+    Update { id: Uuid, file: PathBuf },
+    /// Soft-delete a user
+    Delete { id: Uuid },
+    /// Import users from a .csv, .ndjson or .json file
+    Import {
+        file: PathBuf,
+        #[arg(long, value_enum, default_value_t = ImportMode::AllOrNothing)]
+        mode: ImportMode,
+        /// Check every row and print the report without importing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write every user to standard output
+    Export {
+        #[arg(long, value_enum, default_value_t = Format::Ndjson)]
+        format: Format,
+        #[arg(long)]
+        include_deleted: bool,
+    },
+    /// Find users by personal id, email or fuzzy name
+    Search {
+        #[arg(long)]
+        personal_id: Option<String>,
+        #[arg(long)]
+        email: Option<String>,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        surname: Option<String>,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Manage the API keys file. The server reads it at startup.
+    Keys {
+        /// API keys file; defaults to auth.api_keys_file of --config
+        #[arg(long, env = "USERS_API_KEYS_FILE")]
+        file: Option<PathBuf>,
+        #[command(subcommand)]
+        command: KeysCommand,
+    },
+    /// Audit log, of a running server with --url or read from the audit
+    /// journal of --config, also while a server appends to it
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+    /// Rewrite the journal in the current format, keeping a copy of the old one
+    Migrate {
+        /// Read the journal and report without rewriting it
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum KeysCommand {
+    /// List keys, without their secrets
+    List,
+    /// Add a key and print its secret, which is not stored
+    Create {
+        subject: String,
+        #[arg(long = "scope", value_enum, required = true)]
+        scopes: Vec<Scope>,
+        /// Also grant the scopes to clients presenting a certificate with this subject
+        #[arg(long)]
+        certificate_subject: Option<String>,
+    },
+    /// Stop accepting the subject's key; it stays in the file, marked revoked
+    Revoke { subject: String },
+    /// Replace the secret of the subject's key and print the new one
+    Rotate { subject: String },
+}
+
+#[derive(Debug, Subcommand)]
+enum AuditCommand {
+    /// Check that no audit entry was changed, removed or inserted
+    Verify,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), String> {
+    let output = cli.output;
+    match cli.command {
+        Command::Keys { file, command } => {
+            let path = match file {
+                Some(file) => file,
+                None => server_config(&cli.config)?
+                    .auth
+                    .api_keys_file
+                    .ok_or("keys: pass --file or a --config with auth.api_keys_file")?,
+            };
+            return keys::run(&path, command, output);
+        }
+        Command::Migrate { dry_run } => return migrate(&server_config(&cli.config)?, dry_run, output),
+        Command::Audit { command: AuditCommand::Verify } if cli.url.is_none() => {
+            let path = server_config(&cli.config)?.storage.audit_path().ok_or(
+                "audit: the memory backend keeps the audit log inside the server; pass --url, or a --config with the journal backend",
+            )?;
+            let entries = audit::read(&path).map_err(|e| format!("audit: {e}"))?;
+            return verify_audit(output, &entries);
+        }
+        _ => {}
+    }
+
+    let backend = match &cli.url {
+        Some(url) => Backend::remote(url, cli.api_key.as_deref())?,
+        None => Backend::offline(&server_config(&cli.config)?)?,
+    };
+    match cli.command {
+        Command::List { include_deleted } => match backend.list(include_deleted).await? {
+            GetAllUsersResponse::Status200_Success(response) => output::users(output, &response.users_list),
+            GetAllUsersResponse::Status400_BadRequest(e)
+            | GetAllUsersResponse::Status401_Unauthorized(e)
+            | GetAllUsersResponse::Status422_UnprocessableEntity(e) => Err(failure(e)),
+        },
+        Command::Get { id } => match backend.get(id).await? {
+            GetUserByIdResponse::Status200_Success(response) => output::users(output, &[response.user]),
+            GetUserByIdResponse::Status400_BadRequest(e)
+            | GetUserByIdResponse::Status401_Unauthorized(e)
+            | GetUserByIdResponse::Status422_UnprocessableEntity(e) => Err(failure(e)),
+            GetUserByIdResponse::Status404_UserNotFound(_) => Err(format!("no user {id}")),
+        },
+        Command::Create { file } => match backend.create(read_user(&file)?).await? {
+            CreateUserResponse::Status201_UserCreatedSuccessfully(response) => {
+                output::users(output, &[response.user])
+            }
+            CreateUserResponse::Status400_BadRequest(e)
+            | CreateUserResponse::Status401_Unauthorized(e)
+            | CreateUserResponse::Status422_UnprocessableEntity(e) => Err(failure(e)),
+        },
+        Command::Update { id, file } => match backend.update(id, read_user(&file)?).await? {
+            UpdateUserResponse::Status200_Success(response) => output::users(output, &[response.user]),
+            UpdateUserResponse::Status400_BadRequest(e)
+            | UpdateUserResponse::Status401_Unauthorized(e)
+            | UpdateUserResponse::Status422_UnprocessableEntity(e) => Err(failure(e)),
+            UpdateUserResponse::Status404_UserNotFound(_) => Err(format!("no user {id}")),
+        },
+        Command::Delete { id } => match backend.delete(id).await? {
+            DeleteUserResponse::Status204_NoContent => output::message(output, &format!("deleted {id}")),
+            DeleteUserResponse::Status400_BadRequest(e)
+            | DeleteUserResponse::Status401_Unauthorized(e)
+            | DeleteUserResponse::Status422_UnprocessableEntity(e) => Err(failure(e)),
+            DeleteUserResponse::Status404_UserNotFound(_) => Err(format!("no user {id}")),
+        },
+        Command::Import { file, mode, dry_run } => {
+            let format = Format::from_path(&file)
+                .ok_or_else(|| format!("{}: unknown format, expected .json, .ndjson or .csv", file.display()))?;
+            let report = backend.import(&file, format, ImportOptions { mode, dry_run }).await?;
+            output::import_report(output, &report)?;
+            if report.rejected > 0 && mode == ImportMode::AllOrNothing {
+                return Err(format!("{} rows rejected, nothing imported", report.rejected));
+            }
+            Ok(())
+        }
+        Command::Export { format, include_deleted } => backend.export(format, include_deleted).await,
+        Command::Search { personal_id, email, name, surname, limit } => {
+            let request = SearchRequest {
+                request_header: openapi::models::RequestHeader::new(Uuid::new_v4(), chrono::Utc::now()),
+                personal_id,
+                email,
+                name,
+                surname,
+                limit,
+            };
+            if request.is_empty() {
+                return Err("search: pass at least one of --personal-id, --email, --name, --surname".into());
+            }
+            output::search_hits(output, &backend.search(&request).await?)
+        }
+        Command::Audit { command: AuditCommand::Verify } => verify_audit(output, &backend.audit_log().await?),
+        Command::Keys { .. } | Command::Migrate { .. } => unreachable!("handled above"),
+    }
+}
+
+/// The configuration a server started with `--config` and the current
+/// `USERS_*` environment would run with.
+fn server_config(path: &Option<PathBuf>) -> Result<Config, String> {
+    Config::from_env(path.as_deref()).map_err(|errors| format!("invalid configuration:\n  {}", errors.join("\n  ")))
+}
+
+fn read_user(path: &Path) -> Result<User, String> {
+    let mut content = Vec::new();
+    let read = if path == Path::new("-") {
+        std::io::stdin().read_to_end(&mut content)
+    } else {
+        File::open(path).and_then(|mut f| f.read_to_end(&mut content))
+    };
+    read.map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    serde_json::from_slice(&content).map_err(|e| format!("{}: not a user: {e}", path.display()))
+}
+
+/// The error response as a message, e.g. `404` or `422 USER_ALREADY_EXISTS`.
+fn failure(error: Error) -> String {
+    match error.message {
+        Some(message) => format!("{}: {message}", error.code),
+        None => error.code,
+    }
+}
+
+fn verify_audit(output: Output, entries: &[AuditEntry]) -> Result<(), String> {
+    let result = audit::verify(entries);
+    print_verification(output, entries.len(), result.as_ref().err())?;
+    result.map_err(|b| format!("audit chain broken at entry {}: {}", b.seq, b.reason))
+}
+
+fn print_verification(output: Output, entries: usize, broken: Option<&ChainBreak>) -> Result<(), String> {
+    #[derive(Serialize)]
+    struct Verification<'a> {
+        entries: usize,
+        intact: bool,
+        #[serde(rename = "break", skip_serializing_if = "Option::is_none")]
+        chain_break: Option<&'a ChainBreak>,
+    }
+
+    match output {
+        Output::Json => output::json(&Verification {
+            entries,
+            intact: broken.is_none(),
+            chain_break: broken,
+        }),
+        Output::Table if broken.is_none() => output::message(output, &format!("audit chain intact, {entries} entries")),
+        // The error message says where.
+        Output::Table => Ok(()),
+    }
+}
+
+/// Replays the journal, which reads every line written by earlier versions, and
+/// writes it back one line per user in the current format.
+fn migrate(config: &Config, dry_run: bool, output: Output) -> Result<(), String> {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Migration {
+        path: PathBuf,
+        lines_read: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        lines_written: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        backup: Option<PathBuf>,
+    }
+
+    let Some(path) = config.storage.journal_path().map(Path::to_path_buf) else {
+        return Err("migrate: needs the journal backend".into());
+    };
+    let mut store = UserStore::open(&config.storage).map_err(|e| format!("storage: {e}"))?;
+    let mut migration = Migration {
+        lines_read: store.replayed().unwrap_or_default(),
+        path,
+        lines_written: None,
+        backup: None,
+    };
+    if !dry_run {
+        let backup = migration
+            .path
+            .with_extension(format!("{}.bak", chrono::Utc::now().format("%Y%m%dT%H%M%SZ")));
+        std::fs::copy(&migration.path, &backup)
+            .map_err(|e| format!("cannot copy {} to {}: {e}", migration.path.display(), backup.display()))?;
+        migration.backup = Some(backup);
+        migration.lines_written = Some(store.rewrite().map_err(|e| format!("storage: {e}"))?);
+    }
+    match output {
+        Output::Json => output::json(&migration),
+        Output::Table => {
+            let mut message = format!("{}: {} lines read", migration.path.display(), migration.lines_read);
+            if let (Some(written), Some(backup)) = (migration.lines_written, &migration.backup) {
+                message.push_str(&format!(", {written} written, previous journal kept as {}", backup.display()));
+            }
+            output::message(output, &message)
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Printing of results, as aligned columns for people or JSON for scripts.
+
+use clap::ValueEnum;
+use openapi::models::User;
+use serde::Serialize;
+
+use implementation::import::ImportReport;
+use implementation::search::SearchHit;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Output {
+    /// Aligned columns
+    Table,
+    /// Pretty-printed JSON
+    Json,
+}
+
+pub fn json(value: &impl Serialize) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    println!("{text}");
+    Ok(())
+}
+
+/// A line of text, or `{"message": ...}` in JSON.
+pub fn message(output: Output, message: &str) -> Result<(), String> {
+    match output {
+        Output::Table => {
+            println!("{message}");
+            Ok(())
+        }
+        Output::Json => json(&serde_json::json!({ "message": message })),
+    }
+}
+
+pub fn table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: &mut dyn Iterator<Item = &str>| {
+        let padded: Vec<String> = cells.zip(&widths).map(|(cell, width)| format!("{cell:width$}")).collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+    line(&mut headers.iter().copied());
+    for row in rows {
+        line(&mut row.iter().map(String::as_str));
+    }
+}
+
+const USER_COLUMNS: [&str; 7] = ["ID", "NAME", "SURNAME", "AGE", "PERSONAL ID", "CITIZENSHIP", "EMAIL"];
+
+fn user_row(user: &User) -> Vec<String> {
+    vec![
+        user.id.map(|id| id.to_string()).unwrap_or_default(),
+        user.name.clone(),
+        user.surname.clone(),
+        user.age.to_string(),
+        user.personal_id.clone(),
+        user.citizenship.clone(),
+        user.email.clone().unwrap_or_default(),
+    ]
+}
+
+pub fn users(output: Output, users: &[User]) -> Result<(), String> {
+    match output {
+        Output::Json => json(&users),
+        Output::Table => {
+            table(&USER_COLUMNS, &users.iter().map(user_row).collect::<Vec<_>>());
+            Ok(())
+        }
+    }
+}
+
+pub fn search_hits(output: Output, hits: &[SearchHit]) -> Result<(), String> {
+    match output {
+        Output::Json => json(&hits),
+        Output::Table => {
+            let headers: Vec<&str> = ["SCORE"].into_iter().chain(USER_COLUMNS).collect();
+            let rows: Vec<Vec<String>> = hits
+                .iter()
+                .map(|hit| [format!("{:.2}", hit.score)].into_iter().chain(user_row(&hit.user)).collect())
+                .collect();
+            table(&headers, &rows);
+            Ok(())
+        }
+    }
+}
+
+/// The rejected rows with their errors, then a summary line.
+pub fn import_report(output: Output, report: &ImportReport) -> Result<(), String> {
+    if output == Output::Json {
+        return json(report);
+    }
+    let rejected: Vec<Vec<String>> = report
+        .rows
+        .iter()
+        .filter(|row| !row.errors.is_empty())
+        .map(|row| vec![row.row.to_string(), row.errors.join("; ")])
+        .collect();
+    if !rejected.is_empty() {
+        table(&["ROW", "ERRORS"], &rejected);
+    }
+    println!(
+        "{} accepted, {} rejected, {}",
+        report.accepted,
+        report.rejected,
+        if report.committed { "imported" } else { "nothing imported" }
+    );
+    Ok(())
+}
@@ -0,0 +1,891 @@
+//! Server configuration.
+//!
+//! Settings are layered: built-in defaults, then the TOML file given by `--config`,
+//! then environment variables, then command-line flags. Each flag has a matching
+//! `USERS_*` environment variable and the flag wins when both are set.
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::fixtures::Format;
+use crate::import::ImportMode;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Users API server")]
+pub struct Cli {
+    /// TOML configuration file
+    #[arg(long, env = "USERS_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Print the effective configuration with secrets redacted, then exit
+    #[arg(long)]
+    pub print_config: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Address to listen on
+    #[arg(long, env = "USERS_BIND")]
+    pub bind: Option<String>,
+
+    #[arg(long, env = "USERS_STORAGE_BACKEND")]
+    pub storage_backend: Option<StorageBackend>,
+
+    /// Journal file of the journal backend [default: users.journal]
+    #[arg(long, env = "USERS_STORAGE_PATH")]
+    pub storage_path: Option<PathBuf>,
+
+    /// Audit journal of the journal backend [default: audit.journal next to the users journal]
+    #[arg(long, env = "USERS_AUDIT_PATH")]
+    pub audit_path: Option<PathBuf>,
+
+    /// JSON file with the accepted API keys; required unless --auth-open is set
+    #[arg(long, env = "USERS_API_KEYS_FILE")]
+    pub api_keys_file: Option<PathBuf>,
+
+    /// Serve without authentication, every caller acting as an anonymous administrator
+    #[arg(long, env = "USERS_AUTH_OPEN")]
+    pub auth_open: Option<bool>,
+
+    /// File with the key used to sign receipts; required unless --auth-open is set
+    #[arg(long, env = "USERS_SIGNING_KEY_FILE")]
+    pub signing_key_file: Option<PathBuf>,
+
+    /// File with the key used to derive pseudonymous user references; required
+    /// unless --auth-open is set
+    #[arg(long, env = "USERS_PSEUDONYM_KEY_FILE")]
+    pub pseudonym_key_file: Option<PathBuf>,
+
+    #[arg(long, env = "USERS_SIGNING_KEY", hide = true, hide_env_values = true)]
+    pub signing_key: Option<String>,
+
+    #[arg(long, env = "USERS_PSEUDONYM_KEY", hide = true, hide_env_values = true)]
+    pub pseudonym_key: Option<String>,
+
+    /// Largest accepted request body, in bytes
+    #[arg(long, env = "USERS_MAX_BODY_BYTES")]
+    pub max_body_bytes: Option<usize>,
+
+    /// Largest accepted bulk import body, in bytes
+    #[arg(long, env = "USERS_MAX_IMPORT_BYTES")]
+    pub max_import_bytes: Option<usize>,
+
+    /// Most rows accepted by one bulk import
+    #[arg(long, env = "USERS_MAX_IMPORT_ROWS")]
+    pub max_import_rows: Option<usize>,
+
+    /// Seconds a request may take before it is answered with 503
+    #[arg(long, env = "USERS_REQUEST_TIMEOUT_SECS")]
+    pub request_timeout_secs: Option<u64>,
+
+    /// Hours a soft-deleted user is kept before it is purged
+    #[arg(long, env = "USERS_SOFT_DELETE_RETENTION_HOURS")]
+    pub soft_delete_retention_hours: Option<u32>,
+
+    /// Log filter, e.g. `info` or `implementation=debug,tower=warn`
+    #[arg(long, env = "USERS_LOG_LEVEL")]
+    pub log_level: Option<String>,
+
+    #[arg(long, env = "USERS_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+
+    /// Comma-separated origins allowed to call the API from a browser
+    #[arg(long, env = "USERS_CORS_ALLOWED_ORIGINS", value_delimiter = ',')]
+    pub cors_allowed_origins: Option<Vec<String>>,
+
+    /// max-age of the Strict-Transport-Security header sent over HTTPS; 0 leaves it out
+    #[arg(long, env = "USERS_HSTS_MAX_AGE_SECS")]
+    pub hsts_max_age_secs: Option<u64>,
+
+    /// Seconds to keep serving, reported not ready, after a shutdown signal
+    #[arg(long, env = "USERS_SHUTDOWN_GRACE_PERIOD_SECS")]
+    pub shutdown_grace_period_secs: Option<u64>,
+
+    /// Seconds in-flight requests get to finish once the listener is closed
+    #[arg(long, env = "USERS_SHUTDOWN_DRAIN_TIMEOUT_SECS")]
+    pub shutdown_drain_timeout_secs: Option<u64>,
+
+    /// Users to load into an empty store: .json, .ndjson or .csv
+    #[arg(long, env = "USERS_SEED_FILE")]
+    pub seed_file: Option<PathBuf>,
+
+    /// Synthetic users to generate into an empty store
+    #[arg(long, env = "USERS_SEED_SYNTHETIC_USERS")]
+    pub seed_synthetic_users: Option<usize>,
+
+    /// Makes the synthetic users reproducible
+    #[arg(long, env = "USERS_SEED_RANDOM_SEED")]
+    pub seed_random_seed: Option<u64>,
+
+    /// Set to false to turn off rate limiting and authentication lockout
+    #[arg(long, env = "USERS_RATE_LIMIT_ENABLED")]
+    pub rate_limit_enabled: Option<bool>,
+
+    /// Listing requests a client may make per minute
+    #[arg(long, env = "USERS_RATE_LIMIT_LIST_PER_MINUTE")]
+    pub rate_limit_list_per_minute: Option<u32>,
+
+    /// Single-user reads a client may make per minute
+    #[arg(long, env = "USERS_RATE_LIMIT_READ_PER_MINUTE")]
+    pub rate_limit_read_per_minute: Option<u32>,
+
+    /// Writes a client may make per minute
+    #[arg(long, env = "USERS_RATE_LIMIT_WRITE_PER_MINUTE")]
+    pub rate_limit_write_per_minute: Option<u32>,
+
+    /// Failed authentications after which a source IP is locked out
+    #[arg(long, env = "USERS_RATE_LIMIT_LOCKOUT_AFTER_FAILURES")]
+    pub rate_limit_lockout_after_failures: Option<u32>,
+
+    /// Seconds over which failed authentications are counted
+    #[arg(long, env = "USERS_RATE_LIMIT_LOCKOUT_WINDOW_SECS")]
+    pub rate_limit_lockout_window_secs: Option<u64>,
+
+    /// Seconds a locked-out source IP is refused
+    #[arg(long, env = "USERS_RATE_LIMIT_LOCKOUT_SECS")]
+    pub rate_limit_lockout_secs: Option<u64>,
+
+    /// File keeping webhook subscriptions and the delivery queue; in memory without it
+    #[arg(long, env = "USERS_WEBHOOKS_PATH")]
+    pub webhooks_path: Option<PathBuf>,
+
+    /// Attempts at a webhook delivery before it is given up
+    #[arg(long, env = "USERS_WEBHOOKS_MAX_ATTEMPTS")]
+    pub webhooks_max_attempts: Option<u32>,
+
+    /// Seconds before the first retry of a webhook delivery; doubles on each retry
+    #[arg(long, env = "USERS_WEBHOOKS_INITIAL_BACKOFF_SECS")]
+    pub webhooks_initial_backoff_secs: Option<u64>,
+
+    /// Longest wait between two attempts at a webhook delivery, in seconds
+    #[arg(long, env = "USERS_WEBHOOKS_MAX_BACKOFF_SECS")]
+    pub webhooks_max_backoff_secs: Option<u64>,
+
+    /// Seconds a webhook receiver gets to answer
+    #[arg(long, env = "USERS_WEBHOOKS_TIMEOUT_SECS")]
+    pub webhooks_timeout_secs: Option<u64>,
+
+    /// Finished deliveries kept in the log of each webhook subscription
+    #[arg(long, env = "USERS_WEBHOOKS_DELIVERY_LOG_SIZE")]
+    pub webhooks_delivery_log_size: Option<usize>,
+
+    /// File each user change is appended to as a JSON line
+    #[arg(long, env = "USERS_OUTBOX_FILE")]
+    pub outbox_file: Option<PathBuf>,
+
+    /// URL batches of user changes are POSTed to
+    #[arg(long, env = "USERS_OUTBOX_WEBHOOK_URL")]
+    pub outbox_webhook_url: Option<String>,
+
+    #[arg(long, env = "USERS_OUTBOX_WEBHOOK_SECRET", hide = true, hide_env_values = true)]
+    pub outbox_webhook_secret: Option<String>,
+
+    /// File keeping the last change accepted by --outbox-webhook-url
+    #[arg(long, env = "USERS_OUTBOX_POSITION_FILE")]
+    pub outbox_position_file: Option<PathBuf>,
+
+    /// Send personal data unmasked to the outbox URL; the outbox file is always masked
+    #[arg(long, env = "USERS_OUTBOX_INCLUDE_PERSONAL_DATA")]
+    pub outbox_include_personal_data: Option<bool>,
+
+    /// Log every user change, without personal data
+    #[arg(long, env = "USERS_OUTBOX_LOG_EVENTS")]
+    pub outbox_log_events: Option<bool>,
+
+    /// Check requests, or requests and responses, against the OpenAPI document
+    #[arg(long, env = "USERS_VALIDATION_MODE")]
+    pub validation_mode: Option<ValidationMode>,
+
+    /// When API v1 was deprecated in favour of v2, e.g. 2026-10-01T00:00:00Z
+    #[arg(long, env = "USERS_V1_DEPRECATED_AT")]
+    pub v1_deprecated_at: Option<DateTime<Utc>>,
+
+    /// When API v1 stops being served
+    #[arg(long, env = "USERS_V1_SUNSET_AT")]
+    pub v1_sunset_at: Option<DateTime<Utc>>,
+
+    /// PEM certificate chain; enables TLS together with --tls-key-file
+    #[arg(long, env = "USERS_TLS_CERT_FILE")]
+    pub tls_cert_file: Option<PathBuf>,
+
+    /// PEM private key
+    #[arg(long, env = "USERS_TLS_KEY_FILE")]
+    pub tls_key_file: Option<PathBuf>,
+
+    /// PEM CA bundle; when set, clients must present a certificate it signed
+    #[arg(long, env = "USERS_TLS_CLIENT_CA_FILE")]
+    pub tls_client_ca_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Print synthetic users with valid PESELs, for fixtures and load tests
+    GenerateUsers {
+        #[arg(long, default_value_t = 100)]
+        count: usize,
+        #[arg(long, value_enum, default_value_t = Format::Ndjson)]
+        format: Format,
+        /// Produce the same users on every run
+        #[arg(long)]
+        random_seed: Option<u64>,
+    },
+    /// Import users from a .csv, .ndjson or .json file into the journal. Stop the
+    /// server first: it does not see users written by another process.
+    ImportUsers {
+        file: PathBuf,
+        #[arg(long, value_enum, default_value_t = ImportMode::AllOrNothing)]
+        mode: ImportMode,
+        /// Check every row and print the report without importing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub storage: StorageConfig,
+    pub seed: SeedConfig,
+    pub auth: AuthConfig,
+    pub limits: LimitsConfig,
+    pub security: SecurityConfig,
+    pub rate_limit: RateLimitConfig,
+    pub shutdown: ShutdownConfig,
+    pub logging: LoggingConfig,
+    pub tls: TlsConfig,
+    pub webhooks: WebhooksConfig,
+    pub outbox: OutboxConfig,
+    pub validation: ValidationConfig,
+    pub versioning: VersioningConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: "0.0.0.0:8080".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// Users live only as long as the process
+    Memory,
+    /// Users are kept in an append-only file replayed at startup
+    #[default]
+    Journal,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// Journal file; `DEFAULT_JOURNAL` in the working directory when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Audit journal; `DEFAULT_AUDIT_JOURNAL` next to the journal when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audit_path: Option<PathBuf>,
+}
+
+pub const DEFAULT_JOURNAL: &str = "users.journal";
+pub const DEFAULT_AUDIT_JOURNAL: &str = "audit.journal";
+
+impl StorageConfig {
+    /// The journal file, None with the memory backend.
+    pub fn journal_path(&self) -> Option<&Path> {
+        match self.backend {
+            StorageBackend::Journal => Some(self.path.as_deref().unwrap_or(Path::new(DEFAULT_JOURNAL))),
+            StorageBackend::Memory => None,
+        }
+    }
+
+    /// The audit journal, None with the memory backend.
+    pub fn audit_path(&self) -> Option<PathBuf> {
+        let journal = self.journal_path()?;
+        Some(match &self.audit_path {
+            Some(path) => path.clone(),
+            None => journal.with_file_name(DEFAULT_AUDIT_JOURNAL),
+        })
+    }
+}
+
+/// Users put into the store when it is empty at startup. Without a file or a
+/// synthetic count the server starts empty.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SeedConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    pub synthetic_users: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub random_seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_keys_file: Option<PathBuf>,
+    /// Without API keys nobody is authenticated, so this must be asked for.
+    pub open: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_key_file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pseudonym_key_file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_key: Option<Secret>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pseudonym_key: Option<Secret>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_body_bytes: usize,
+    /// Applies to `POST /api/users/import` instead of `max_body_bytes`.
+    pub max_import_bytes: usize,
+    /// Rows of one `POST /api/users/import`; all of them are held until it is committed.
+    pub max_import_rows: usize,
+    pub request_timeout_secs: u64,
+    pub soft_delete_retention_hours: u32,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_body_bytes: 64 * 1024,
+            max_import_bytes: 64 * 1024 * 1024,
+            max_import_rows: 100_000,
+            request_timeout_secs: 30,
+            soft_delete_retention_hours: 30 * 24,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecurityConfig {
+    /// Exact origins, e.g. `https://admin.example.com`. Empty refuses every cross-origin call.
+    pub cors_allowed_origins: Vec<String>,
+    pub hsts_max_age_secs: u64,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        SecurityConfig {
+            cors_allowed_origins: Vec::new(),
+            hsts_max_age_secs: 365 * 24 * 60 * 60,
+        }
+    }
+}
+
+/// Token buckets per source IP and, when the caller authenticated, per API key
+/// subject as well. A client may burst up to a full minute's allowance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub list_per_minute: u32,
+    pub read_per_minute: u32,
+    pub write_per_minute: u32,
+    pub lockout_after_failures: u32,
+    pub lockout_window_secs: u64,
+    pub lockout_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            enabled: true,
+            list_per_minute: 30,
+            read_per_minute: 600,
+            write_per_minute: 120,
+            lockout_after_failures: 10,
+            lockout_window_secs: 5 * 60,
+            lockout_secs: 15 * 60,
+        }
+    }
+}
+
+/// On SIGTERM or Ctrl+C the server reports not ready for `grace_period_secs` while
+/// still serving, then closes the listener and gives in-flight requests up to
+/// `drain_timeout_secs` before the store is flushed and the process exits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    pub grace_period_secs: u64,
+    pub drain_timeout_secs: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        ShutdownConfig {
+            grace_period_secs: 5,
+            drain_timeout_secs: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: "info".into(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert_file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<PathBuf>,
+    /// Enables mutual TLS; the client certificate subject identifies the caller.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ca_file: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    pub fn enabled(&self) -> bool {
+        self.cert_file.is_some() && self.key_file.is_some()
+    }
+}
+
+/// Deliveries of user changes to subscribed URLs. A failed delivery is retried
+/// after `initial_backoff_secs`, then after twice as long each time up to
+/// `max_backoff_secs`, until `max_attempts` attempts have been made.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhooksConfig {
+    /// Subscriptions and queued deliveries are lost on restart without it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    pub max_attempts: u32,
+    pub initial_backoff_secs: u64,
+    pub max_backoff_secs: u64,
+    pub timeout_secs: u64,
+    pub delivery_log_size: usize,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        WebhooksConfig {
+            path: None,
+            max_attempts: 8,
+            initial_backoff_secs: 10,
+            max_backoff_secs: 60 * 60,
+            timeout_secs: 10,
+            delivery_log_size: 100,
+        }
+    }
+}
+
+/// Sinks the outbox relays user changes to, each from where it left off. The
+/// webhook sink POSTs `{"events": [...]}` signed with `webhook_secret` like
+/// subscription webhooks, and remembers its position in `position_file`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutboxConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_secret: Option<Secret>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position_file: Option<PathBuf>,
+    /// Applies to the webhook sink only. The file sink, which erasure does not
+    /// rewrite, and logged events never have any.
+    pub include_personal_data: bool,
+    pub log_events: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidationMode {
+    /// Only the checks built into the handlers
+    #[default]
+    Off,
+    /// Requests not matching the OpenAPI document are refused with 400
+    Requests,
+    /// Also answers 500 in place of responses not matching the document, for tests
+    Strict,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ValidationConfig {
+    pub mode: ValidationMode,
+}
+
+/// Announces the retirement of the v1 user operations that v2 replaces, with
+/// `Deprecation` and `Sunset` headers on their responses.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VersioningConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub v1_deprecated_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub v1_sunset_at: Option<DateTime<Utc>>,
+}
+
+/// A configuration value that must never be printed or logged.
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("<redacted>")
+    }
+}
+
+impl Config {
+    /// Builds the effective configuration. All problems found are returned at once.
+    pub fn load(cli: &Cli) -> Result<Config, Vec<String>> {
+        let mut config = match &cli.config {
+            Some(path) => Config::from_file(path).map_err(|e| vec![e])?,
+            None => Config::default(),
+        };
+        config.apply_cli(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads the configuration as the server would without flags: the file at
+    /// `path`, or `USERS_CONFIG` without one, overridden by the `USERS_*`
+    /// environment.
+    pub fn from_env(path: Option<&Path>) -> Result<Config, Vec<String>> {
+        let mut cli = Cli::try_parse_from(["implementation"]).map_err(|e| vec![e.to_string()])?;
+        if let Some(path) = path {
+            cli.config = Some(path.to_path_buf());
+        }
+        Config::load(&cli)
+    }
+
+    fn from_file(path: &Path) -> Result<Config, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read config file {}: {e}", path.display()))?;
+        toml::from_str(&content).map_err(|e| format!("invalid config file {}: {e}", path.display()))
+    }
+
+    /// Applies flags and their environment variables on top of the file settings.
+    fn apply_cli(&mut self, cli: &Cli) {
+        fn set<T: Clone>(target: &mut T, value: &Option<T>) {
+            if let Some(value) = value {
+                *target = value.clone();
+            }
+        }
+        fn set_opt<T: Clone>(target: &mut Option<T>, value: &Option<T>) {
+            if value.is_some() {
+                target.clone_from(value);
+            }
+        }
+
+        set(&mut self.server.bind, &cli.bind);
+        set(&mut self.storage.backend, &cli.storage_backend);
+        set_opt(&mut self.storage.path, &cli.storage_path);
+        set_opt(&mut self.storage.audit_path, &cli.audit_path);
+        set_opt(&mut self.seed.file, &cli.seed_file);
+        set(&mut self.seed.synthetic_users, &cli.seed_synthetic_users);
+        set_opt(&mut self.seed.random_seed, &cli.seed_random_seed);
+        set_opt(&mut self.auth.api_keys_file, &cli.api_keys_file);
+        set(&mut self.auth.open, &cli.auth_open);
+        set_opt(&mut self.auth.signing_key_file, &cli.signing_key_file);
+        set_opt(&mut self.auth.pseudonym_key_file, &cli.pseudonym_key_file);
+        set_opt(&mut self.auth.signing_key, &cli.signing_key.clone().map(Secret));
+        set_opt(&mut self.auth.pseudonym_key, &cli.pseudonym_key.clone().map(Secret));
+        set(&mut self.limits.max_body_bytes, &cli.max_body_bytes);
+        set(&mut self.limits.max_import_bytes, &cli.max_import_bytes);
+        set(&mut self.limits.max_import_rows, &cli.max_import_rows);
+        set(&mut self.limits.request_timeout_secs, &cli.request_timeout_secs);
+        set(&mut self.limits.soft_delete_retention_hours, &cli.soft_delete_retention_hours);
+        set(&mut self.security.cors_allowed_origins, &cli.cors_allowed_origins);
+        set(&mut self.security.hsts_max_age_secs, &cli.hsts_max_age_secs);
+        set(&mut self.rate_limit.enabled, &cli.rate_limit_enabled);
+        set(&mut self.rate_limit.list_per_minute, &cli.rate_limit_list_per_minute);
+        set(&mut self.rate_limit.read_per_minute, &cli.rate_limit_read_per_minute);
+        set(&mut self.rate_limit.write_per_minute, &cli.rate_limit_write_per_minute);
+        set(&mut self.rate_limit.lockout_after_failures, &cli.rate_limit_lockout_after_failures);
+        set(&mut self.rate_limit.lockout_window_secs, &cli.rate_limit_lockout_window_secs);
+        set(&mut self.rate_limit.lockout_secs, &cli.rate_limit_lockout_secs);
+        set(&mut self.shutdown.grace_period_secs, &cli.shutdown_grace_period_secs);
+        set(&mut self.shutdown.drain_timeout_secs, &cli.shutdown_drain_timeout_secs);
+        set(&mut self.logging.level, &cli.log_level);
+        set(&mut self.logging.format, &cli.log_format);
+        set_opt(&mut self.tls.cert_file, &cli.tls_cert_file);
+        set_opt(&mut self.tls.key_file, &cli.tls_key_file);
+        set_opt(&mut self.tls.client_ca_file, &cli.tls_client_ca_file);
+        set_opt(&mut self.webhooks.path, &cli.webhooks_path);
+        set(&mut self.webhooks.max_attempts, &cli.webhooks_max_attempts);
+        set(&mut self.webhooks.initial_backoff_secs, &cli.webhooks_initial_backoff_secs);
+        set(&mut self.webhooks.max_backoff_secs, &cli.webhooks_max_backoff_secs);
+        set(&mut self.webhooks.timeout_secs, &cli.webhooks_timeout_secs);
+        set(&mut self.webhooks.delivery_log_size, &cli.webhooks_delivery_log_size);
+        set_opt(&mut self.outbox.file, &cli.outbox_file);
+        set_opt(&mut self.outbox.webhook_url, &cli.outbox_webhook_url);
+        set_opt(&mut self.outbox.webhook_secret, &cli.outbox_webhook_secret.clone().map(Secret));
+        set_opt(&mut self.outbox.position_file, &cli.outbox_position_file);
+        set(&mut self.outbox.include_personal_data, &cli.outbox_include_personal_data);
+        set(&mut self.outbox.log_events, &cli.outbox_log_events);
+        set(&mut self.validation.mode, &cli.validation_mode);
+        set_opt(&mut self.versioning.v1_deprecated_at, &cli.v1_deprecated_at);
+        set_opt(&mut self.versioning.v1_sunset_at, &cli.v1_sunset_at);
+    }
+
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.server.bind.parse::<SocketAddr>().is_err() {
+            errors.push(format!(
+                "server.bind: `{}` is not an IP:port address",
+                self.server.bind
+            ));
+        }
+
+        match (self.storage.backend, &self.storage.path) {
+            (StorageBackend::Journal, None) => {}
+            (StorageBackend::Journal, Some(path)) => {
+                let dir = path.parent().filter(|d| !d.as_os_str().is_empty());
+                if dir.is_some_and(|d| !d.is_dir()) {
+                    errors.push(format!(
+                        "storage.path: directory of {} does not exist",
+                        path.display()
+                    ));
+                }
+            }
+            (StorageBackend::Memory, Some(_)) => {
+                errors.push("storage.path: only used by the journal backend".into())
+            }
+            (StorageBackend::Memory, None) => {}
+        }
+
+        check_file(&mut errors, "seed.file", &self.seed.file);
+        if let Some(file) = &self.seed.file {
+            if Format::from_path(file).is_none() {
+                errors.push(format!(
+                    "seed.file: {} must end in .json, .ndjson or .csv",
+                    file.display()
+                ));
+            }
+            if self.seed.synthetic_users > 0 {
+                errors.push("seed: set either file or synthetic_users, not both".into());
+            }
+        }
+
+        match (&self.auth.api_keys_file, self.auth.open) {
+            (None, false) => errors.push(
+                "auth.api_keys_file: required, or set auth.open to serve without authentication".into(),
+            ),
+            (Some(_), true) => errors.push("auth: set either api_keys_file or open, not both".into()),
+            _ => {}
+        }
+        check_file(&mut errors, "auth.api_keys_file", &self.auth.api_keys_file);
+        check_file(&mut errors, "auth.signing_key_file", &self.auth.signing_key_file);
+        check_file(&mut errors, "auth.pseudonym_key_file", &self.auth.pseudonym_key_file);
+        for (name, inline, file) in [
+            ("signing_key", &self.auth.signing_key, &self.auth.signing_key_file),
+            ("pseudonym_key", &self.auth.pseudonym_key, &self.auth.pseudonym_key_file),
+        ] {
+            if inline.is_some() && file.is_some() {
+                errors.push(format!("auth.{name}: set either {name} or {name}_file, not both"));
+            }
+            // Receipts and pseudonyms made with an ephemeral key cannot be checked
+            // after a restart.
+            if inline.is_none() && file.is_none() && !self.auth.open {
+                errors.push(format!("auth.{name}_file: required unless auth.open is set"));
+            }
+            if inline.as_ref().is_some_and(|k| k.expose().len() < 32) {
+                errors.push(format!("auth.{name}: must be at least 32 bytes long"));
+            }
+        }
+
+        if self.limits.max_body_bytes == 0 {
+            errors.push("limits.max_body_bytes: must be greater than 0".into());
+        }
+        if self.limits.max_import_bytes == 0 {
+            errors.push("limits.max_import_bytes: must be greater than 0".into());
+        }
+        if self.limits.max_import_rows == 0 {
+            errors.push("limits.max_import_rows: must be greater than 0".into());
+        }
+        if self.limits.request_timeout_secs == 0 {
+            errors.push("limits.request_timeout_secs: must be greater than 0".into());
+        }
+
+        for origin in &self.security.cors_allowed_origins {
+            if !is_origin(origin) {
+                errors.push(format!(
+                    "security.cors_allowed_origins: `{origin}` is not a scheme://host[:port] origin"
+                ));
+            }
+        }
+
+        let rate_limit = &self.rate_limit;
+        for (name, value) in [
+            ("list_per_minute", rate_limit.list_per_minute),
+            ("read_per_minute", rate_limit.read_per_minute),
+            ("write_per_minute", rate_limit.write_per_minute),
+            ("lockout_after_failures", rate_limit.lockout_after_failures),
+        ] {
+            if value == 0 {
+                errors.push(format!("rate_limit.{name}: must be greater than 0"));
+            }
+        }
+
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.logging.level) {
+            errors.push(format!("logging.level: {e}"));
+        }
+
+        if self.tls.cert_file.is_some() != self.tls.key_file.is_some() {
+            errors.push("tls: cert_file and key_file must be set together".into());
+        }
+        check_file(&mut errors, "tls.cert_file", &self.tls.cert_file);
+        check_file(&mut errors, "tls.key_file", &self.tls.key_file);
+        check_file(&mut errors, "tls.client_ca_file", &self.tls.client_ca_file);
+        if self.tls.client_ca_file.is_some() && !self.tls.enabled() {
+            errors.push("tls: client_ca_file requires cert_file and key_file".into());
+        }
+
+        let webhooks = &self.webhooks;
+        if let Some(path) = &webhooks.path {
+            let dir = path.parent().filter(|d| !d.as_os_str().is_empty());
+            if dir.is_some_and(|d| !d.is_dir()) {
+                errors.push(format!(
+                    "webhooks.path: directory of {} does not exist",
+                    path.display()
+                ));
+            }
+            if self.storage.journal_path() == Some(path.as_path()) {
+                errors.push("webhooks.path: must not be the storage journal".into());
+            }
+        }
+        for (name, value) in [
+            ("max_attempts", u64::from(webhooks.max_attempts)),
+            ("initial_backoff_secs", webhooks.initial_backoff_secs),
+            ("timeout_secs", webhooks.timeout_secs),
+            ("delivery_log_size", webhooks.delivery_log_size as u64),
+        ] {
+            if value == 0 {
+                errors.push(format!("webhooks.{name}: must be greater than 0"));
+            }
+        }
+        if webhooks.max_backoff_secs < webhooks.initial_backoff_secs {
+            errors.push("webhooks.max_backoff_secs: must not be less than initial_backoff_secs".into());
+        }
+
+        let outbox = &self.outbox;
+        for (name, path) in [("file", &outbox.file), ("position_file", &outbox.position_file)] {
+            let Some(path) = path else { continue };
+            let dir = path.parent().filter(|d| !d.as_os_str().is_empty());
+            if dir.is_some_and(|d| !d.is_dir()) {
+                errors.push(format!("outbox.{name}: directory of {} does not exist", path.display()));
+            }
+            if self.storage.journal_path() == Some(path.as_path()) || webhooks.path.as_ref() == Some(path) {
+                errors.push(format!("outbox.{name}: must not be the storage or webhooks journal"));
+            }
+        }
+        match &outbox.webhook_url {
+            Some(url) => {
+                if !url.parse::<http::Uri>().is_ok_and(|uri| {
+                    matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some_and(|h| !h.is_empty())
+                }) {
+                    errors.push(format!("outbox.webhook_url: `{url}` is not an http or https URL"));
+                }
+                match &outbox.webhook_secret {
+                    None => errors.push("outbox.webhook_secret: required by webhook_url".into()),
+                    Some(secret) if secret.expose().len() < 16 => {
+                        errors.push("outbox.webhook_secret: must be at least 16 bytes long".into())
+                    }
+                    Some(_) => {}
+                }
+                if outbox.position_file.is_none() {
+                    errors.push("outbox.position_file: required by webhook_url".into());
+                }
+            }
+            None => {
+                if outbox.webhook_secret.is_some() || outbox.position_file.is_some() {
+                    errors.push("outbox: webhook_secret and position_file are only used with webhook_url".into());
+                }
+            }
+        }
+
+        if let (Some(deprecated), Some(sunset)) = (self.versioning.v1_deprecated_at, self.versioning.v1_sunset_at) {
+            if sunset < deprecated {
+                errors.push("versioning.v1_sunset_at: must not be before v1_deprecated_at".into());
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The configuration as TOML, with secrets replaced by `<redacted>`.
+    pub fn to_redacted_toml(&self) -> String {
+        toml::to_string(self).expect("configuration is serializable")
+    }
+}
+
+fn is_origin(origin: &str) -> bool {
+    let Some((scheme, host)) = origin.split_once("://") else {
+        return false;
+    };
+    matches!(scheme, "http" | "https")
+        && !host.is_empty()
+        && !host.contains(['/', '*', '?', '#'])
+        && http::HeaderValue::from_str(origin).is_ok()
+}
+
+fn check_file(errors: &mut Vec<String>, name: &str, path: &Option<PathBuf>) {
+    if let Some(path) = path {
+        if !path.is_file() {
+            errors.push(format!("{name}: {} is not a readable file", path.display()));
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! The OpenAPI document of the running server and a self-contained page for
+//! trying the API from a browser. Neither needs an API key.
+
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use base64::Engine;
+use http::header::{CONTENT_SECURITY_POLICY, CONTENT_TYPE};
+use http::HeaderValue;
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+
+const EXPLORER_HTML: &str = include_str!("../explorer/explorer.html");
+const EXPLORER_CSS: &str = include_str!("../explorer/explorer.css");
+const EXPLORER_JS: &str = include_str!("../explorer/explorer.js");
+
+lazy_static! {
+    static ref EXPLORER: (String, String) = explorer();
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/api/openapi.json", get(get_openapi_document))
+        .route("/api/openapi.yaml", get(get_openapi_document_yaml))
+        .route("/api/docs", get(get_api_explorer))
+}
+
+/// GetOpenApiDocument - GET /api/openapi.json
+async fn get_openapi_document() -> Response {
+    document(openapi::spec::JSON, "application/json")
+}
+
+/// GetOpenApiDocumentYaml - GET /api/openapi.yaml
+async fn get_openapi_document_yaml() -> Response {
+    document(openapi::spec::yaml(), "application/yaml")
+}
+
+/// GetApiExplorer - GET /api/docs
+///
+/// The style and script are inline, allowed by their hashes, so the page loads
+/// nothing but the document and what the user sends.
+async fn get_api_explorer() -> Response {
+    let (page, policy) = &*EXPLORER;
+    let mut response = document(page, "text/html; charset=utf-8");
+    response.headers_mut().insert(
+        CONTENT_SECURITY_POLICY,
+        HeaderValue::from_str(policy).expect("valid header value"),
+    );
+    response
+}
+
+fn document(body: &'static str, content_type: &'static str) -> Response {
+    let mut response = Response::new(body.into());
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response
+}
+
+/// The explorer page and the Content-Security-Policy admitting its inline style
+/// and script.
+fn explorer() -> (String, String) {
+    let page = EXPLORER_HTML
+        .replace("{{style}}", EXPLORER_CSS)
+        .replace("{{script}}", EXPLORER_JS);
+    let policy = format!(
+        "default-src 'none'; style-src '{}'; script-src '{}'; connect-src 'self'; \
+         base-uri 'none'; form-action 'none'; frame-ancestors 'none'",
+        source_hash(EXPLORER_CSS),
+        source_hash(EXPLORER_JS),
+    );
+    (page, policy)
+}
+
+fn source_hash(source: &str) -> String {
+    format!("sha256-{}", base64::engine::general_purpose::STANDARD.encode(Sha256::digest(source)))
+}
@@ -0,0 +1,162 @@
+//! Near-duplicate detection between users, and merging of duplicates.
+
+use std::collections::{HashMap, HashSet};
+
+use openapi::models::{RequestHeader, User};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::search::{fold, similarity};
+
+pub const DEFAULT_MIN_SCORE: f64 = 0.8;
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 1000;
+
+/// Weight of each field in a pair's score; they add up to 1.
+const NAME_WEIGHT: f64 = 0.2;
+const SURNAME_WEIGHT: f64 = 0.2;
+const PERSONAL_ID_WEIGHT: f64 = 0.3;
+const EMAIL_WEIGHT: f64 = 0.2;
+const AGE_WEIGHT: f64 = 0.1;
+
+/// Two users that may be the same person.
+#[derive(Debug, Serialize)]
+pub struct Candidate {
+    pub score: f64,
+    /// Why the pair scored, e.g. `same_email` or `similar_personal_id`.
+    pub reasons: Vec<&'static str>,
+    pub first: User,
+    pub second: User,
+}
+
+/// Pairs of users scoring at least `min_score`, best first. Only users sharing
+/// their folded name and surname, their email or their personal id are compared.
+pub fn candidates<'a>(users: impl Iterator<Item = &'a User>, min_score: f64) -> Vec<Candidate> {
+    let users: Vec<&User> = users.collect();
+    let mut blocks: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, user) in users.iter().enumerate() {
+        let names = format!("name:{}|{}", fold(user.name.trim()), fold(user.surname.trim()));
+        blocks.entry(names).or_default().push(index);
+        if let Some(email) = &user.email {
+            blocks.entry(format!("email:{}", email.to_lowercase())).or_default().push(index);
+        }
+        blocks.entry(format!("pesel:{}", user.personal_id)).or_default().push(index);
+    }
+
+    let mut compared = HashSet::new();
+    let mut found = Vec::new();
+    for block in blocks.values() {
+        for (i, &a) in block.iter().enumerate() {
+            for &b in &block[i + 1..] {
+                if !compared.insert((a.min(b), a.max(b))) {
+                    continue;
+                }
+                let (score, reasons) = score(users[a], users[b]);
+                if score >= min_score {
+                    found.push(Candidate {
+                        score,
+                        reasons,
+                        first: users[a.min(b)].clone(),
+                        second: users[a.max(b)].clone(),
+                    });
+                }
+            }
+        }
+    }
+    found.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.first.surname.cmp(&b.first.surname))
+    });
+    found
+}
+
+fn score(a: &User, b: &User) -> (f64, Vec<&'static str>) {
+    let mut reasons = Vec::new();
+
+    let name = similarity(&a.name, &b.name);
+    let surname = similarity(&a.surname, &b.surname);
+    if name == 1.0 {
+        reasons.push("same_name");
+    }
+    if surname == 1.0 {
+        reasons.push("same_surname");
+    }
+
+    let personal_id = if a.personal_id == b.personal_id {
+        reasons.push("same_personal_id");
+        1.0
+    } else if is_typo(&a.personal_id, &b.personal_id) {
+        reasons.push("similar_personal_id");
+        0.8
+    } else {
+        0.0
+    };
+
+    let email = match (&a.email, &b.email) {
+        (Some(x), Some(y)) if x.eq_ignore_ascii_case(y) => {
+            reasons.push("same_email");
+            1.0
+        }
+        (Some(_), Some(_)) => 0.0,
+        _ => {
+            reasons.push("missing_email");
+            0.5
+        }
+    };
+
+    let age = match a.age.abs_diff(b.age) {
+        0 => {
+            reasons.push("same_age");
+            1.0
+        }
+        1 => 0.5,
+        _ => 0.0,
+    };
+
+    let score = NAME_WEIGHT * name
+        + SURNAME_WEIGHT * surname
+        + PERSONAL_ID_WEIGHT * personal_id
+        + EMAIL_WEIGHT * email
+        + AGE_WEIGHT * age;
+    (score, reasons)
+}
+
+/// One digit wrong, or two neighbouring digits swapped.
+fn is_typo(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let differing: Vec<usize> = a
+        .bytes()
+        .zip(b.bytes())
+        .enumerate()
+        .filter(|(_, (x, y))| x != y)
+        .map(|(i, _)| i)
+        .collect();
+    match differing[..] {
+        [_] => true,
+        [i, j] => j == i + 1 && a.as_bytes()[i] == b.as_bytes()[j] && a.as_bytes()[j] == b.as_bytes()[i],
+        _ => false,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeRequest {
+    pub request_header: RequestHeader,
+    /// The user merged away; its id resolves to the surviving user afterwards.
+    pub retired_id: Uuid,
+    /// The surviving user's data. Without it the survivor keeps its own data,
+    /// taking the email from the retired user if it has none.
+    pub user: Option<User>,
+}
+
+/// The surviving user's data when the caller did not give it.
+pub fn combine(survivor: &User, retired: &User) -> User {
+    let mut user = survivor.clone();
+    if user.email.is_none() {
+        user.email.clone_from(&retired.email);
+    }
+    user
+}
@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::keys::KeyMaterial;
+
+/// Fields of `User`, and of its v2 `Profile`, that are destroyed by an erasure.
+pub const ERASED_FIELDS: &[&str] = &[
+    "name",
+    "surname",
+    "age",
+    "personalId",
+    "citizenship",
+    "email",
+    "givenNames",
+    "birthDate",
+    "addresses",
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErasureReceiptPayload {
+    pub receipt_id: Uuid,
+    pub user_id: Uuid,
+    pub subject_ref: String,
+    pub erased_at: DateTime<Utc>,
+    pub actor: String,
+    pub erased_fields: Vec<String>,
+    pub audit_records_retained: usize,
+    pub key_id: String,
+    pub algorithm: String,
+}
+
+/// Proof that a user was erased. `signature` is the HMAC-SHA256 of the JSON
+/// serialization of the payload.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErasureReceipt {
+    #[serde(flatten)]
+    pub payload: ErasureReceiptPayload,
+    pub signature: String,
+}
+
+impl ErasureReceipt {
+    pub fn sign(payload: ErasureReceiptPayload, keys: &KeyMaterial) -> Self {
+        let bytes = serde_json::to_vec(&payload).expect("receipt payload is serializable");
+        ErasureReceipt {
+            signature: keys.sign(&bytes),
+            payload,
+        }
+    }
+}
+
+pub enum ErasureOutcome {
+    Erased(ErasureReceipt),
+    AlreadyErased,
+    NotFound,
+}
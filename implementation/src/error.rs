@@ -0,0 +1,135 @@
+//! Failures an operation cannot answer for with one of its documented responses.
+//!
+//! Clients get a 500 or 503 with an `Error` body whose `responseHeader.requestId`
+//! is the correlation id; the cause chain is only written to the server log.
+//! A 503 tells the client when to try again in `Retry-After`.
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use axum::response::{IntoResponse, Response};
+use http::header::RETRY_AFTER;
+use http::{HeaderValue, StatusCode};
+use openapi::models::Error;
+
+use crate::routes::json_response;
+use crate::server::build_response_header;
+use crate::store::StoreError;
+
+type Cause = Box<dyn StdError + Send + Sync>;
+
+/// Seconds a client is asked to wait before retrying a 503.
+const RETRY_AFTER_SECS: &str = "5";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Storage could not be read or written; the same request may succeed later.
+    Unavailable,
+    /// The request was not answered within the request timeout.
+    Timeout,
+    /// The server broke one of its own invariants.
+    Internal,
+}
+
+pub struct ApiError {
+    kind: ErrorKind,
+    context: &'static str,
+    cause: Cause,
+}
+
+impl ApiError {
+    pub fn new(kind: ErrorKind, context: &'static str, cause: impl Into<Cause>) -> Self {
+        ApiError {
+            kind,
+            context,
+            cause: cause.into(),
+        }
+    }
+
+    pub fn unavailable(context: &'static str, cause: impl Into<Cause>) -> Self {
+        ApiError::new(ErrorKind::Unavailable, context, cause)
+    }
+
+    pub fn internal(context: &'static str, cause: impl Into<Cause>) -> Self {
+        ApiError::new(ErrorKind::Internal, context, cause)
+    }
+
+    pub fn timeout(context: &'static str, cause: impl Into<Cause>) -> Self {
+        ApiError::new(ErrorKind::Timeout, context, cause)
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self.kind {
+            ErrorKind::Unavailable | ErrorKind::Timeout => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self.kind {
+            ErrorKind::Unavailable => "SERVICE_UNAVAILABLE",
+            ErrorKind::Timeout => "TIMEOUT",
+            ErrorKind::Internal => "INTERNAL_ERROR",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.context)
+    }
+}
+
+/// The context followed by every cause, outermost first.
+impl fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.context)?;
+        let mut cause: Option<&(dyn StdError + 'static)> = Some(&*self.cause);
+        while let Some(e) = cause {
+            write!(f, ": {e}")?;
+            cause = e.source();
+        }
+        Ok(())
+    }
+}
+
+impl StdError for ApiError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&*self.cause)
+    }
+}
+
+/// A journal that cannot be written is unavailable storage; the other store
+/// errors are answered by callers before they write, so reaching here is a bug.
+impl From<StoreError> for ApiError {
+    fn from(e: StoreError) -> Self {
+        let kind = match e {
+            StoreError::Io(_) => ErrorKind::Unavailable,
+            StoreError::Erased | StoreError::Exists => ErrorKind::Internal,
+        };
+        ApiError::new(kind, "user store failure", e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let response_header = build_response_header();
+        tracing::error!(
+            correlation_id = %response_header.request_id,
+            status = self.status().as_u16(),
+            error = ?self,
+            "request failed"
+        );
+        let mut response = json_response(self.status(), &Error::new(response_header, self.code().into()));
+        if self.status() == StatusCode::SERVICE_UNAVAILABLE {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from_static(RETRY_AFTER_SECS));
+        }
+        response
+    }
+}
@@ -0,0 +1,61 @@
+//! Streaming export of the users collection.
+
+use std::io;
+use std::sync::Arc;
+
+use axum::body::Body;
+use bytes::Bytes;
+use futures_util::stream;
+use uuid::Uuid;
+
+use crate::feed::{mask, mask_profile};
+use crate::fixtures::{Encoder, Format};
+use crate::server::ServerImpl;
+
+/// Users read from the store, and encoded, per chunk.
+const CHUNK: usize = 500;
+
+/// A response body with every user in id order. The store is read one chunk at
+/// a time, so writes made during a long export may or may not be included, and
+/// memory use does not grow with the collection. Users carry their v2 profiles,
+/// and personal data is masked as in the change feed unless `unmasked`.
+pub fn body(api_impl: Arc<ServerImpl>, format: Format, include_deleted: bool, unmasked: bool) -> Body {
+    struct State {
+        api_impl: Arc<ServerImpl>,
+        encoder: Option<Encoder>,
+        after: Option<Uuid>,
+        include_deleted: bool,
+        unmasked: bool,
+    }
+
+    let state = State {
+        api_impl,
+        encoder: Some(Encoder::new(format)),
+        after: None,
+        include_deleted,
+        unmasked,
+    };
+    Body::from_stream(stream::unfold(state, |mut state| async move {
+        let encoder = state.encoder.as_mut()?;
+        let users = state
+            .api_impl
+            .users_page(state.after, CHUNK, state.include_deleted)
+            .await;
+        let chunk: io::Result<Vec<u8>> = match users.last() {
+            Some((last, _, _)) => {
+                state.after = Some(*last);
+                let users: Vec<_> = if state.unmasked {
+                    users.into_iter().map(|(_, user, profile)| (user, profile)).collect()
+                } else {
+                    users
+                        .into_iter()
+                        .map(|(_, user, profile)| (mask(user), mask_profile(profile)))
+                        .collect()
+                };
+                encoder.chunk_with_profiles(users.iter().map(|(user, profile)| (user, profile)))
+            }
+            None => state.encoder.take().expect("checked above").finish(),
+        };
+        Some((chunk.map(Bytes::from), state))
+    }))
+}
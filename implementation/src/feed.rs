@@ -0,0 +1,197 @@
+//! Change feed of users: every revision in commit order, numbered by a sequence
+//! that survives restarts, for `GET /api/users/changes` (Server-Sent Events) and
+//! `GET /api/users/changes/ws` (WebSocket).
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use axum::extract::ws::{Message, WebSocket};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, Stream, StreamExt};
+use openapi::models::User;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+use crate::history::{Revision, RevisionKind};
+use crate::server::ServerImpl;
+use crate::v2::Profile;
+
+/// Changes read from the store at a time.
+const CHUNK: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeType {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl ChangeType {
+    /// Restored users reappear, erased and merged-away ones disappear.
+    pub fn of(kind: RevisionKind) -> Self {
+        match kind {
+            RevisionKind::Created | RevisionKind::Restored => ChangeType::Created,
+            RevisionKind::Updated | RevisionKind::Merged => ChangeType::Updated,
+            RevisionKind::Deleted | RevisionKind::Erased | RevisionKind::MergedInto => ChangeType::Deleted,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChangeType::Created => "created",
+            ChangeType::Updated => "updated",
+            ChangeType::Deleted => "deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeEvent {
+    /// The same on every delivery of the change, for deduplication.
+    pub event_id: Uuid,
+    pub sequence: u64,
+    #[serde(rename = "type")]
+    pub change: ChangeType,
+    /// The revision behind the change, e.g. `restored` for a `created` event.
+    pub kind: RevisionKind,
+    pub user_id: Uuid,
+    pub revision: u64,
+    pub at: DateTime<Utc>,
+    /// The user after the change; absent once the user has been erased.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<User>,
+    /// What only API v2 carries about `user`, if anything.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<Profile>,
+}
+
+impl ChangeEvent {
+    pub fn new(user_id: Uuid, revision: Revision, unmasked: bool) -> Self {
+        let sequence = revision.sequence.unwrap_or_default();
+        ChangeEvent {
+            // Revisions numbered before event ids existed get one derived from
+            // their position, which is just as stable.
+            event_id: revision
+                .event_id
+                .unwrap_or_else(|| Uuid::from_u64_pair(0, sequence)),
+            sequence,
+            change: ChangeType::of(revision.kind),
+            kind: revision.kind,
+            user_id,
+            revision: revision.revision,
+            at: revision.at,
+            user: revision.user.map(|user| if unmasked { user } else { mask(user) }),
+            profile: revision
+                .profile
+                .map(|profile| if unmasked { profile } else { mask_profile(profile) })
+                .filter(|p| !p.is_empty()),
+        }
+    }
+}
+
+/// Keeps the first letter of names and of the email, the email domain and the
+/// last four digits of the personal id.
+pub(crate) fn mask(mut user: User) -> User {
+    user.name = initial(&user.name);
+    user.surname = initial(&user.surname);
+    let digits = user.personal_id.chars().count();
+    user.personal_id = user
+        .personal_id
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i + 4 < digits { '*' } else { c })
+        .collect();
+    user.email = user.email.map(|email| match email.split_once('@') {
+        Some((local, domain)) => format!("{}@{domain}", initial(local)),
+        None => initial(&email),
+    });
+    user
+}
+
+/// Keeps the first letter of given names, like `mask` does for the name, and
+/// drops the birth date and addresses.
+pub(crate) fn mask_profile(profile: Profile) -> Profile {
+    Profile {
+        given_names: profile.given_names.iter().map(|name| initial(name)).collect(),
+        birth_date: None,
+        addresses: Vec::new(),
+    }
+}
+
+fn initial(s: &str) -> String {
+    s.chars().take(1).chain("***".chars()).collect()
+}
+
+/// Changes after feed position `after`, then every change committed from then
+/// on. Never ends on its own. Personal data is masked unless `unmasked`.
+pub fn events(
+    api_impl: Arc<ServerImpl>,
+    after: u64,
+    changes: watch::Receiver<u64>,
+    unmasked: bool,
+) -> impl Stream<Item = ChangeEvent> {
+    struct State {
+        api_impl: Arc<ServerImpl>,
+        after: u64,
+        changes: watch::Receiver<u64>,
+        pending: VecDeque<ChangeEvent>,
+    }
+
+    let state = State {
+        api_impl,
+        after,
+        changes,
+        pending: VecDeque::new(),
+    };
+    stream::unfold(state, move |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((event, state));
+            }
+            let changes = state.api_impl.changes_after(state.after, CHUNK).await;
+            if let Some((_, last)) = changes.last() {
+                state.after = last.sequence.unwrap_or(state.after);
+                state.pending.extend(
+                    changes
+                        .into_iter()
+                        .map(|(id, revision)| ChangeEvent::new(id, revision, unmasked)),
+                );
+                continue;
+            }
+            // Caught up: wait for the next commit. The receiver was created before
+            // the first read, so no commit in between is missed.
+            state.changes.changed().await.ok()?;
+        }
+    })
+}
+
+/// Sends each event as a JSON text message until the client goes away. Messages
+/// from the client are read only to answer pings and notice the close.
+pub async fn forward(mut socket: WebSocket, events: impl Stream<Item = ChangeEvent>) {
+    let mut events = std::pin::pin!(events);
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else { break };
+                let text = match serde_json::to_string(&event) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        tracing::error!(error = ?e, "cannot encode change event");
+                        break;
+                    }
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    return;
+                }
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
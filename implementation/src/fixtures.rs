@@ -0,0 +1,284 @@
+//! Files of users: JSON arrays, NDJSON and CSV with a header row.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+use openapi::models::User;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::config::SeedConfig;
+use crate::store::UserStore;
+use crate::synthetic::Generator;
+use crate::v2::Profile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// A single JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// Comma-separated values with a header row
+    Csv,
+}
+
+impl Format {
+    /// Guesses the format from the file extension.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "ndjson" | "jsonl" => Some(Format::Ndjson),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn media_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Ndjson => "application/x-ndjson",
+            Format::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    /// The format of a request body from its media type, parameters ignored.
+    pub fn from_media_type(content_type: &str) -> Option<Format> {
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        match essence.to_ascii_lowercase().as_str() {
+            "application/json" => Some(Format::Json),
+            "application/x-ndjson" | "application/jsonl" => Some(Format::Ndjson),
+            "text/csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Column order of CSV files, using the JSON field names. The last three are
+/// the v2 profile, which exports carry and imports ignore: given names joined
+/// with spaces, the birth date and the addresses as a JSON array.
+pub const CSV_COLUMNS: [&str; 10] = [
+    "id",
+    "name",
+    "surname",
+    "email",
+    "age",
+    "personalId",
+    "citizenship",
+    "givenNames",
+    "birthDate",
+    "addresses",
+];
+
+/// A user read from a file, with its 1-based row number (the line number for
+/// NDJSON, the record number after the header for CSV).
+pub type Row = (usize, Result<User, String>);
+
+/// Reads users one row at a time. A JSON array is read whole; the other formats
+/// are streamed.
+pub fn rows(reader: impl Read + Send + 'static, format: Format) -> Box<dyn Iterator<Item = Row> + Send> {
+    match format {
+        Format::Json => match serde_json::from_reader::<_, Vec<serde_json::Value>>(reader) {
+            Ok(values) => Box::new(values.into_iter().enumerate().map(|(index, value)| {
+                (index + 1, serde_json::from_value(value).map_err(|e| e.to_string()))
+            })),
+            Err(e) => Box::new(std::iter::once((1, Err(e.to_string())))),
+        },
+        Format::Ndjson => Box::new(BufReader::new(reader).lines().enumerate().filter_map(
+            |(index, line)| match line {
+                Ok(line) if line.trim().is_empty() => None,
+                Ok(line) => Some((index + 1, serde_json::from_str(&line).map_err(|e| e.to_string()))),
+                Err(e) => Some((index + 1, Err(e.to_string()))),
+            },
+        )),
+        Format::Csv => Box::new(
+            csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(reader)
+                .into_deserialize::<User>()
+                .enumerate()
+                .map(|(index, user)| (index + 1, user.map_err(|e| e.to_string()))),
+        ),
+    }
+}
+
+/// The CSV record of a user, in `CSV_COLUMNS` order.
+pub fn csv_record(user: &User, profile: &Profile) -> io::Result<[String; 10]> {
+    let addresses = if profile.addresses.is_empty() {
+        String::new()
+    } else {
+        serde_json::to_string(&profile.addresses)?
+    };
+    Ok([
+        user.id.map(|id| id.to_string()).unwrap_or_default(),
+        user.name.clone(),
+        user.surname.clone(),
+        user.email.clone().unwrap_or_default(),
+        user.age.to_string(),
+        user.personal_id.clone(),
+        user.citizenship.clone(),
+        profile.given_names.join(" "),
+        profile.birth_date.map(|d| d.to_string()).unwrap_or_default(),
+        addresses,
+    ])
+}
+
+/// Writes users in the given format.
+pub fn write<'a>(
+    writer: impl Write,
+    format: Format,
+    users: impl IntoIterator<Item = &'a User>,
+) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(writer);
+    let mut encoder = Encoder::new(format);
+    writer.write_all(&encoder.chunk(users)?)?;
+    writer.write_all(&encoder.finish()?)?;
+    writer.flush()
+}
+
+/// Encodes users a chunk at a time, so that a long list can be sent without
+/// holding all of it in memory. The concatenated chunks are a complete file.
+pub struct Encoder {
+    format: Format,
+    written: usize,
+    started: bool,
+}
+
+impl Encoder {
+    pub fn new(format: Format) -> Self {
+        Encoder {
+            format,
+            written: 0,
+            started: false,
+        }
+    }
+
+    pub fn chunk<'a>(&mut self, users: impl IntoIterator<Item = &'a User>) -> io::Result<Vec<u8>> {
+        let none = Profile::default();
+        self.chunk_with_profiles(users.into_iter().map(|user| (user, &none)))
+    }
+
+    /// Like `chunk`, with the v2 profile of each user next to its v1 fields.
+    pub fn chunk_with_profiles<'a>(
+        &mut self,
+        users: impl IntoIterator<Item = (&'a User, &'a Profile)>,
+    ) -> io::Result<Vec<u8>> {
+        #[derive(Serialize)]
+        struct Exported<'a> {
+            #[serde(flatten)]
+            user: &'a User,
+            #[serde(flatten)]
+            profile: &'a Profile,
+        }
+
+        let mut out = Vec::new();
+        self.start(&mut out)?;
+        match self.format {
+            Format::Json => {
+                for (user, profile) in users {
+                    if self.written > 0 {
+                        out.push(b',');
+                    }
+                    out.push(b'\n');
+                    serde_json::to_writer(&mut out, &Exported { user, profile })?;
+                    self.written += 1;
+                }
+            }
+            Format::Ndjson => {
+                for (user, profile) in users {
+                    serde_json::to_writer(&mut out, &Exported { user, profile })?;
+                    out.push(b'\n');
+                    self.written += 1;
+                }
+            }
+            Format::Csv => {
+                let mut csv = csv::WriterBuilder::new().has_headers(false).from_writer(&mut out);
+                for (user, profile) in users {
+                    csv.write_record(csv_record(user, profile)?)?;
+                    self.written += 1;
+                }
+                csv.flush()?;
+            }
+        }
+        Ok(out)
+    }
+
+    /// The end of the file, which is also its start if no chunk was encoded.
+    pub fn finish(mut self) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.start(&mut out)?;
+        if self.format == Format::Json {
+            out.extend_from_slice(b"\n]\n");
+        }
+        Ok(out)
+    }
+
+    fn start(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        if std::mem::replace(&mut self.started, true) {
+            return Ok(());
+        }
+        match self.format {
+            Format::Json => out.push(b'['),
+            Format::Ndjson => {}
+            Format::Csv => {
+                let mut csv = csv::Writer::from_writer(out);
+                csv.write_record(CSV_COLUMNS)?;
+                csv.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads and validates a whole fixture file. Users without an id get a random one.
+/// The first invalid row fails the load.
+pub fn load(path: &Path) -> Result<Vec<User>, String> {
+    let format = Format::from_path(path)
+        .ok_or_else(|| format!("{}: unknown format, expected .json, .ndjson or .csv", path.display()))?;
+    let file = File::open(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    let mut users = Vec::new();
+    let mut ids = HashSet::new();
+    let mut personal_ids = HashSet::new();
+    for (row, user) in rows(file, format) {
+        let fail = |e: String| format!("{}: row {row}: {e}", path.display());
+        let mut user = user.map_err(fail)?;
+        user.validate().map_err(|e| fail(e.to_string()))?;
+        let id = *user.id.get_or_insert_with(Uuid::new_v4);
+        if !ids.insert(id) {
+            return Err(fail(format!("duplicate id {id}")));
+        }
+        if !personal_ids.insert(user.personal_id.clone()) {
+            return Err(fail("duplicate personalId".into()));
+        }
+        users.push(user);
+    }
+    Ok(users)
+}
+
+/// Fills an empty store from the configured fixture file or with synthetic users.
+/// Returns how many users were added.
+pub fn seed(store: &mut UserStore, config: &SeedConfig) -> Result<usize, String> {
+    if !store.is_empty() {
+        return Ok(0);
+    }
+    let users = match &config.file {
+        Some(path) => load(path)?,
+        None => {
+            let mut generator = Generator::new(config.random_seed);
+            (0..config.synthetic_users).map(|_| generator.user()).collect()
+        }
+    };
+    let count = users.len();
+    let users = users
+        .into_iter()
+        .map(|user| (user.id.expect("ids are assigned when loading"), user))
+        .collect();
+    store
+        .insert_all(users, "seed")
+        .map_err(|e| format!("seed: {e}"))?;
+    Ok(count)
+}
@@ -0,0 +1,150 @@
+//! Liveness and readiness probes, served outside `/api` and without authentication.
+//!
+//! Ephemeral keys leave the server degraded rather than down: it serves, but
+//! its receipts and pseudonyms change with every restart.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use http::StatusCode;
+use serde::Serialize;
+
+use crate::routes::json_response;
+use crate::server::ServerImpl;
+
+/// How long the readiness probe waits for the store before reporting it down.
+const STORE_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct Health {
+    api_impl: Arc<ServerImpl>,
+    draining: AtomicBool,
+}
+
+impl Health {
+    pub fn new(api_impl: Arc<ServerImpl>) -> Self {
+        Health {
+            api_impl,
+            draining: AtomicBool::new(false),
+        }
+    }
+
+    /// Makes the readiness probe fail so that no new traffic is routed here.
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Up,
+    Degraded,
+    Down,
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+impl Check {
+    fn up(detail: impl Into<Option<String>>) -> Self {
+        Check {
+            status: Status::Up,
+            detail: detail.into(),
+        }
+    }
+
+    fn degraded(detail: impl Into<String>) -> Self {
+        Check {
+            status: Status::Degraded,
+            detail: Some(detail.into()),
+        }
+    }
+
+    fn down(detail: impl Into<String>) -> Self {
+        Check {
+            status: Status::Down,
+            detail: Some(detail.into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    status: Status,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    checks: BTreeMap<&'static str, Check>,
+}
+
+pub fn router(health: Arc<Health>) -> Router {
+    Router::new()
+        .route("/health/live", get(live))
+        .route("/health/ready", get(ready))
+        .with_state(health)
+}
+
+/// Live - GET /health/live
+async fn live() -> Response {
+    let report = Report {
+        status: Status::Up,
+        checks: BTreeMap::new(),
+    };
+    json_response(StatusCode::OK, &report)
+}
+
+/// Ready - GET /health/ready
+async fn ready(State(health): State<Arc<Health>>) -> Response {
+    let mut checks = BTreeMap::new();
+
+    checks.insert(
+        "storage",
+        match health.api_impl.check_storage(STORE_TIMEOUT).await {
+            Ok(()) => Check::up(None),
+            Err(e) => Check::down(e),
+        },
+    );
+    checks.insert(
+        "journal",
+        match health.api_impl.check_journal(STORE_TIMEOUT).await {
+            Ok(Some(entries)) => Check::up(format!("replayed {entries} entries")),
+            Ok(None) => Check::up("no journal configured".to_string()),
+            Err(e) => Check::down(e),
+        },
+    );
+    checks.insert(
+        "keys",
+        match health.api_impl.check_keys() {
+            Ok(key_id) => Check::up(format!("key id {key_id}")),
+            Err(ephemeral) => Check::degraded(format!(
+                "{} not configured: receipts and pseudonyms will not match after a restart",
+                ephemeral.join(" and ")
+            )),
+        },
+    );
+    checks.insert(
+        "shutdown",
+        if health.draining.load(Ordering::SeqCst) {
+            Check::down("shutting down")
+        } else {
+            Check::up(None)
+        },
+    );
+
+    let (status, code) = if checks.values().any(|c| matches!(c.status, Status::Down)) {
+        (Status::Down, StatusCode::SERVICE_UNAVAILABLE)
+    } else if checks.values().any(|c| matches!(c.status, Status::Degraded)) {
+        (Status::Degraded, StatusCode::OK)
+    } else {
+        (Status::Up, StatusCode::OK)
+    };
+    json_response(code, &Report { status, checks })
+}
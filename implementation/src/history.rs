@@ -0,0 +1,169 @@
+use chrono::{DateTime, Utc};
+use openapi::models::User;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::v2::Profile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevisionKind {
+    Created,
+    Updated,
+    Deleted,
+    Restored,
+    Erased,
+    /// Another user was merged into this one.
+    Merged,
+    /// This user was merged into another one and no longer exists on its own.
+    MergedInto,
+}
+
+/// One numbered change to a user. `user` is the state after the change; it is
+/// dropped from every revision when the user is erased.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Revision {
+    pub revision: u64,
+    pub at: DateTime<Utc>,
+    pub actor: String,
+    pub kind: RevisionKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<User>,
+    /// What only API v2 carries about `user`, if anything; dropped with it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<Profile>,
+    /// The other user of a merge.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge: Option<Uuid>,
+    /// Position in the change feed, across all users. Revisions written before
+    /// the feed existed have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+    /// Identifies the change to consumers, who may see it more than once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<Uuid>,
+}
+
+impl Revision {
+    /// Whether the user is visible in the collection after this revision.
+    pub fn is_live(&self) -> bool {
+        !matches!(
+            self.kind,
+            RevisionKind::Deleted | RevisionKind::Erased | RevisionKind::MergedInto
+        )
+    }
+}
+
+/// Ordered revisions of a single user.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct History {
+    revisions: Vec<Revision>,
+}
+
+impl History {
+    pub fn push(&mut self, actor: &str, kind: RevisionKind, user: Option<User>) {
+        self.push_revision(actor, kind, user, None);
+    }
+
+    /// Records one side of a merge; `other` is the user on the other side.
+    pub fn push_merge(&mut self, actor: &str, kind: RevisionKind, user: Option<User>, other: Uuid) {
+        self.push_revision(actor, kind, user, Some(other));
+    }
+
+    fn push_revision(&mut self, actor: &str, kind: RevisionKind, user: Option<User>, merge: Option<Uuid>) {
+        self.revisions.push(Revision {
+            revision: self.revisions.len() as u64 + 1,
+            at: Utc::now(),
+            actor: actor.into(),
+            kind,
+            user,
+            profile: None,
+            merge,
+            sequence: None,
+            event_id: None,
+        });
+    }
+
+    /// Keeps the user's v2 profile with the latest revision.
+    pub fn set_profile(&mut self, profile: &Profile) {
+        if let Some(revision) = self.revisions.last_mut() {
+            revision.profile = Some(profile.clone()).filter(|p| !p.is_empty());
+        }
+    }
+
+    pub fn revisions(&self) -> &[Revision] {
+        &self.revisions
+    }
+
+    /// Revisions after the first `known` ones.
+    pub fn added_since(&mut self, known: usize) -> &mut [Revision] {
+        let known = known.min(self.revisions.len());
+        &mut self.revisions[known..]
+    }
+
+    /// Adds revisions read back from the journal after the ones already here.
+    pub fn extend(&mut self, revisions: Vec<Revision>) {
+        self.revisions.extend(revisions);
+    }
+
+    pub fn get(&self, revision: u64) -> Option<&Revision> {
+        let index = revision.checked_sub(1)?;
+        self.revisions.get(usize::try_from(index).ok()?)
+    }
+
+    /// The latest revision made at or before `at`.
+    pub fn as_of(&self, at: DateTime<Utc>) -> Option<&Revision> {
+        self.revisions.iter().rev().find(|r| r.at <= at)
+    }
+
+    /// Removes every stored copy of the user's data, keeping revision metadata.
+    pub fn anonymise(&mut self) {
+        for revision in &mut self.revisions {
+            revision.user = None;
+            revision.profile = None;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    pub field: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<serde_json::Value>,
+}
+
+/// Field-level differences between the users of two revisions, keyed by their
+/// JSON names. The v2 profile counts as fields of the user.
+pub fn diff(from: &Revision, to: &Revision) -> Vec<FieldChange> {
+    let from = fields(from);
+    let to = fields(to);
+    let mut names: Vec<&String> = from.keys().chain(to.keys()).collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter(|name| from.get(*name) != to.get(*name))
+        .map(|name| FieldChange {
+            field: name.clone(),
+            from: from.get(name).cloned(),
+            to: to.get(name).cloned(),
+        })
+        .collect()
+}
+
+fn fields(revision: &Revision) -> serde_json::Map<String, serde_json::Value> {
+    let mut fields = serde_json::Map::new();
+    let user = revision.user.as_ref().map(serde_json::to_value);
+    let profile = revision.profile.as_ref().map(serde_json::to_value);
+    for value in [user, profile].into_iter().flatten() {
+        if let Ok(serde_json::Value::Object(map)) = value {
+            fields.extend(map);
+        }
+    }
+    fields
+}
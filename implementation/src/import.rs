@@ -0,0 +1,222 @@
+//! Bulk import of users, shared by `POST /api/users/import` and the
+//! `import-users` command.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+
+use clap::ValueEnum;
+use openapi::models::User;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::{Validate, ValidationErrors};
+
+use crate::fixtures::{self, Format};
+use crate::metrics;
+use crate::store::UserStore;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Nothing is imported unless every row is accepted
+    #[default]
+    AllOrNothing,
+    /// Accepted rows are imported, rejected ones are only reported
+    BestEffort,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportOptions {
+    pub mode: ImportMode,
+    /// Checks every row without writing anything.
+    pub dry_run: bool,
+}
+
+/// A parsed and validated row, not yet checked against the store.
+pub struct ImportRow {
+    pub row: usize,
+    pub user: Result<User, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowStatus {
+    Accepted,
+    Rejected,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RowReport {
+    pub row: usize,
+    pub status: RowStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    #[serde(flatten)]
+    pub options: ImportOptions,
+    /// Whether the accepted rows were written to the store.
+    pub committed: bool,
+    pub accepted: usize,
+    pub rejected: usize,
+    pub rows: Vec<RowReport>,
+}
+
+/// Parses and validates every row of `reader`. Blocks on the reader, so request
+/// bodies are read on a blocking thread.
+pub fn read(reader: impl Read + Send + 'static, format: Format) -> Vec<ImportRow> {
+    fixtures::rows(reader, format).map(checked).collect()
+}
+
+/// As `read`, but stops reading at the first row past `max_rows`.
+pub fn read_at_most(
+    reader: impl Read + Send + 'static,
+    format: Format,
+    max_rows: usize,
+) -> Result<Vec<ImportRow>, TooManyRows> {
+    let mut rows = Vec::new();
+    for row in fixtures::rows(reader, format).map(checked) {
+        if rows.len() == max_rows {
+            return Err(TooManyRows(max_rows));
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// More rows than the import accepts.
+#[derive(Debug)]
+pub struct TooManyRows(pub usize);
+
+impl fmt::Display for TooManyRows {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "more than {} rows", self.0)
+    }
+}
+
+impl std::error::Error for TooManyRows {}
+
+fn checked((row, user): fixtures::Row) -> ImportRow {
+    ImportRow {
+        row,
+        user: user
+            .map_err(|e| vec![format!("invalid row: {e}")])
+            .and_then(|user| match user.validate() {
+                Ok(()) => Ok(user),
+                Err(e) => {
+                    metrics::validation_failures(&e);
+                    Err(field_errors(&e))
+                }
+            }),
+    }
+}
+
+/// Checks ids and personal ids against the store and against earlier rows, and
+/// assigns ids to new users. Returns the report, not yet committed, and the users
+/// to insert if it is.
+pub fn review(
+    store: &UserStore,
+    rows: Vec<ImportRow>,
+    options: ImportOptions,
+) -> (ImportReport, Vec<(Uuid, User)>) {
+    // Soft-deleted users keep their personal id: they may still be restored.
+    let existing: HashSet<&str> = store.list(true).map(|u| u.personal_id.as_str()).collect();
+    let mut seen_ids = HashMap::new();
+    let mut seen_personal_ids = HashMap::new();
+    let mut reports = Vec::with_capacity(rows.len());
+    let mut accepted = Vec::new();
+    for ImportRow { row, user } in rows {
+        let checked = user.and_then(|mut user| {
+            let mut errors = Vec::new();
+            let id = *user.id.get_or_insert_with(Uuid::new_v4);
+            if store.tombstone(&id).is_some() {
+                errors.push("id: user has been erased".to_owned());
+            } else if store.record(&id).is_some() || store.merged_into(&id).is_some() {
+                errors.push("id: already in use".to_owned());
+            } else if let Some(first) = seen_ids.get(&id) {
+                errors.push(format!("id: same as row {first}"));
+            }
+            if existing.contains(user.personal_id.as_str()) {
+                errors.push("personalId: already in use".to_owned());
+            } else if let Some(first) = seen_personal_ids.get(&user.personal_id) {
+                errors.push(format!("personalId: same as row {first}"));
+            }
+            if errors.is_empty() {
+                seen_ids.insert(id, row);
+                seen_personal_ids.insert(user.personal_id.clone(), row);
+                Ok((id, user))
+            } else {
+                Err(errors)
+            }
+        });
+        reports.push(match checked {
+            Ok((id, user)) => {
+                accepted.push((id, user));
+                RowReport {
+                    row,
+                    status: RowStatus::Accepted,
+                    id: Some(id),
+                    errors: Vec::new(),
+                }
+            }
+            Err(errors) => RowReport {
+                row,
+                status: RowStatus::Rejected,
+                id: None,
+                errors,
+            },
+        });
+    }
+    let report = ImportReport {
+        options,
+        committed: false,
+        accepted: accepted.len(),
+        rejected: reports.len() - accepted.len(),
+        rows: reports,
+    };
+    (report, accepted)
+}
+
+impl ImportReport {
+    /// Whether the accepted rows should be written, given the mode.
+    pub fn should_commit(&self) -> bool {
+        !self.options.dry_run
+            && self.accepted > 0
+            && (self.options.mode == ImportMode::BestEffort || self.rejected == 0)
+    }
+}
+
+/// `field: rule` for every failed validation, with the JSON field names.
+fn field_errors(errors: &ValidationErrors) -> Vec<String> {
+    let mut messages: Vec<String> = errors
+        .field_errors()
+        .into_iter()
+        .flat_map(|(field, errors)| {
+            errors
+                .iter()
+                .map(move |error| format!("{}: {}", camel_case(field), error.code))
+        })
+        .collect();
+    messages.sort();
+    messages
+}
+
+fn camel_case(field: &str) -> String {
+    let mut parts = field.split('_');
+    let mut name = parts.next().unwrap_or_default().to_owned();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            name.extend(first.to_uppercase());
+            name.push_str(chars.as_str());
+        }
+    }
+    name
+}
@@ -0,0 +1,197 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Append-only file of JSON lines. Every append is synced to disk before it returns.
+pub struct Journal {
+    path: PathBuf,
+    file: AppendFile,
+    /// Holds an exclusive lock on the lock file next to the journal for as long
+    /// as it is open. The journal itself is replaced by compaction, so it cannot
+    /// carry the lock.
+    _lock: File,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, creating it if needed, and returns the entries
+    /// already written to it. A last line cut short by a crash during an append
+    /// is dropped: the append it belonged to never returned. Fails if another
+    /// process has the journal open.
+    pub fn open<T: DeserializeOwned>(path: &Path) -> io::Result<(Journal, Vec<T>)> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock = OpenOptions::new().create(true).truncate(false).write(true).open(lock_path)?;
+        lock.try_lock().map_err(|e| match e {
+            TryLockError::WouldBlock => io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("{} is in use by another process", path.display()),
+            ),
+            TryLockError::Error(e) => e,
+        })?;
+        let content = read_content(path)?;
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (entries, cut) = parse(path, &content)?;
+        if let Some(Cut { offset, line }) = cut {
+            file.set_len(offset as u64)?;
+            file.sync_all()?;
+            tracing::warn!(path = %path.display(), line, "dropped an incomplete last line from the journal");
+        } else if content.last().is_some_and(|b| *b != b'\n') {
+            // The last line is whole but lost its newline; the next append must
+            // not continue it.
+            file.write_all(b"\n")?;
+            file.sync_data()?;
+        }
+        let journal = Journal {
+            path: path.to_path_buf(),
+            file: AppendFile::new(file),
+            _lock: lock,
+        };
+        Ok((journal, entries))
+    }
+
+    /// Reads the entries of the journal at `path` without opening it for writing,
+    /// so a process holding it open can go on appending. A last line still being
+    /// written is left out.
+    pub fn read<T: DeserializeOwned>(path: &Path) -> io::Result<Vec<T>> {
+        let content = read_content(path)?;
+        parse(path, &content).map(|(entries, _)| entries)
+    }
+
+    /// Appends the entries and syncs once, after the last one. On error none of
+    /// them is left in the file, as `AppendFile::append` describes.
+    pub fn append<'a, T: Serialize + 'a>(&mut self, entries: impl IntoIterator<Item = &'a T>) -> io::Result<()> {
+        let mut lines = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut lines, entry)?;
+            lines.push(b'\n');
+        }
+        self.file.append(&lines)
+    }
+
+    /// Forces everything written so far, file metadata included, to disk.
+    pub fn sync(&self) -> io::Result<()> {
+        self.file.file.sync_all()
+    }
+
+    /// Fails if the file is gone or can no longer be written, or still holds
+    /// the start of an append that failed.
+    pub fn check(&self) -> io::Result<()> {
+        if self.file.is_poisoned() {
+            return Err(io::Error::other("a failed append could not be undone"));
+        }
+        if std::fs::metadata(&self.path)?.permissions().readonly() {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "journal is read-only"));
+        }
+        OpenOptions::new().append(true).open(&self.path).map(drop)
+    }
+
+    /// Replaces the whole journal with `entries`. Earlier lines, and any data they
+    /// held, are gone once this returns.
+    pub fn compact<T: Serialize>(&mut self, entries: impl IntoIterator<Item = T>) -> io::Result<()> {
+        let tmp = self.path.with_extension("compact");
+        {
+            let mut file = io::BufWriter::new(File::create(&tmp)?);
+            for entry in entries {
+                serde_json::to_writer(&mut file, &entry)?;
+                file.write_all(b"\n")?;
+            }
+            file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+        std::fs::rename(&tmp, &self.path)?;
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+        self.file = AppendFile::new(OpenOptions::new().append(true).open(&self.path)?);
+        Ok(())
+    }
+}
+
+/// A file only ever written at its end, which an append that fails leaves as
+/// it was.
+pub struct AppendFile {
+    file: File,
+    /// Length to cut the file back to before anything else is appended: an
+    /// append failed and so did cutting off what it had written.
+    undo: Option<u64>,
+}
+
+impl AppendFile {
+    pub fn new(file: File) -> Self {
+        AppendFile { file, undo: None }
+    }
+
+    /// Appends `bytes` and syncs them. On any error, the sync included, the
+    /// file is cut back to where the append started, so a partial line is
+    /// never continued by the next one. Until that cut succeeds every append
+    /// fails, after trying it again.
+    pub fn append(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if let Some(len) = self.undo {
+            self.cut(len)?;
+        }
+        let len = self.file.metadata()?.len();
+        let written = self.file.write_all(bytes).and_then(|()| self.file.sync_data());
+        if written.is_err() {
+            self.undo = Some(len);
+            if let Err(e) = self.cut(len) {
+                tracing::error!(error = %e, "cannot cut off a failed append");
+            }
+        }
+        written
+    }
+
+    /// Whether an append failed and what it wrote may still be in the file.
+    pub fn is_poisoned(&self) -> bool {
+        self.undo.is_some()
+    }
+
+    fn cut(&mut self, len: u64) -> io::Result<()> {
+        self.file.set_len(len)?;
+        self.file.sync_data()?;
+        self.undo = None;
+        Ok(())
+    }
+}
+
+fn read_content(path: &Path) -> io::Result<Vec<u8>> {
+    match std::fs::read(path) {
+        Ok(content) => Ok(content),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// An incomplete last line: where it starts and its line number.
+struct Cut {
+    offset: usize,
+    line: usize,
+}
+
+/// The entries of `content`, and its incomplete last line if there is one.
+fn parse<T: DeserializeOwned>(path: &Path, content: &[u8]) -> io::Result<(Vec<T>, Option<Cut>)> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+    for (index, line) in content.split_inclusive(|b| *b == b'\n').enumerate() {
+        let offset = start;
+        start += line.len();
+        if line.trim_ascii().is_empty() {
+            continue;
+        }
+        match serde_json::from_slice(line) {
+            Ok(entry) => entries.push(entry),
+            // Only the last line can lack its newline.
+            Err(_) if !line.ends_with(b"\n") => {
+                return Ok((entries, Some(Cut { offset, line: index + 1 })))
+            }
+            Err(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {e}", path.display(), index + 1),
+                ))
+            }
+        }
+    }
+    Ok((entries, None))
+}
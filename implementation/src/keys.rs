@@ -0,0 +1,98 @@
+use std::path::PathBuf;
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::config::{AuthConfig, Secret};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Secret key material used to sign receipts and derive pseudonymous references.
+pub struct KeyMaterial {
+    key_id: String,
+    signing_key: Vec<u8>,
+    pseudonym_key: Vec<u8>,
+    /// Settings of the keys that were not configured.
+    ephemeral: Vec<&'static str>,
+}
+
+impl KeyMaterial {
+    /// Loads the configured keys. A key that is not configured falls back to a random
+    /// key that only lives as long as the process, which the configuration allows
+    /// only with `auth.open`.
+    pub fn load(config: &AuthConfig) -> Result<Self, String> {
+        let signing_key = read_key("signing_key", &config.signing_key, &config.signing_key_file)?;
+        let pseudonym_key = read_key("pseudonym_key", &config.pseudonym_key, &config.pseudonym_key_file)?;
+        let ephemeral = [("auth.signing_key", &signing_key), ("auth.pseudonym_key", &pseudonym_key)]
+            .into_iter()
+            .filter(|(_, key)| key.is_none())
+            .map(|(name, _)| name)
+            .collect();
+        let mut keys = Self::new(
+            signing_key.unwrap_or_else(random_key),
+            pseudonym_key.unwrap_or_else(random_key),
+        );
+        keys.ephemeral = ephemeral;
+        Ok(keys)
+    }
+
+    pub fn new(signing_key: Vec<u8>, pseudonym_key: Vec<u8>) -> Self {
+        let key_id = hex::encode(&Sha256::digest(&signing_key)[..8]);
+        KeyMaterial {
+            key_id,
+            signing_key,
+            pseudonym_key,
+            ephemeral: Vec::new(),
+        }
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Settings of the keys that only live as long as the process, because
+    /// they were not configured.
+    pub fn ephemeral(&self) -> &[&'static str] {
+        &self.ephemeral
+    }
+
+    /// HMAC-SHA256 signature of `payload`, base64 encoded.
+    pub fn sign(&self, payload: &[u8]) -> String {
+        let mut mac = HmacSha256::new_from_slice(&self.signing_key).expect("HMAC accepts any key length");
+        mac.update(payload);
+        base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes())
+    }
+
+    /// Stable pseudonymous reference for a user id. It can be used to correlate records
+    /// about the same person without revealing who that person is.
+    pub fn pseudonym(&self, id: &Uuid) -> String {
+        let mut mac = HmacSha256::new_from_slice(&self.pseudonym_key).expect("HMAC accepts any key length");
+        mac.update(id.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+/// The configured key, or None if there is none.
+fn read_key(name: &str, inline: &Option<Secret>, file: &Option<PathBuf>) -> Result<Option<Vec<u8>>, String> {
+    if let Some(key) = inline {
+        return Ok(Some(key.expose().as_bytes().to_vec()));
+    }
+    let Some(path) = file else {
+        tracing::warn!("auth.{name} is not configured, using an ephemeral key");
+        return Ok(None);
+    };
+    let key = std::fs::read(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    if key.len() < 32 {
+        return Err(format!("{} must contain at least 32 bytes", path.display()));
+    }
+    Ok(Some(key))
+}
+
+fn random_key() -> Vec<u8> {
+    [Uuid::new_v4(), Uuid::new_v4()]
+        .iter()
+        .flat_map(|u| *u.as_bytes())
+        .collect()
+}
@@ -0,0 +1,34 @@
+//! The users API server, shared by the `implementation` server binary and the
+//! `admin` tool.
+
+pub mod audit;
+pub mod auth;
+pub mod batch;
+pub mod config;
+pub mod docs;
+pub mod duplicates;
+pub mod error;
+pub mod erasure;
+pub mod export;
+pub mod feed;
+pub mod fixtures;
+pub mod health;
+pub mod history;
+pub mod import;
+pub mod journal;
+pub mod keys;
+pub mod metrics;
+pub mod outbox;
+pub mod purge;
+pub mod ratelimit;
+pub mod routes;
+pub mod schema;
+pub mod search;
+pub mod security;
+pub mod serve;
+pub mod server;
+pub mod store;
+pub mod synthetic;
+pub mod tls;
+pub mod v2;
+pub mod webhooks;
@@ -1,218 +1,137 @@
-use async_trait::async_trait;
-use axum::extract::Host;
-use axum_extra::extract::CookieJar;
-use http::Method;
-use openapi::apis::users::{
-    CreateUserResponse, DeleteUserResponse, GetAllUsersResponse, GetUserByIdResponse,
-    UpdateUserResponse,
-};
-use openapi::apis::ApiKeyAuthHeader;
-use openapi::models::{
-    CreateRequest, DeleteUserPathParams, Error, GetUserByIdPathParams, RequestHeader,
-    ResponseHeader, UpdateRequest, UpdateUserPathParams, User, UserListResponse, UserResponse,
-};
+use std::fs::File;
+use std::path::Path;
+use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::extract::DefaultBodyLimit;
+use axum::middleware;
+use clap::Parser;
 use tokio::net::TcpListener;
 use tokio::signal;
-use tokio::sync::RwLock;
-use uuid::{Uuid, uuid};
-use validator::Validate;
-
-struct ServerImpl {
-    // database: sea_orm::DbConn,
-    users: Arc<RwLock<std::collections::HashMap<Uuid, User>>>,
-}
-
-impl ServerImpl {
-    fn new() -> Self {
-        let mut users = std::collections::HashMap::new();
-        users.insert(
-            uuid!("67e55044-10b1-426f-9247-bb680e5fe0c8"),
-            User {
-                id: Some(uuid!("67e55044-10b1-426f-9247-bb680e5fe0c8")),
-                name: "Adam".into(),
-                surname: "Mickiewicz".into(),
-                email: Some("mickiewicz@o2.pl".into()),
-                age: 37,
-                personal_id: "12345678900".into(),
-                citizenship: "PL".into(),
-            }
-        );
-        ServerImpl {
-            users: Arc::new(RwLock::new(users)),
-        }
-    }
-}
-
-fn build_response_header() -> ResponseHeader {
-    ResponseHeader {
-        request_id: Uuid::new_v4(),
-        send_date: chrono::Utc::now(),
-    }
-}
-
-fn build_request_header() -> RequestHeader {
-    RequestHeader {
-        request_id: Uuid::new_v4(),
-        send_date: chrono::Utc::now(),
-    }
-}
 
-#[allow(unused_variables)]
-#[async_trait]
-impl openapi::apis::users::Users for ServerImpl {
-    type Claims = ();
-
-    async fn create_user(
-        &self,
-        method: Method,
-        host: Host,
-        cookies: CookieJar,
-        mut body: CreateRequest,
-    ) -> Result<CreateUserResponse, ()> {
-        let val = body.user.validate();
-        if let Err(e) = val {
-            return Ok(CreateUserResponse::Status400_BadRequest(Error::new(
-                build_response_header(),
-                e.to_string(),
-            )));
-        };
-        let uuid = Uuid::new_v4();
-        body.user.id = Some(uuid);
-        self.users.write().await.insert(uuid, body.user.clone());
-        Ok(CreateUserResponse::Status201_UserCreatedSuccessfully(
-            UserResponse {
-                response_header: body.request_header,
-                user: body.user,
-            },
-        ))
+use implementation::audit::AuditLog;
+use implementation::auth::Authenticator;
+use implementation::config::{Cli, Command, Config, LogFormat, LoggingConfig, StorageBackend, ValidationMode};
+use implementation::fixtures::{self, Format};
+use implementation::health::{self, Health};
+use implementation::import::{self, ImportMode, ImportOptions, ImportReport};
+use implementation::keys::KeyMaterial;
+use implementation::ratelimit::{self, RateLimiter};
+use implementation::schema::{self, Validator};
+use implementation::server::ServerImpl;
+use implementation::store::UserStore;
+use implementation::tls::{self, TlsReloader};
+use implementation::v2::{self, Deprecation};
+use implementation::webhooks::{self, Webhooks};
+use implementation::{docs, metrics, outbox, purge, routes, security, serve, synthetic};
+
+pub async fn start_server(config: Config) -> Result<(), String> {
+    let auth = Authenticator::load(&config.auth)?;
+    let keys = KeyMaterial::load(&config.auth)?;
+    let mut users = UserStore::open(&config.storage).map_err(|e| format!("storage: {e}"))?;
+    let seeded = fixtures::seed(&mut users, &config.seed)?;
+    if seeded > 0 {
+        tracing::info!(users = seeded, "seeded empty store");
     }
+    let audit = AuditLog::open(&config.storage).map_err(|e| format!("audit: {e}"))?;
+    let api_impl = Arc::new(ServerImpl::new(auth, keys, users, audit));
+    let retention = chrono::Duration::hours(config.limits.soft_delete_retention_hours.into());
+    let webhooks = Arc::new(Webhooks::open(api_impl.clone(), &config.webhooks).await?);
+    webhooks.clone().spawn();
+    outbox::spawn(api_impl.clone(), webhooks.clone(), false).await;
+    outbox::start(&api_impl, &config.outbox, config.webhooks.timeout_secs).await?;
+    // After the sinks, whose positions decide which users can be purged.
+    purge::spawn(api_impl.clone(), retention);
 
-    async fn delete_user(
-        &self,
-        method: Method,
-        host: Host,
-        cookies: CookieJar,
-        claims: Self::Claims,
-        path_params: DeleteUserPathParams,
-    ) -> Result<DeleteUserResponse, ()> {
-        match self.users.write().await.remove(&path_params.id) {
-            None => Ok(DeleteUserResponse::Status404_UserNotFound(Error::new(
-                build_response_header(),
-                "404".into(),
-            ))),
-            Some(user) => Ok(DeleteUserResponse::Status204_NoContent),
-        }
+    // Init Axum router
+    let health = Arc::new(Health::new(api_impl.clone()));
+    let mut app = openapi::server::new(api_impl.clone())
+        .merge(routes::router(api_impl.clone(), &config.limits))
+        .merge(webhooks::router(webhooks))
+        .merge(health::router(health.clone()))
+        .merge(v2::router(api_impl.clone()))
+        .merge(docs::router());
+    if config.validation.mode != ValidationMode::Off {
+        let validator = Arc::new(Validator::new(config.validation.mode, config.limits.max_body_bytes));
+        app = app.route_layer(middleware::from_fn_with_state(validator, schema::validate));
     }
-
-    async fn get_all_users(
-        &self,
-        method: Method,
-        host: Host,
-        cookies: CookieJar,
-    ) -> Result<GetAllUsersResponse, ()> {
-        Ok(GetAllUsersResponse::Status200_Success(UserListResponse {
-            response_header: build_request_header(),
-            users_list: self.users.read().await.values().cloned().collect(),
-        }))
+    app = app.route_layer(middleware::from_fn_with_state(api_impl.clone(), routes::redirect_merged));
+    if let Some(deprecation) = Deprecation::new(&config.versioning) {
+        app = app.route_layer(middleware::from_fn_with_state(Arc::new(deprecation), v2::deprecate_v1));
     }
+    let app = app
+        .merge(metrics::router(api_impl.clone()))
+        .route_layer(middleware::from_fn(metrics::track));
 
-    async fn get_user_by_id(
-        &self,
-        method: Method,
-        host: Host,
-        cookies: CookieJar,
-        path_params: GetUserByIdPathParams,
-    ) -> Result<GetUserByIdResponse, ()> {
-        match self.users.read().await.get(&path_params.id) {
-            None => Ok(GetUserByIdResponse::Status404_UserNotFound(Error::new(
-                build_response_header(),
-                "404".into(),
-            ))),
-            Some(user) => Ok(GetUserByIdResponse::Status200_Success(UserResponse {
-                response_header: build_request_header(),
-                user: user.clone(),
-            })),
-        }
+    // Add layers to the router
+    let mut app = app
+        .layer(DefaultBodyLimit::max(config.limits.max_body_bytes))
+        .layer(middleware::from_fn(security::check_request));
+    if config.rate_limit.enabled {
+        let limiter = Arc::new(RateLimiter::new(config.rate_limit.clone(), api_impl.clone()));
+        app = app.layer(middleware::from_fn_with_state(limiter, ratelimit::limit));
     }
+    let app = security::harden(app, &config.security, &config.limits);
+
+    let tls = if config.tls.enabled() {
+        let tls = Arc::new(TlsReloader::load(&config.tls)?);
+        tls::reload_on_sighup(tls.clone())?;
+        Some(tls)
+    } else {
+        None
+    };
 
-    async fn update_user(
-        &self,
-        method: Method,
-        host: Host,
-        cookies: CookieJar,
-        claims: Self::Claims,
-        path_params: UpdateUserPathParams,
-        mut body: UpdateRequest,
-    ) -> Result<UpdateUserResponse, ()> {
-        let val = body.user.validate();
-        body.user.id = Some(path_params.id);
-        if let Err(e) = val {
-            return Ok(UpdateUserResponse::Status400_BadRequest(Error::new(
-                build_response_header(),
-                e.to_string(),
-            )));
-        };
-        let mut collection = self.users.write().await;
-        match collection.get(&path_params.id) {
-            None => Ok(UpdateUserResponse::Status404_UserNotFound(Error::new(
-                build_response_header(),
-                "404".into(),
-            ))),
-            Some(user) => {
-                collection
-                    .remove(&path_params.id);
-                collection
-                    .insert(path_params.id, body.user.clone());
-                Ok(UpdateUserResponse::Status200_Success(UserResponse {
-                    response_header: build_request_header(),
-                    user: body.user.clone(),
-                }))
-            }
-        }
-    }
+    // Run the server with graceful shutdown
+    let listener = TcpListener::bind(&config.server.bind)
+        .await
+        .map_err(|e| format!("server.bind: cannot listen on {}: {e}", config.server.bind))?;
+    let addr = listener
+        .local_addr()
+        .map_err(|e| format!("server.bind: cannot listen on {}: {e}", config.server.bind))?;
+    tracing::info!(%addr, tls = tls.is_some(), "listening");
+    let grace_period = Duration::from_secs(config.shutdown.grace_period_secs);
+    let signal = async move {
+        shutdown_signal().await;
+        tracing::info!(grace_period_secs = grace_period.as_secs(), "shutdown requested, reporting not ready");
+        health.start_draining();
+        tokio::time::sleep(grace_period).await;
+    };
+    let drain_timeout = Duration::from_secs(config.shutdown.drain_timeout_secs);
+    let drained = serve::run(listener, app, tls, signal, drain_timeout).await;
+
+    let started = Instant::now();
+    let flushed = api_impl.flush().await;
+    tracing::info!(
+        in_flight = drained.in_flight,
+        abandoned = drained.abandoned,
+        flushed = flushed.is_ok(),
+        flush_ms = started.elapsed().as_millis() as u64,
+        "shutdown complete"
+    );
+    flushed.map_err(|e| format!("storage: {e}"))
 }
 
-impl ApiKeyAuthHeader for ServerImpl {
-    type Claims = ();
-
-    #[doc = " Extracting Claims from Header. Return None if the Claims is invalid."]
-    #[must_use]
-    #[allow(clippy::type_complexity, clippy::type_repetition_in_bounds)]
-    fn extract_claims_from_header<'a, 'b, 'c, 'async_trait>(
-        &'a self,
-        _headers: &'b axum::http::header::HeaderMap,
-        _key: &'c str,
-    ) -> ::core::pin::Pin<
-        Box<
-            dyn ::core::future::Future<Output = Option<Self::Claims>>
-                + ::core::marker::Send
-                + 'async_trait,
-        >,
-    >
-    where
-        'a: 'async_trait,
-        'b: 'async_trait,
-        'c: 'async_trait,
-        Self: 'async_trait,
-    {
-        Box::pin(async move { Some(()) })
+/// Imports a file straight into the configured journal, as the `import-users` command.
+async fn import_users(config: &Config, file: &Path, options: ImportOptions) -> Result<ImportReport, String> {
+    if config.storage.backend != StorageBackend::Journal {
+        return Err("import-users: needs the journal backend, users kept in memory would be lost".into());
     }
-}
-
-pub async fn start_server(addr: &str) {
-    // Init Axum router
-    let app = openapi::server::new(Arc::new(ServerImpl::new()));
-
-    // Add layers to the router
-    // let app = app.layer(...);
-
-    // Run the server with graceful shutdown
-    let listener = TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
+    let format = Format::from_path(file)
+        .ok_or_else(|| format!("{}: unknown format, expected .json, .ndjson or .csv", file.display()))?;
+    let reader = File::open(file).map_err(|e| format!("cannot read {}: {e}", file.display()))?;
+    let rows = import::read(reader, format);
+
+    let keys = KeyMaterial::load(&config.auth)?;
+    let users = UserStore::open(&config.storage).map_err(|e| format!("storage: {e}"))?;
+    let audit = AuditLog::open(&config.storage).map_err(|e| format!("audit: {e}"))?;
+    let api_impl = ServerImpl::new(Authenticator::Open, keys, users, audit);
+    let report = api_impl
+        .import_users("import", rows, options)
         .await
-        .unwrap();
+        .map_err(|e| format!("import failed: {e:?}"))?;
+    api_impl.flush().await.map_err(|e| format!("storage: {e}"))?;
+    Ok(report)
 }
 
 async fn shutdown_signal() {
@@ -239,7 +158,75 @@ async fn shutdown_signal() {
     }
 }
 
+fn init_logging(config: &LoggingConfig) {
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::new(&config.level));
+    match config.format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}
+
 #[tokio::main]
-async fn main() {
-    start_server("0.0.0.0:8080").await;
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    if let Some(Command::GenerateUsers { count, format, random_seed }) = cli.command {
+        let mut generator = synthetic::Generator::new(random_seed);
+        let users: Vec<_> = (0..count).map(|_| generator.user()).collect();
+        return match fixtures::write(std::io::stdout().lock(), format, &users) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(errors) => {
+            eprintln!("invalid configuration:");
+            for error in errors {
+                eprintln!("  {error}");
+            }
+            return ExitCode::FAILURE;
+        }
+    };
+    if cli.print_config {
+        print!("{}", config.to_redacted_toml());
+        return ExitCode::SUCCESS;
+    }
+
+    init_logging(&config.logging);
+    if let Some(Command::ImportUsers { file, mode, dry_run }) = &cli.command {
+        let options = ImportOptions { mode: *mode, dry_run: *dry_run };
+        return match import_users(&config, file, options).await {
+            Ok(report) => {
+                if let Err(e) = serde_json::to_writer_pretty(std::io::stdout().lock(), &report) {
+                    eprintln!("{e}");
+                }
+                eprintln!(
+                    "{} accepted, {} rejected, {}",
+                    report.accepted,
+                    report.rejected,
+                    if report.committed { "imported" } else { "nothing imported" }
+                );
+                if report.rejected > 0 && mode == &ImportMode::AllOrNothing {
+                    ExitCode::FAILURE
+                } else {
+                    ExitCode::SUCCESS
+                }
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    match start_server(config).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            tracing::error!("{e}");
+            ExitCode::FAILURE
+        }
+    }
 }
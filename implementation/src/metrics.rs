@@ -0,0 +1,214 @@
+//! Prometheus metrics, exposed in text format on `/metrics`.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::body::Body;
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use http::header::CONTENT_TYPE;
+use http::{HeaderValue, Method, StatusCode};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge_vec,
+    Encoder, HistogramTimer, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use validator::ValidationErrors;
+
+use crate::error::ApiError;
+use crate::server::ServerImpl;
+
+lazy_static! {
+    static ref REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "users_http_requests_total",
+        "HTTP requests by method, matched route, status and response",
+        &["method", "route", "status", "response"]
+    )
+    .unwrap();
+    static ref REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "users_http_request_duration_seconds",
+        "Time to answer a request, by method, matched route, status and response",
+        &["method", "route", "status", "response"]
+    )
+    .unwrap();
+    static ref AUTH_FAILURES: IntCounter = register_int_counter!(
+        "users_auth_failures_total",
+        "Requests whose credentials were missing or rejected"
+    )
+    .unwrap();
+    static ref RATE_LIMITED: IntCounterVec = register_int_counter_vec!(
+        "users_rate_limited_total",
+        "Requests refused by the rate limiter",
+        &["reason"]
+    )
+    .unwrap();
+    static ref VALIDATION_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "users_validation_failures_total",
+        "User fields that failed validation, by rule",
+        &["field", "rule"]
+    )
+    .unwrap();
+    static ref USERS: IntGaugeVec = register_int_gauge_vec!(
+        "users_current",
+        "Users currently stored",
+        &["state"]
+    )
+    .unwrap();
+    static ref WEBHOOK_ATTEMPTS: IntCounterVec = register_int_counter_vec!(
+        "users_webhook_attempts_total",
+        "Webhook delivery attempts, by what became of the delivery",
+        &["result"]
+    )
+    .unwrap();
+    static ref OUTBOX_EVENTS: IntCounterVec = register_int_counter_vec!(
+        "users_outbox_events_total",
+        "User changes accepted by each outbox sink",
+        &["sink"]
+    )
+    .unwrap();
+    static ref OUTBOX_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "users_outbox_failures_total",
+        "Failed attempts to publish to each outbox sink",
+        &["sink"]
+    )
+    .unwrap();
+    static ref SCHEMA_VIOLATIONS: IntCounterVec = register_int_counter_vec!(
+        "users_schema_violations_total",
+        "Requests and responses not matching the OpenAPI document, by schema keyword",
+        &["operation", "direction", "keyword"]
+    )
+    .unwrap();
+    static ref STORE_DURATION: HistogramVec = register_histogram_vec!(
+        "users_store_operation_duration_seconds",
+        "Time spent in store operations, including journal writes",
+        &["operation"]
+    )
+    .unwrap();
+}
+
+/// `*Response` variants of the generated operations, by method, route and
+/// status code.
+const RESPONSES: &[(&str, &str, u16, &str)] = &[
+    ("POST", "/api/users", 201, "Status201_UserCreatedSuccessfully"),
+    ("POST", "/api/users", 400, "Status400_BadRequest"),
+    ("POST", "/api/users", 401, "Status401_Unauthorized"),
+    ("POST", "/api/users", 422, "Status422_UnprocessableEntity"),
+    ("DELETE", "/api/users/:id", 204, "Status204_NoContent"),
+    ("DELETE", "/api/users/:id", 400, "Status400_BadRequest"),
+    ("DELETE", "/api/users/:id", 401, "Status401_Unauthorized"),
+    ("DELETE", "/api/users/:id", 404, "Status404_UserNotFound"),
+    ("DELETE", "/api/users/:id", 422, "Status422_UnprocessableEntity"),
+    ("GET", "/api/users", 200, "Status200_Success"),
+    ("GET", "/api/users", 400, "Status400_BadRequest"),
+    ("GET", "/api/users", 401, "Status401_Unauthorized"),
+    ("GET", "/api/users", 422, "Status422_UnprocessableEntity"),
+    ("GET", "/api/users/:id", 200, "Status200_Success"),
+    ("GET", "/api/users/:id", 400, "Status400_BadRequest"),
+    ("GET", "/api/users/:id", 401, "Status401_Unauthorized"),
+    ("GET", "/api/users/:id", 404, "Status404_UserNotFound"),
+    ("GET", "/api/users/:id", 422, "Status422_UnprocessableEntity"),
+    ("PUT", "/api/users/:id", 200, "Status200_Success"),
+    ("PUT", "/api/users/:id", 400, "Status400_BadRequest"),
+    ("PUT", "/api/users/:id", 401, "Status401_Unauthorized"),
+    ("PUT", "/api/users/:id", 404, "Status404_UserNotFound"),
+    ("PUT", "/api/users/:id", 422, "Status422_UnprocessableEntity"),
+];
+
+pub fn router(api_impl: Arc<ServerImpl>) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(api_impl)
+}
+
+/// Metrics - GET /metrics
+async fn metrics(State(api_impl): State<Arc<ServerImpl>>) -> Response {
+    let (live, deleted) = api_impl.user_counts().await;
+    USERS.with_label_values(&["live"]).set(live as i64);
+    USERS.with_label_values(&["deleted"]).set(deleted as i64);
+
+    let mut body = Vec::new();
+    let encoder = TextEncoder::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut body) {
+        return ApiError::internal("cannot encode metrics", e).into_response();
+    }
+    let mut response = Response::new(Body::from(body));
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_str(encoder.format_type()).expect("valid header value"),
+    );
+    response
+}
+
+/// Route layer counting and timing every routed request, labelled with the
+/// route template it matched, so ids never become label values.
+pub async fn track(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_default();
+    let method = request.method().clone();
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let status = response.status();
+    let labels = [method.as_str(), route.as_str(), status.as_str(), &response_name(&method, &route, status)];
+    REQUESTS.with_label_values(&labels).inc();
+    REQUEST_DURATION
+        .with_label_values(&labels)
+        .observe(started.elapsed().as_secs_f64());
+    response
+}
+
+/// The generated `*Response` variant, or `Status<code>` for hand-written operations.
+fn response_name(method: &Method, route: &str, status: StatusCode) -> String {
+    RESPONSES
+        .iter()
+        .find(|(m, r, code, _)| *m == method.as_str() && *r == route && *code == status.as_u16())
+        .map(|(_, _, _, variant)| (*variant).to_owned())
+        .unwrap_or_else(|| format!("Status{}", status.as_u16()))
+}
+
+pub fn auth_failure() {
+    AUTH_FAILURES.inc();
+}
+
+pub fn rate_limited(reason: &str) {
+    RATE_LIMITED.with_label_values(&[reason]).inc();
+}
+
+pub fn validation_failures(errors: &ValidationErrors) {
+    for (field, errors) in errors.field_errors() {
+        for error in errors {
+            VALIDATION_FAILURES
+                .with_label_values(&[field, &error.code])
+                .inc();
+        }
+    }
+}
+
+pub fn schema_violation(operation: &str, direction: &str, keyword: &str) {
+    SCHEMA_VIOLATIONS
+        .with_label_values(&[operation, direction, keyword])
+        .inc();
+}
+
+pub fn webhook_attempt(result: &str) {
+    WEBHOOK_ATTEMPTS.with_label_values(&[result]).inc();
+}
+
+pub fn outbox_published(sink: &str, events: usize) {
+    OUTBOX_EVENTS.with_label_values(&[sink]).inc_by(events as u64);
+}
+
+pub fn outbox_failure(sink: &str) {
+    OUTBOX_FAILURES.with_label_values(&[sink]).inc();
+}
+
+/// Observes the store operation's duration when dropped.
+pub fn store_timer(operation: &str) -> HistogramTimer {
+    STORE_DURATION.with_label_values(&[operation]).start_timer()
+}
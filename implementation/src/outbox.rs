@@ -0,0 +1,328 @@
+//! Relays of the change feed to sinks. Every revision is written together with
+//! its feed position and event id, in the same journal line as the change, so
+//! the feed is a transactional outbox: what a relay reads was committed, and
+//! nothing committed is missing. Each sink has a relay that hands it the
+//! changes after the last one it took, in order, retrying until it succeeds.
+//! Delivery is at least once; consumers deduplicate on `eventId`.
+//!
+//! Changes of purged users leave the feed with them, so soft-deleted users are
+//! only purged once every sink has taken their changes: a sink that falls
+//! behind holds purges back rather than missing changes.
+
+use std::fs::OpenOptions;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::Utc;
+use http::header::{CONTENT_TYPE, USER_AGENT};
+use http_body_util::Full;
+use openapi::client::{error_chain, http_client, HttpClient};
+use serde::Serialize;
+use tokio::sync::{mpsc, Mutex};
+
+use crate::config::{OutboxConfig, Secret};
+use crate::feed::ChangeEvent;
+use crate::journal::AppendFile;
+use crate::metrics;
+use crate::server::ServerImpl;
+use crate::webhooks;
+
+/// Changes handed to a sink at a time.
+const CHUNK: usize = 500;
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// How far from the end of an event file its last line is looked for.
+const TAIL_BYTES: u64 = 64 * 1024;
+
+/// Somewhere changes are published to.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Feed position of the last change the sink has taken; the relay resumes
+    /// after it.
+    async fn position(&self) -> io::Result<u64>;
+
+    /// Takes the next changes, in feed order. On error the same changes are
+    /// offered again, so a sink that fails halfway may see some of them twice.
+    async fn publish(&self, events: &[ChangeEvent]) -> Result<(), String>;
+}
+
+/// Runs a relay from the change feed to `sink`. Personal data is masked unless
+/// `unmasked`. Returns once the sink's position is known to purges.
+pub async fn spawn(api_impl: Arc<ServerImpl>, sink: Arc<dyn Sink>, unmasked: bool) {
+    let name = sink.name();
+    let (_, mut changes) = api_impl.subscribe_changes().await;
+    let mut position = sink.position().await;
+    // Until the position can be read, purges keep every change.
+    api_impl.sink_taken(name, *position.as_ref().unwrap_or(&0));
+    tokio::spawn(async move {
+        let mut backoff = MIN_BACKOFF;
+        let mut after = loop {
+            match position {
+                Ok(position) => break position,
+                Err(e) => {
+                    tracing::error!(sink = name, error = %e, "cannot read outbox position");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    position = sink.position().await;
+                }
+            }
+        };
+        api_impl.sink_taken(name, after);
+        tracing::info!(sink = name, after, "outbox relay started");
+        backoff = MIN_BACKOFF;
+        loop {
+            let events: Vec<ChangeEvent> = api_impl
+                .changes_after(after, CHUNK)
+                .await
+                .into_iter()
+                .map(|(id, revision)| ChangeEvent::new(id, revision, unmasked))
+                .collect();
+            let Some(last) = events.last().map(|e| e.sequence) else {
+                if changes.changed().await.is_err() {
+                    return;
+                }
+                continue;
+            };
+            match sink.publish(&events).await {
+                Ok(()) => {
+                    metrics::outbox_published(name, events.len());
+                    api_impl.sink_taken(name, last);
+                    after = last;
+                    backoff = MIN_BACKOFF;
+                }
+                Err(e) => {
+                    metrics::outbox_failure(name);
+                    tracing::warn!(sink = name, error = %e, retry_secs = backoff.as_secs(), "outbox publish failed");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    });
+}
+
+/// Starts a relay for each sink enabled in `config`.
+pub async fn start(api_impl: &Arc<ServerImpl>, config: &OutboxConfig, timeout_secs: u64) -> Result<(), String> {
+    if let Some(path) = &config.file {
+        let sink = FileSink::open(path).map_err(|e| format!("outbox.file: {e}"))?;
+        spawn(api_impl.clone(), Arc::new(sink), false).await;
+    }
+    if let (Some(url), Some(secret), Some(position_file)) =
+        (&config.webhook_url, &config.webhook_secret, &config.position_file)
+    {
+        let sink = WebhookSink::new(url, secret, position_file, timeout_secs);
+        spawn(api_impl.clone(), Arc::new(sink), config.include_personal_data).await;
+    }
+    if config.log_events {
+        let (sink, events) = ChannelSink::new(api_impl.subscribe_changes().await.0);
+        spawn(api_impl.clone(), Arc::new(sink), false).await;
+        log_events(events);
+    }
+    Ok(())
+}
+
+/// Appends each change as a JSON line and syncs the file. The last line tells
+/// where to resume, so the position is written together with the changes.
+/// Personal data is always masked: nothing rewrites the file, so what erasure
+/// removes from the store would stay in it.
+pub struct FileSink {
+    path: PathBuf,
+    file: Mutex<AppendFile>,
+    position: AtomicU64,
+}
+
+impl FileSink {
+    /// Opens the file, dropping an incomplete last line left by a crash.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        let length = file.metadata()?.len();
+        let start = length.saturating_sub(TAIL_BYTES);
+        file.seek(SeekFrom::Start(start))?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail)?;
+        let complete = tail.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+        if start + (complete as u64) < length && (start == 0 || complete > 0) {
+            file.set_len(start + complete as u64)?;
+            tracing::warn!(path = %path.display(), "dropped an incomplete last line from the outbox file");
+        }
+        let position = tail[..complete]
+            .split(|b| *b == b'\n')
+            .rev()
+            .find(|line| !line.is_empty())
+            .map(|line| {
+                #[derive(serde::Deserialize)]
+                struct Line {
+                    sequence: u64,
+                }
+                serde_json::from_slice::<Line>(line)
+                    .map(|l| l.sequence)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: last line: {e}", path.display())))
+            })
+            .transpose()?
+            .unwrap_or_default();
+        Ok(FileSink {
+            path: path.to_path_buf(),
+            file: Mutex::new(AppendFile::new(file)),
+            position: AtomicU64::new(position),
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn position(&self) -> io::Result<u64> {
+        Ok(self.position.load(Ordering::SeqCst))
+    }
+
+    async fn publish(&self, events: &[ChangeEvent]) -> Result<(), String> {
+        let mut lines = Vec::new();
+        for event in events {
+            serde_json::to_writer(&mut lines, event).map_err(|e| e.to_string())?;
+            lines.push(b'\n');
+        }
+        self.file
+            .lock()
+            .await
+            .append(&lines)
+            .map_err(|e| format!("{}: {e}", self.path.display()))?;
+        if let Some(last) = events.last() {
+            self.position.store(last.sequence, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+}
+
+/// POSTs batches of changes as `{"events": [...]}`, signed like subscription
+/// webhooks, and records the last one delivered in `position_file` once the
+/// receiver accepted them.
+pub struct WebhookSink {
+    url: String,
+    secret: Secret,
+    position_file: PathBuf,
+    timeout: Duration,
+    client: HttpClient,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str, secret: &Secret, position_file: &Path, timeout_secs: u64) -> Self {
+        WebhookSink {
+            url: url.to_owned(),
+            secret: secret.clone(),
+            position_file: position_file.to_path_buf(),
+            timeout: Duration::from_secs(timeout_secs),
+            client: http_client(),
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn position(&self) -> io::Result<u64> {
+        match std::fs::read_to_string(&self.position_file) {
+            Ok(content) => content
+                .trim()
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {e}", self.position_file.display()))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn publish(&self, events: &[ChangeEvent]) -> Result<(), String> {
+        #[derive(Serialize)]
+        struct Payload<'a> {
+            events: &'a [ChangeEvent],
+        }
+
+        let body = serde_json::to_vec(&Payload { events }).map_err(|e| e.to_string())?;
+        let timestamp = Utc::now().timestamp().to_string();
+        let request = http::Request::post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(USER_AGENT, concat!("users-api-outbox/", env!("CARGO_PKG_VERSION")))
+            .header("webhook-timestamp", &timestamp)
+            .header("webhook-signature", webhooks::signature(self.secret.expose(), &timestamp, &body))
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| e.to_string())?;
+        let status = match tokio::time::timeout(self.timeout, self.client.request(request)).await {
+            Ok(Ok(response)) => response.status(),
+            Ok(Err(e)) => return Err(error_chain(&e)),
+            Err(_) => return Err(format!("no answer within {}s", self.timeout.as_secs())),
+        };
+        if !status.is_success() {
+            return Err(format!("receiver answered {status}"));
+        }
+        let Some(last) = events.last() else {
+            return Ok(());
+        };
+        // A crash before the position is written sends these changes again.
+        let tmp = self.position_file.with_extension("tmp");
+        std::fs::write(&tmp, last.sequence.to_string())
+            .and_then(|()| std::fs::rename(&tmp, &self.position_file))
+            .map_err(|e| format!("{}: {e}", self.position_file.display()))
+    }
+}
+
+/// Hands changes to a task in this process. Its position is not kept across
+/// restarts: it starts at the end of the feed as it was at startup.
+pub struct ChannelSink {
+    sender: mpsc::Sender<Vec<ChangeEvent>>,
+    start: u64,
+}
+
+impl ChannelSink {
+    pub fn new(start: u64) -> (Self, mpsc::Receiver<Vec<ChangeEvent>>) {
+        let (sender, receiver) = mpsc::channel(16);
+        (ChannelSink { sender, start }, receiver)
+    }
+}
+
+#[async_trait]
+impl Sink for ChannelSink {
+    fn name(&self) -> &'static str {
+        "channel"
+    }
+
+    async fn position(&self) -> io::Result<u64> {
+        Ok(self.start)
+    }
+
+    async fn publish(&self, events: &[ChangeEvent]) -> Result<(), String> {
+        self.sender
+            .send(events.to_vec())
+            .await
+            .map_err(|_| "receiver is gone".to_owned())
+    }
+}
+
+/// Logs every change received from the channel sink, without personal data.
+fn log_events(mut events: mpsc::Receiver<Vec<ChangeEvent>>) {
+    tokio::spawn(async move {
+        while let Some(batch) = events.recv().await {
+            for event in batch {
+                tracing::info!(
+                    event_id = %event.event_id,
+                    sequence = event.sequence,
+                    r#type = event.change.as_str(),
+                    user_id = %event.user_id,
+                    revision = event.revision,
+                    "user changed"
+                );
+            }
+        }
+    });
+}
@@ -0,0 +1,21 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::server::ServerImpl;
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically hard-deletes users whose soft-delete is older than `retention`.
+pub fn spawn(api_impl: Arc<ServerImpl>, retention: chrono::Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match api_impl.purge_deleted(retention).await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!(purged, "purged soft-deleted users"),
+                Err(e) => tracing::error!(error = %e, "purging soft-deleted users failed"),
+            }
+        }
+    });
+}
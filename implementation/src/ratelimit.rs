@@ -0,0 +1,212 @@
+//! Per-client token buckets and lockout of source IPs that keep failing to authenticate.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use http::header::RETRY_AFTER;
+use http::{HeaderValue, Method, StatusCode};
+
+use crate::auth::Identity;
+use crate::config::RateLimitConfig;
+use crate::metrics;
+use crate::routes::error_response;
+use crate::server::ServerImpl;
+
+/// Above this many tracked clients, idle entries are dropped.
+const MAX_TRACKED: usize = 10_000;
+
+/// A bucket holds one minute's allowance, so one idle this long is full again.
+const REFILL_PERIOD: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Operation {
+    List,
+    Read,
+    Write,
+}
+
+/// Routes reading many users, or whole histories, in one request.
+const LIST_ROUTES: [&str; 9] = [
+    "/api/users",
+    "/api/v2/users",
+    "/api/audit",
+    "/api/users/history",
+    "/api/users/export",
+    "/api/users/duplicates",
+    "/api/users/changes",
+    "/api/users/changes/ws",
+    "/api/users/:id/revisions",
+];
+
+impl Operation {
+    /// Classifies the request by the route it matched; requests matching none by
+    /// their method.
+    fn of(request: &Request) -> Self {
+        let route = request.extensions().get::<MatchedPath>().map(MatchedPath::as_str);
+        match (request.method(), route) {
+            (&Method::GET | &Method::HEAD, Some(route)) if LIST_ROUTES.contains(&route) => Operation::List,
+            // Searching reads the whole collection, like listing.
+            (&Method::POST, Some("/api/users/search")) => Operation::List,
+            (&Method::GET | &Method::HEAD, _) => Operation::Read,
+            _ => Operation::Write,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Client {
+    Subject(String),
+    Ip(IpAddr),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Default)]
+struct Failures {
+    count: u32,
+    window_start: Option<Instant>,
+    locked_until: Option<Instant>,
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    api_impl: Arc<ServerImpl>,
+    buckets: Mutex<HashMap<(Operation, Client), Bucket>>,
+    failures: Mutex<HashMap<IpAddr, Failures>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig, api_impl: Arc<ServerImpl>) -> Self {
+        RateLimiter {
+            config,
+            api_impl,
+            buckets: Mutex::default(),
+            failures: Mutex::default(),
+        }
+    }
+
+    fn per_minute(&self, operation: Operation) -> u32 {
+        match operation {
+            Operation::List => self.config.list_per_minute,
+            Operation::Read => self.config.read_per_minute,
+            Operation::Write => self.config.write_per_minute,
+        }
+    }
+
+    /// Takes a token from the bucket of each of `clients`, or from none of them and
+    /// returns how long until all have one available.
+    fn take(&self, operation: Operation, clients: &[Client]) -> Result<(), Duration> {
+        let capacity = f64::from(self.per_minute(operation));
+        let per_second = capacity / REFILL_PERIOD.as_secs_f64();
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("rate limit lock poisoned");
+        if buckets.len() > MAX_TRACKED {
+            buckets.retain(|_, b| now.duration_since(b.updated) < REFILL_PERIOD);
+        }
+        let mut wait = None;
+        for client in clients {
+            let bucket = buckets.entry((operation, client.clone())).or_insert(Bucket {
+                tokens: capacity,
+                updated: now,
+            });
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+            bucket.updated = now;
+            if bucket.tokens < 1.0 {
+                let until = Duration::from_secs_f64((1.0 - bucket.tokens) / per_second);
+                wait = wait.max(Some(until));
+            }
+        }
+        if let Some(wait) = wait {
+            return Err(wait);
+        }
+        for client in clients {
+            let bucket = buckets.get_mut(&(operation, client.clone())).expect("refilled above");
+            bucket.tokens -= 1.0;
+        }
+        Ok(())
+    }
+
+    /// How much longer `ip` stays locked out, if it is.
+    fn locked(&self, ip: IpAddr) -> Option<Duration> {
+        let failures = self.failures.lock().expect("rate limit lock poisoned");
+        let until = failures.get(&ip)?.locked_until?;
+        until.checked_duration_since(Instant::now())
+    }
+
+    fn record_failure(&self, ip: IpAddr) {
+        let window = Duration::from_secs(self.config.lockout_window_secs);
+        let now = Instant::now();
+        let mut failures = self.failures.lock().expect("rate limit lock poisoned");
+        if failures.len() > MAX_TRACKED {
+            failures.retain(|_, f| {
+                f.locked_until.is_some_and(|until| until > now)
+                    || f.window_start.is_some_and(|start| now.duration_since(start) < window)
+            });
+        }
+        let entry = failures.entry(ip).or_default();
+        if entry.window_start.is_none_or(|start| now.duration_since(start) >= window) {
+            entry.count = 0;
+            entry.window_start = Some(now);
+        }
+        entry.count += 1;
+        if entry.count >= self.config.lockout_after_failures {
+            let lockout = Duration::from_secs(self.config.lockout_secs);
+            tracing::warn!(%ip, failures = entry.count, "locking out client after failed authentications");
+            entry.count = 0;
+            entry.window_start = None;
+            entry.locked_until = Some(now + lockout);
+        }
+    }
+}
+
+/// Middleware applying the limits to every request except health probes and metrics.
+pub async fn limit(State(limiter): State<Arc<RateLimiter>>, request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    if path.starts_with("/health/") || path == "/metrics" {
+        return next.run(request).await;
+    }
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+
+    if let Some(retry_after) = ip.and_then(|ip| limiter.locked(ip)) {
+        return too_many_requests("LOCKED_OUT", retry_after);
+    }
+
+    let subject = match limiter.api_impl.identify(request.headers()) {
+        Identity::Authenticated(subject) => Some(Client::Subject(subject)),
+        Identity::Rejected => {
+            if let Some(ip) = ip {
+                limiter.record_failure(ip);
+            }
+            None
+        }
+        Identity::Anonymous => None,
+    };
+    // Every request counts against its source IP, so that a client cannot lift
+    // the limit by cycling keys, and authenticated ones against their subject too.
+    let clients: Vec<Client> = ip.map(Client::Ip).into_iter().chain(subject).collect();
+    if let Err(retry_after) = limiter.take(Operation::of(&request), &clients) {
+        return too_many_requests("RATE_LIMITED", retry_after);
+    }
+    next.run(request).await
+}
+
+fn too_many_requests(code: &str, retry_after: Duration) -> Response {
+    metrics::rate_limited(code);
+    let mut response = error_response(StatusCode::TOO_MANY_REQUESTS, code);
+    // Whole seconds, rounded up so that retrying on time succeeds.
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(seconds.max(1)));
+    response
+}
@@ -0,0 +1,772 @@
+//! Operations served next to the generated `openapi::server` router.
+
+use std::io;
+use std::sync::{Arc, OnceLock};
+
+use axum::body::Body;
+use axum::extract::{MatchedPath, Path, RawPathParams, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::ws::{rejection::WebSocketUpgradeRejection, WebSocketUpgrade};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::IntoResponse;
+use axum::extract::Query;
+use axum::routing::{get, post};
+use axum::{BoxError, Extension, Json, RequestExt, Router};
+use futures_util::{StreamExt, TryStreamExt};
+use http::header::{CONTENT_DISPOSITION, CONTENT_TYPE, LOCATION};
+use http::{HeaderMap, HeaderValue, Method, StatusCode};
+use openapi::apis::ApiKeyAuthHeader;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use openapi::models::{Error, ResponseHeader, UserListResponse, UserResponse};
+use serde::{Deserialize, Serialize};
+use tokio_util::io::{StreamReader, SyncIoBridge};
+use uuid::Uuid;
+
+use crate::audit::{self, AuditEntry};
+use crate::auth::{Claims, Scope};
+use crate::batch::{BatchMode, BatchRequest, OperationResult};
+use crate::config::LimitsConfig;
+use crate::duplicates::{self, Candidate, MergeRequest};
+use crate::erasure::ErasureOutcome;
+use crate::error::ApiError;
+use crate::export;
+use crate::feed::{self, ChangeEvent};
+use crate::fixtures::Format;
+use crate::history::{self, FieldChange, Revision};
+use crate::import::{self, ImportMode, ImportOptions, ImportReport};
+use crate::search::{SearchHit, SearchRequest};
+use crate::server::{build_request_header, build_response_header, MergeOutcome, RestoreOutcome, ServerImpl};
+
+/// Bulk import route; its body limit is `limits.max_import_bytes`.
+pub(crate) const IMPORT_PATH: &str = "/api/users/import";
+
+#[derive(Debug, Clone, Copy)]
+struct ImportLimit {
+    bytes: usize,
+    rows: usize,
+}
+
+pub fn router(api_impl: Arc<ServerImpl>, limits: &LimitsConfig) -> Router {
+    Router::new()
+        .route(
+            IMPORT_PATH,
+            post(import_users).layer(Extension(ImportLimit {
+                bytes: limits.max_import_bytes,
+                rows: limits.max_import_rows,
+            })),
+        )
+        .route("/api/audit", get(get_audit_log))
+        .route("/api/users/:id/erasure", post(erase_user))
+        .route("/api/users/:id/merge", post(merge_users))
+        .route("/api/users/:id/restore", post(restore_user))
+        .route("/api/users/:id/revisions", get(get_user_revisions))
+        .route("/api/users/:id/revisions/diff", get(diff_user_revisions))
+        .route("/api/users/:id/revisions/:revision", get(get_user_revision))
+        .route("/api/users/batch", post(apply_batch))
+        .route("/api/users/changes", get(get_user_changes))
+        .route("/api/users/changes/ws", get(subscribe_user_changes))
+        .route("/api/users/duplicates", get(get_duplicate_candidates))
+        .route("/api/users/history", get(get_users_as_of))
+        .route("/api/users/search", post(search_users))
+        .route("/api/users/export", get(export_users))
+        .with_state(api_impl)
+}
+
+#[derive(Debug, Deserialize)]
+struct AuditLogQueryParams {
+    after: Option<u64>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditLogResponse {
+    response_header: ResponseHeader,
+    entries: Vec<AuditEntry>,
+}
+
+/// GetAuditLog - GET /api/audit?after={after}&limit={limit}
+///
+/// Entries after sequence number `after`, oldest first, with the hashes chaining
+/// them. With the memory backend the log starts over when the server restarts.
+#[tracing::instrument(skip_all)]
+async fn get_audit_log(
+    headers: HeaderMap,
+    Query(query_params): Query<AuditLogQueryParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+) -> Response {
+    if let Err(response) = authorize(&api_impl, &headers, Scope::Admin).await {
+        return response;
+    }
+    let limit = query_params.limit.unwrap_or(audit::DEFAULT_LIMIT).min(audit::MAX_LIMIT);
+    json_response(
+        StatusCode::OK,
+        &AuditLogResponse {
+            response_header: build_response_header(),
+            entries: api_impl.audit_entries(query_params.after.unwrap_or(0), limit).await,
+        },
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct ErasureUserPathParams {
+    id: Uuid,
+}
+
+/// EraseUser - POST /api/users/{id}/erasure
+#[tracing::instrument(skip_all)]
+async fn erase_user(
+    headers: HeaderMap,
+    Path(path_params): Path<ErasureUserPathParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Admin).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    match api_impl.erase_user(&claims, path_params.id).await {
+        Ok(ErasureOutcome::Erased(receipt)) => json_response(StatusCode::OK, &receipt),
+        Ok(ErasureOutcome::AlreadyErased) => error_response(StatusCode::GONE, "USER_ERASED"),
+        Ok(ErasureOutcome::NotFound) => error_response(StatusCode::NOT_FOUND, "404"),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeUsersPathParams {
+    id: Uuid,
+}
+
+/// MergeUsers - POST /api/users/{id}/merge
+///
+/// Merges `retiredId` into the user at `id`, which survives.
+#[tracing::instrument(skip_all)]
+async fn merge_users(
+    headers: HeaderMap,
+    Path(path_params): Path<MergeUsersPathParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+    body: Result<Json<MergeRequest>, JsonRejection>,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Admin).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let Json(request) = match body {
+        Ok(body) => body,
+        Err(rejection) => return invalid_body(rejection),
+    };
+    let request_header = request.request_header.clone();
+    match api_impl.merge_users(&claims, path_params.id, request).await {
+        Ok(MergeOutcome::Merged(user)) => json_response(StatusCode::OK, &UserResponse::new(request_header, user)),
+        Ok(MergeOutcome::SameUser) => error_response(StatusCode::BAD_REQUEST, "CANNOT_MERGE_SELF"),
+        Ok(MergeOutcome::Invalid(e)) => error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+        Ok(MergeOutcome::NotFound) => error_response(StatusCode::NOT_FOUND, "404"),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MergedUserResponse {
+    response_header: ResponseHeader,
+    code: &'static str,
+    merged_into: Uuid,
+}
+
+/// Routes of GetUserById, in either version.
+const GET_USER_ROUTES: [&str; 2] = ["/api/users/:id", "/api/v2/users/:id"];
+
+/// Answers GetUserById, in either version, for a merged-away id with a redirect to the user it was
+/// merged into. Other requests, and ids that were never merged, pass through.
+pub async fn redirect_merged(
+    State(api_impl): State<Arc<ServerImpl>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(route) = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .filter(|path| request.method() == Method::GET && GET_USER_ROUTES.contains(&path.as_str()))
+    else {
+        return next.run(request).await;
+    };
+    let id = match request.extract_parts::<RawPathParams>().await {
+        Ok(params) => params
+            .iter()
+            .find(|(name, _)| *name == "id")
+            .and_then(|(_, value)| Uuid::parse_str(value).ok()),
+        Err(_) => None,
+    };
+    let merged_into = match id {
+        Some(id) => api_impl.merged_into(&id).await,
+        None => None,
+    };
+    let Some(survivor) = merged_into else {
+        return next.run(request).await;
+    };
+    let mut response = json_response(
+        StatusCode::MOVED_PERMANENTLY,
+        &MergedUserResponse {
+            response_header: build_response_header(),
+            code: "USER_MERGED",
+            merged_into: survivor,
+        },
+    );
+    if let Ok(location) = HeaderValue::try_from(route.replace(":id", &survivor.to_string())) {
+        response.headers_mut().insert(LOCATION, location);
+    }
+    response
+}
+
+#[derive(Debug, Deserialize)]
+struct DuplicateCandidatesQueryParams {
+    #[serde(rename = "minScore")]
+    min_score: Option<f64>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DuplicateCandidatesResponse {
+    response_header: ResponseHeader,
+    candidates: Vec<Candidate>,
+}
+
+/// GetDuplicateCandidates - GET /api/users/duplicates?minScore={minScore}&limit={limit}
+#[tracing::instrument(skip_all)]
+async fn get_duplicate_candidates(
+    headers: HeaderMap,
+    Query(query_params): Query<DuplicateCandidatesQueryParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+) -> Response {
+    if let Err(response) = authorize(&api_impl, &headers, Scope::Admin).await {
+        return response;
+    }
+    let min_score = query_params.min_score.unwrap_or(duplicates::DEFAULT_MIN_SCORE);
+    if !(0.0..=1.0).contains(&min_score) {
+        return error_response(StatusCode::BAD_REQUEST, "INVALID_MIN_SCORE");
+    }
+    let limit = query_params
+        .limit
+        .unwrap_or(duplicates::DEFAULT_LIMIT)
+        .min(duplicates::MAX_LIMIT);
+    json_response(
+        StatusCode::OK,
+        &DuplicateCandidatesResponse {
+            response_header: build_response_header(),
+            candidates: api_impl.duplicate_candidates(min_score, limit).await,
+        },
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct RestoreUserPathParams {
+    id: Uuid,
+}
+
+/// RestoreUser - POST /api/users/{id}/restore
+#[tracing::instrument(skip_all)]
+async fn restore_user(
+    headers: HeaderMap,
+    Path(path_params): Path<RestoreUserPathParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Admin).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    match api_impl.restore_user(&claims, path_params.id).await {
+        Ok(RestoreOutcome::Restored(user)) => json_response(
+            StatusCode::OK,
+            &UserResponse::new(build_request_header(), user),
+        ),
+        Ok(RestoreOutcome::NotDeleted) => error_response(StatusCode::CONFLICT, "USER_NOT_DELETED"),
+        Ok(RestoreOutcome::NotFound) => error_response(StatusCode::NOT_FOUND, "404"),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct UserRevisionsPathParams {
+    id: Uuid,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RevisionListResponse {
+    response_header: ResponseHeader,
+    revisions: Vec<Revision>,
+}
+
+/// GetUserRevisions - GET /api/users/{id}/revisions
+#[tracing::instrument(skip_all)]
+async fn get_user_revisions(
+    headers: HeaderMap,
+    Path(path_params): Path<UserRevisionsPathParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+) -> Response {
+    if let Err(response) = authorize(&api_impl, &headers, Scope::Admin).await {
+        return response;
+    }
+    match api_impl.user_revisions(path_params.id).await {
+        Some(revisions) => json_response(
+            StatusCode::OK,
+            &RevisionListResponse {
+                response_header: build_response_header(),
+                // Snapshots are only returned by the single revision endpoint.
+                revisions: revisions
+                    .into_iter()
+                    .map(|r| Revision { user: None, profile: None, ..r })
+                    .collect(),
+            },
+        ),
+        None => error_response(StatusCode::NOT_FOUND, "404"),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct UserRevisionPathParams {
+    id: Uuid,
+    revision: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RevisionResponse {
+    response_header: ResponseHeader,
+    revision: Revision,
+}
+
+/// GetUserRevision - GET /api/users/{id}/revisions/{revision}
+#[tracing::instrument(skip_all)]
+async fn get_user_revision(
+    headers: HeaderMap,
+    Path(path_params): Path<UserRevisionPathParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+) -> Response {
+    if let Err(response) = authorize(&api_impl, &headers, Scope::Admin).await {
+        return response;
+    }
+    match api_impl.user_revision(path_params.id, path_params.revision).await {
+        Some(revision) => json_response(
+            StatusCode::OK,
+            &RevisionResponse {
+                response_header: build_response_header(),
+                revision,
+            },
+        ),
+        None => error_response(StatusCode::NOT_FOUND, "404"),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DiffUserRevisionsQueryParams {
+    from: u64,
+    to: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RevisionDiffResponse {
+    response_header: ResponseHeader,
+    from: u64,
+    to: u64,
+    changes: Vec<FieldChange>,
+}
+
+/// DiffUserRevisions - GET /api/users/{id}/revisions/diff?from={from}&to={to}
+#[tracing::instrument(skip_all)]
+async fn diff_user_revisions(
+    headers: HeaderMap,
+    Path(path_params): Path<UserRevisionsPathParams>,
+    Query(query_params): Query<DiffUserRevisionsQueryParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+) -> Response {
+    if let Err(response) = authorize(&api_impl, &headers, Scope::Admin).await {
+        return response;
+    }
+    let from = api_impl.user_revision(path_params.id, query_params.from).await;
+    let to = api_impl.user_revision(path_params.id, query_params.to).await;
+    let (Some(from), Some(to)) = (from, to) else {
+        return error_response(StatusCode::NOT_FOUND, "404");
+    };
+    json_response(
+        StatusCode::OK,
+        &RevisionDiffResponse {
+            response_header: build_response_header(),
+            from: from.revision,
+            to: to.revision,
+            changes: history::diff(&from, &to),
+        },
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct GetUsersAsOfQueryParams {
+    #[serde(rename = "asOf")]
+    as_of: chrono::DateTime<chrono::Utc>,
+}
+
+/// GetUsersAsOf - GET /api/users/history?asOf={timestamp}
+#[tracing::instrument(skip_all)]
+async fn get_users_as_of(
+    headers: HeaderMap,
+    Query(query_params): Query<GetUsersAsOfQueryParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+) -> Response {
+    if let Err(response) = authorize(&api_impl, &headers, Scope::Admin).await {
+        return response;
+    }
+    json_response(
+        StatusCode::OK,
+        &UserListResponse::new(
+            build_request_header(),
+            api_impl.users_as_of(query_params.as_of).await,
+        ),
+    )
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchResponse {
+    response_header: ResponseHeader,
+    mode: BatchMode,
+    /// False when a transactional batch was rolled back.
+    committed: bool,
+    results: Vec<OperationResult>,
+}
+
+/// ApplyBatch - POST /api/users/batch
+///
+/// Each result carries the status and body the single-user endpoint would have
+/// answered. A rolled back transactional batch answers 422, with 424 for the
+/// operations that did not fail themselves.
+#[tracing::instrument(skip_all)]
+async fn apply_batch(
+    headers: HeaderMap,
+    State(api_impl): State<Arc<ServerImpl>>,
+    body: Result<Json<BatchRequest>, JsonRejection>,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Write).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let Json(request) = match body {
+        Ok(body) => body,
+        Err(rejection) => return invalid_body(rejection),
+    };
+    if request.operations.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "EMPTY_BATCH");
+    }
+    let mode = request.mode;
+    let request_id = request.request_header.request_id;
+    let (committed, results) = match api_impl.apply_batch(&claims, request).await {
+        Ok(applied) => applied,
+        Err(e) => return e.into_response(),
+    };
+    let status = if committed {
+        StatusCode::OK
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    json_response(
+        status,
+        &BatchResponse {
+            response_header: ResponseHeader::new(request_id, chrono::Utc::now()),
+            mode,
+            committed,
+            results,
+        },
+    )
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchResponse {
+    response_header: ResponseHeader,
+    results: Vec<SearchHit>,
+}
+
+/// SearchUsers - POST /api/users/search
+#[tracing::instrument(skip_all)]
+async fn search_users(
+    headers: HeaderMap,
+    State(api_impl): State<Arc<ServerImpl>>,
+    body: Result<Json<SearchRequest>, JsonRejection>,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Read).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let Json(request) = match body {
+        Ok(body) => body,
+        Err(rejection) => return invalid_body(rejection),
+    };
+    if request.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "NO_SEARCH_CRITERIA");
+    }
+    json_response(
+        StatusCode::OK,
+        &SearchResponse {
+            response_header: ResponseHeader::new(request.request_header.request_id, chrono::Utc::now()),
+            results: api_impl.search_users(&request, claims.has(Scope::Pii)).await,
+        },
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct UserChangesQueryParams {
+    after: Option<u64>,
+}
+
+/// Where a change feed subscription starts: the `Last-Event-ID` header of a
+/// reconnecting `EventSource`, else `after`, else the latest change.
+async fn feed_start(
+    api_impl: &ServerImpl,
+    headers: &HeaderMap,
+    query_params: UserChangesQueryParams,
+) -> Result<(u64, tokio::sync::watch::Receiver<u64>), Response> {
+    let last_event_id = match headers.get("last-event-id").map(|v| v.to_str().ok().and_then(|v| v.parse().ok())) {
+        Some(Some(id)) => Some(id),
+        Some(None) => return Err(error_response(StatusCode::BAD_REQUEST, "INVALID_LAST_EVENT_ID")),
+        None => None,
+    };
+    let (latest, changes) = api_impl.subscribe_changes().await;
+    Ok((last_event_id.or(query_params.after).unwrap_or(latest), changes))
+}
+
+/// GetUserChanges - GET /api/users/changes?after={after}
+///
+/// Server-Sent Events, one per change, with the feed position as event id and
+/// the change type as event name.
+#[tracing::instrument(skip_all)]
+async fn get_user_changes(
+    headers: HeaderMap,
+    Query(query_params): Query<UserChangesQueryParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Read).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let (after, changes) = match feed_start(&api_impl, &headers, query_params).await {
+        Ok(start) => start,
+        Err(response) => return response,
+    };
+    let events = feed::events(api_impl, after, changes, claims.has(Scope::Pii)).map(|event: ChangeEvent| {
+        Event::default()
+            .id(event.sequence.to_string())
+            .event(event.change.as_str())
+            .json_data(&event)
+    });
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+/// SubscribeUserChanges - GET /api/users/changes/ws?after={after}
+///
+/// The same events as GetUserChanges, as JSON text messages.
+#[tracing::instrument(skip_all)]
+async fn subscribe_user_changes(
+    headers: HeaderMap,
+    Query(query_params): Query<UserChangesQueryParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+    upgrade: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Read).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let Ok(upgrade) = upgrade else {
+        return error_response(StatusCode::UPGRADE_REQUIRED, "WEBSOCKET_REQUIRED");
+    };
+    let (after, changes) = match feed_start(&api_impl, &headers, query_params).await {
+        Ok(start) => start,
+        Err(response) => return response,
+    };
+    let events = feed::events(api_impl, after, changes, claims.has(Scope::Pii));
+    upgrade.on_upgrade(move |socket| feed::forward(socket, events))
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportUsersQueryParams {
+    format: Option<Format>,
+    #[serde(rename = "includeDeleted")]
+    include_deleted: Option<bool>,
+}
+
+/// ExportUsers - GET /api/users/export?format={format}&includeDeleted={includeDeleted}
+///
+/// Streams the users matching the same filter as GetAllUsers, as NDJSON (the
+/// default), CSV or a JSON array, masked without the `pii` scope.
+#[tracing::instrument(skip_all)]
+async fn export_users(
+    headers: HeaderMap,
+    Query(query_params): Query<ExportUsersQueryParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Read).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let format = query_params.format.unwrap_or(Format::Ndjson);
+    let include_deleted = query_params.include_deleted.unwrap_or(false);
+    let unmasked = claims.has(Scope::Pii);
+    // Deleted users are kept for restoring and erasure, not for browsing.
+    if include_deleted && !unmasked && !claims.has(Scope::Admin) {
+        return error_response(StatusCode::FORBIDDEN, "INSUFFICIENT_SCOPE");
+    }
+    let extension = match format {
+        Format::Json => "json",
+        Format::Ndjson => "ndjson",
+        Format::Csv => "csv",
+    };
+    let mut response = Response::new(export::body(api_impl, format, include_deleted, unmasked));
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(format.media_type()));
+    headers.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!("attachment; filename=\"users.{extension}\""))
+            .expect("valid header value"),
+    );
+    response
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportUsersQueryParams {
+    mode: Option<ImportMode>,
+    #[serde(rename = "dryRun")]
+    dry_run: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportUsersResponse {
+    response_header: ResponseHeader,
+    report: ImportReport,
+}
+
+/// ImportUsers - POST /api/users/import?mode={mode}&dryRun={dryRun}
+///
+/// The body is a JSON array, NDJSON or CSV, as told by its Content-Type. It is
+/// parsed as it arrives, up to `limits.max_import_rows` rows; nothing is written
+/// before the last row has been checked.
+#[tracing::instrument(skip_all)]
+async fn import_users(
+    headers: HeaderMap,
+    Query(query_params): Query<ImportUsersQueryParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+    Extension(limit): Extension<ImportLimit>,
+    body: Body,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Write).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let Some(format) = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(Format::from_media_type)
+    else {
+        return error_response(StatusCode::UNSUPPORTED_MEDIA_TYPE, "UNSUPPORTED_MEDIA_TYPE");
+    };
+    let options = ImportOptions {
+        mode: query_params.mode.unwrap_or_default(),
+        dry_run: query_params.dry_run.unwrap_or(false),
+    };
+
+    // A body that cannot be read fails the whole import rather than its last row.
+    let body_error = Arc::new(OnceLock::new());
+    let stream = Limited::new(body, limit.bytes).into_data_stream().map_err({
+        let body_error = body_error.clone();
+        move |e| {
+            let too_large = is_length_limit(&e);
+            let _ = body_error.set(too_large);
+            io::Error::other(e)
+        }
+    });
+    let reader = SyncIoBridge::new(StreamReader::new(stream));
+    let read = tokio::task::spawn_blocking(move || import::read_at_most(reader, format, limit.rows));
+    let rows = match read.await {
+        Ok(rows) => rows,
+        Err(e) => return ApiError::internal("import reader panicked", e).into_response(),
+    };
+    match body_error.get() {
+        Some(true) => return error_response(StatusCode::PAYLOAD_TOO_LARGE, "PAYLOAD_TOO_LARGE"),
+        Some(false) => return error_response(StatusCode::BAD_REQUEST, "INVALID_BODY"),
+        None => {}
+    }
+    let Ok(rows) = rows else {
+        return error_response(StatusCode::PAYLOAD_TOO_LARGE, "TOO_MANY_ROWS");
+    };
+
+    let report = match api_impl.import_users(&claims.subject, rows, options).await {
+        Ok(report) => report,
+        Err(e) => return e.into_response(),
+    };
+    // All or nothing with rejected rows: nothing was imported.
+    let status = if report.rejected > 0 && options.mode == ImportMode::AllOrNothing {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::OK
+    };
+    json_response(
+        status,
+        &ImportUsersResponse {
+            response_header: build_response_header(),
+            report,
+        },
+    )
+}
+
+fn is_length_limit(error: &BoxError) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error.as_ref());
+    while let Some(e) = source {
+        if e.is::<LengthLimitError>() {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// Authenticates the caller and checks that it holds `scope`.
+pub(crate) async fn authorize(
+    api_impl: &ServerImpl,
+    headers: &HeaderMap,
+    scope: Scope,
+) -> Result<Claims, Response> {
+    let Some(claims) = api_impl.extract_claims_from_header(headers, "Bearer").await else {
+        return Err(error_response(StatusCode::UNAUTHORIZED, "UNAUTHORIZED"));
+    };
+    if !claims.has(scope) {
+        return Err(error_response(StatusCode::FORBIDDEN, "INSUFFICIENT_SCOPE"));
+    }
+    Ok(claims)
+}
+
+pub(crate) fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response {
+    let body = match serde_json::to_vec(body) {
+        Ok(body) => body,
+        Err(e) => return ApiError::internal("cannot serialize the response", e).into_response(),
+    };
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+pub(crate) fn error_response(status: StatusCode, code: &str) -> Response {
+    json_response(status, &Error::new(build_response_header(), code.into()))
+}
+
+/// 400 for a JSON body that could not be read, with the reason as message.
+pub(crate) fn invalid_body(rejection: JsonRejection) -> Response {
+    let mut error = Error::new(build_response_header(), "INVALID_BODY".into());
+    error.message = Some(rejection.body_text());
+    json_response(StatusCode::BAD_REQUEST, &error)
+}
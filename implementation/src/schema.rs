@@ -0,0 +1,569 @@
+//! Checks of requests, and in strict mode responses, against the OpenAPI
+//! document in `openapi::spec`. The generated models only keep part of the
+//! schema, e.g. not the citizenship enum; these checks apply all of it.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::body::{to_bytes, Body, HttpBody};
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use http::header::CONTENT_TYPE;
+use http::request::Parts;
+use http::StatusCode;
+use openapi::models::Error;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::ValidationMode;
+use crate::fixtures::Format;
+use crate::metrics;
+use crate::routes::{error_response, json_response, IMPORT_PATH};
+use crate::server::build_response_header;
+
+/// Where a violation was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Location {
+    Path,
+    Query,
+    Header,
+    Body,
+    Response,
+}
+
+impl Location {
+    fn direction(self) -> &'static str {
+        match self {
+            Location::Response => "response",
+            _ => "request",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    #[serde(rename = "in")]
+    pub location: Location,
+    /// JSON pointer into the body, or the parameter name.
+    pub path: String,
+    /// The schema keyword that failed, e.g. `enum` or `required`.
+    pub keyword: &'static str,
+    /// Says what was expected, never what was sent, which may be personal data.
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ViolationResponse {
+    #[serde(flatten)]
+    error: Error,
+    violations: Vec<Violation>,
+}
+
+/// What the document says about one operation, with references resolved.
+struct Operation {
+    id: String,
+    parameters: Vec<Parameter>,
+    /// Schema of a JSON body, and whether a body is required.
+    body: Option<(bool, Option<&'static Value>)>,
+    /// JSON schema of each documented status (`200`, `2XX`, `default`), or None
+    /// for responses without a JSON body.
+    responses: HashMap<String, Option<&'static Value>>,
+}
+
+struct Parameter {
+    name: String,
+    location: Location,
+    required: bool,
+    schema: &'static Value,
+}
+
+pub struct Validator {
+    mode: ValidationMode,
+    max_body_bytes: usize,
+    document: &'static Value,
+    /// By method and route as axum matches it, e.g. `/api/users/:id`.
+    operations: HashMap<(String, String), Operation>,
+    patterns: HashMap<&'static str, Regex>,
+}
+
+impl Validator {
+    pub fn new(mode: ValidationMode, max_body_bytes: usize) -> Self {
+        let document = openapi::spec::document();
+        let mut validator = Validator {
+            mode,
+            max_body_bytes,
+            document,
+            operations: HashMap::new(),
+            patterns: HashMap::new(),
+        };
+        collect_patterns(document, &mut validator.patterns);
+
+        let paths = document["paths"].as_object().into_iter().flatten();
+        for (path, item) in paths {
+            let route = path
+                .split('/')
+                .map(|segment| match segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                    Some(name) => format!(":{name}"),
+                    None => segment.to_owned(),
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            for method in ["get", "post", "put", "delete", "patch"] {
+                let Some(operation) = item.get(method) else { continue };
+                let operation = validator.operation(item, operation);
+                validator
+                    .operations
+                    .insert((method.to_ascii_uppercase(), route.clone()), operation);
+            }
+        }
+        validator
+    }
+
+    fn operation(&self, item: &'static Value, operation: &'static Value) -> Operation {
+        let parameters = [&item["parameters"], &operation["parameters"]]
+            .into_iter()
+            .filter_map(Value::as_array)
+            .flatten()
+            .map(|p| self.resolve(p))
+            .filter_map(|p| {
+                let location = match p["in"].as_str()? {
+                    "path" => Location::Path,
+                    "query" => Location::Query,
+                    "header" => Location::Header,
+                    _ => return None,
+                };
+                Some(Parameter {
+                    name: p["name"].as_str()?.to_owned(),
+                    location,
+                    required: p["required"].as_bool().unwrap_or(false),
+                    schema: &p["schema"],
+                })
+            })
+            .collect();
+        let body = operation.get("requestBody").map(|body| {
+            let body = self.resolve(body);
+            (
+                body["required"].as_bool().unwrap_or(false),
+                body["content"].get("application/json").map(|c| &c["schema"]),
+            )
+        });
+        let responses = operation["responses"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(status, response)| {
+                let schema = self.resolve(response)["content"]
+                    .get("application/json")
+                    .map(|c| &c["schema"]);
+                (status.clone(), schema)
+            })
+            .collect();
+        Operation {
+            id: operation["operationId"].as_str().unwrap_or_default().to_owned(),
+            parameters,
+            body,
+            responses,
+        }
+    }
+
+    /// Follows `$ref`s within the document.
+    fn resolve(&self, mut node: &'static Value) -> &'static Value {
+        while let Some(reference) = node.get("$ref").and_then(Value::as_str) {
+            match reference.strip_prefix('#').and_then(|pointer| self.document.pointer(pointer)) {
+                Some(target) => node = target,
+                None => break,
+            }
+        }
+        node
+    }
+
+    /// Checks `value` against `schema`, adding what does not match to `violations`.
+    pub fn check(
+        &self,
+        schema: &'static Value,
+        value: &Value,
+        location: Location,
+        path: &str,
+        violations: &mut Vec<Violation>,
+    ) {
+        let schema = self.resolve(schema);
+        let mut violation = |keyword: &'static str, message: String| {
+            violations.push(Violation {
+                location,
+                path: path.to_owned(),
+                keyword,
+                message,
+            })
+        };
+
+        if value.is_null() && schema["nullable"].as_bool() == Some(true) {
+            return;
+        }
+        if let Some(all) = schema["allOf"].as_array() {
+            for schema in all {
+                self.check(schema, value, location, path, violations);
+            }
+            return self.check_keywords(schema, value, location, path, violations);
+        }
+        for (keyword, exactly_one) in [("oneOf", true), ("anyOf", false)] {
+            let Some(options) = schema[keyword].as_array() else { continue };
+            let matching = options
+                .iter()
+                .filter(|option| {
+                    let mut found = Vec::new();
+                    self.check(option, value, location, path, &mut found);
+                    found.is_empty()
+                })
+                .count();
+            if matching == 0 || (exactly_one && matching > 1) {
+                violation(
+                    if exactly_one { "oneOf" } else { "anyOf" },
+                    format!("must match {} of the {} schemas", if exactly_one { "exactly one" } else { "one" }, options.len()),
+                );
+                return;
+            }
+        }
+
+        if let Some(expected) = schema["type"].as_str() {
+            let matches = match expected {
+                "object" => value.is_object(),
+                "array" => value.is_array(),
+                "string" => value.is_string(),
+                "boolean" => value.is_boolean(),
+                "number" => value.is_number(),
+                "integer" => value.is_i64() || value.is_u64(),
+                _ => true,
+            };
+            if !matches {
+                violation("type", format!("must be {}", article(expected)));
+                return;
+            }
+        }
+        self.check_keywords(schema, value, location, path, violations);
+    }
+
+    fn check_keywords(
+        &self,
+        schema: &'static Value,
+        value: &Value,
+        location: Location,
+        path: &str,
+        violations: &mut Vec<Violation>,
+    ) {
+        let mut found = Vec::new();
+        let mut violation = |keyword: &'static str, message: String| {
+            found.push(Violation {
+                location,
+                path: path.to_owned(),
+                keyword,
+                message,
+            })
+        };
+
+        if let Some(allowed) = schema["enum"].as_array() {
+            if !allowed.contains(value) {
+                let allowed: Vec<String> = allowed
+                    .iter()
+                    .map(|v| v.as_str().map_or_else(|| v.to_string(), str::to_owned))
+                    .collect();
+                violation("enum", format!("must be one of {}", allowed.join(", ")));
+            }
+        }
+        match value {
+            Value::String(text) => {
+                if let Some(pattern) = schema["pattern"].as_str() {
+                    if self.patterns.get(pattern).is_some_and(|re| !re.is_match(text)) {
+                        violation("pattern", format!("must match {pattern}"));
+                    }
+                }
+                let length = text.chars().count() as u64;
+                if schema["minLength"].as_u64().is_some_and(|min| length < min) {
+                    violation("minLength", format!("must be at least {} characters long", schema["minLength"]));
+                }
+                if schema["maxLength"].as_u64().is_some_and(|max| length > max) {
+                    violation("maxLength", format!("must be at most {} characters long", schema["maxLength"]));
+                }
+                let valid_format = match schema["format"].as_str() {
+                    Some("uuid") => uuid::Uuid::parse_str(text).is_ok(),
+                    Some("date-time") => chrono::DateTime::parse_from_rfc3339(text).is_ok(),
+                    Some("date") => chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d").is_ok(),
+                    Some("uri") => text.parse::<http::Uri>().is_ok_and(|uri| uri.scheme().is_some()),
+                    _ => true,
+                };
+                if !valid_format {
+                    violation("format", format!("must be a {}", schema["format"].as_str().unwrap_or_default()));
+                }
+            }
+            Value::Number(number) => {
+                let number = number.as_f64().unwrap_or_default();
+                if let Some(minimum) = schema["minimum"].as_f64() {
+                    let exclusive = schema["exclusiveMinimum"].as_bool() == Some(true);
+                    if number < minimum || (exclusive && number == minimum) {
+                        violation("minimum", format!("must be at least {}", schema["minimum"]));
+                    }
+                }
+                if let Some(maximum) = schema["maximum"].as_f64() {
+                    let exclusive = schema["exclusiveMaximum"].as_bool() == Some(true);
+                    if number > maximum || (exclusive && number == maximum) {
+                        violation("maximum", format!("must be at most {}", schema["maximum"]));
+                    }
+                }
+            }
+            Value::Array(items) => {
+                if schema["minItems"].as_u64().is_some_and(|min| (items.len() as u64) < min) {
+                    violation("minItems", format!("must have at least {} items", schema["minItems"]));
+                }
+                if schema["maxItems"].as_u64().is_some_and(|max| items.len() as u64 > max) {
+                    violation("maxItems", format!("must have at most {} items", schema["maxItems"]));
+                }
+            }
+            Value::Object(fields) => {
+                for name in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                    if !fields.contains_key(name) {
+                        found.push(Violation {
+                            location,
+                            path: format!("{path}/{}", escape(name)),
+                            keyword: "required",
+                            message: "is required".into(),
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+        violations.append(&mut found);
+
+        if let Value::Array(items) = value {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    self.check(item_schema, item, location, &format!("{path}/{index}"), violations);
+                }
+            }
+        }
+        if let Value::Object(fields) = value {
+            let properties = schema["properties"].as_object();
+            for (name, field) in fields {
+                let field_path = format!("{path}/{}", escape(name));
+                match (properties.and_then(|p| p.get(name)), schema.get("additionalProperties")) {
+                    (Some(property), _) => self.check(property, field, location, &field_path, violations),
+                    (None, Some(Value::Bool(false))) => violations.push(Violation {
+                        location,
+                        path: field_path,
+                        keyword: "additionalProperties",
+                        message: "is not a known field".into(),
+                    }),
+                    (None, Some(additional @ Value::Object(_))) => {
+                        self.check(additional, field, location, &field_path, violations)
+                    }
+                    (None, _) => {}
+                }
+            }
+        }
+    }
+
+    /// Checks the parameters of a request. Parameters come as text and are read
+    /// as the type their schema gives before being checked.
+    fn check_parameters(&self, operation: &Operation, route: &str, parts: &Parts, violations: &mut Vec<Violation>) {
+        let path_values: HashMap<&str, &str> = route
+            .split('/')
+            .zip(parts.uri.path().split('/'))
+            .filter_map(|(template, value)| Some((template.strip_prefix(':')?, value)))
+            .collect();
+        let query: Vec<(String, String)> = serde_urlencoded::from_str(parts.uri.query().unwrap_or_default())
+            .unwrap_or_default();
+        for parameter in &operation.parameters {
+            let text = match parameter.location {
+                Location::Path => path_values.get(parameter.name.as_str()).map(|v| (*v).to_owned()),
+                Location::Query => query.iter().find(|(name, _)| *name == parameter.name).map(|(_, v)| v.clone()),
+                Location::Header => parts
+                    .headers
+                    .get(&parameter.name)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_owned),
+                Location::Body | Location::Response => None,
+            };
+            let Some(text) = text else {
+                if parameter.required {
+                    violations.push(Violation {
+                        location: parameter.location,
+                        path: parameter.name.clone(),
+                        keyword: "required",
+                        message: "is required".into(),
+                    });
+                }
+                continue;
+            };
+            let value = match self.resolve(parameter.schema)["type"].as_str() {
+                Some("integer") => text.parse::<i64>().map(Value::from).ok(),
+                Some("number") => text.parse::<f64>().ok().map(Value::from),
+                Some("boolean") => text.parse::<bool>().map(Value::from).ok(),
+                _ => Some(Value::String(text)),
+            };
+            match value {
+                Some(value) => self.check(parameter.schema, &value, parameter.location, &parameter.name, violations),
+                None => violations.push(Violation {
+                    location: parameter.location,
+                    path: parameter.name.clone(),
+                    keyword: "type",
+                    message: format!(
+                        "must be {}",
+                        article(self.resolve(parameter.schema)["type"].as_str().unwrap_or_default())
+                    ),
+                }),
+            }
+        }
+    }
+}
+
+fn collect_patterns(node: &'static Value, patterns: &mut HashMap<&'static str, Regex>) {
+    match node {
+        Value::Object(fields) => {
+            if let Some(pattern) = fields.get("pattern").and_then(Value::as_str) {
+                let regex = Regex::new(pattern).expect("patterns of the OpenAPI document are valid");
+                patterns.insert(pattern, regex);
+            }
+            fields.values().for_each(|v| collect_patterns(v, patterns));
+        }
+        Value::Array(items) => items.iter().for_each(|v| collect_patterns(v, patterns)),
+        _ => {}
+    }
+}
+
+/// A JSON pointer reference token.
+fn escape(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
+fn article(kind: &str) -> String {
+    match kind {
+        "object" | "array" | "integer" => format!("an {kind}"),
+        _ => format!("a {kind}"),
+    }
+}
+
+/// Route layer checking each request of a documented operation, and in strict
+/// mode its response. The bulk import is left to its own row checks, as its
+/// body is read as it streams in.
+pub async fn validate(State(validator): State<Arc<Validator>>, request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_default();
+    let Some(operation) = validator.operations.get(&(request.method().to_string(), route.clone())) else {
+        return next.run(request).await;
+    };
+
+    let (parts, body) = request.into_parts();
+    let mut violations = Vec::new();
+    validator.check_parameters(operation, &route, &parts, &mut violations);
+
+    let json_body = parts
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(Format::from_media_type)
+        == Some(Format::Json);
+    let body = match operation.body {
+        Some((required, schema)) if route != IMPORT_PATH => {
+            let Ok(bytes) = to_bytes(body, validator.max_body_bytes).await else {
+                return error_response(StatusCode::PAYLOAD_TOO_LARGE, "PAYLOAD_TOO_LARGE");
+            };
+            if bytes.is_empty() && required {
+                violations.push(Violation {
+                    location: Location::Body,
+                    path: String::new(),
+                    keyword: "required",
+                    message: "a body is required".into(),
+                });
+            }
+            // A body that is not JSON is left to the handler to refuse.
+            if let (Some(schema), true, Ok(value)) = (schema, json_body, serde_json::from_slice::<Value>(&bytes)) {
+                validator.check(schema, &value, Location::Body, "", &mut violations);
+            }
+            Body::from(bytes)
+        }
+        _ => body,
+    };
+
+    if !violations.is_empty() {
+        record(&operation.id, &violations);
+        return violation_response(StatusCode::BAD_REQUEST, "SCHEMA_VIOLATION", violations);
+    }
+    let response = next.run(Request::from_parts(parts, body)).await;
+    if validator.mode == ValidationMode::Strict {
+        return check_response(&validator, operation, response).await;
+    }
+    response
+}
+
+/// Replaces a response that does not match the document with a 500 saying why.
+/// Server errors and streamed bodies are passed through as they are.
+async fn check_response(validator: &Validator, operation: &Operation, response: Response) -> Response {
+    let status = response.status();
+    if status.is_server_error() {
+        return response;
+    }
+    let code = status.as_str();
+    let range = format!("{}XX", &code[..1]);
+    let documented = [code, range.as_str(), "default"]
+        .into_iter()
+        .find_map(|key| operation.responses.get(key));
+    let Some(schema) = documented else {
+        let violations = vec![Violation {
+            location: Location::Response,
+            path: String::new(),
+            keyword: "status",
+            message: format!("status {code} is not documented"),
+        }];
+        record(&operation.id, &violations);
+        return violation_response(StatusCode::INTERNAL_SERVER_ERROR, "RESPONSE_SCHEMA_VIOLATION", violations);
+    };
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    let (Some(schema), true, Some(_)) = (schema, is_json, response.body().size_hint().exact()) else {
+        return response;
+    };
+
+    let (parts, body) = response.into_parts();
+    let Ok(bytes) = to_bytes(body, usize::MAX).await else {
+        return json_response(StatusCode::INTERNAL_SERVER_ERROR, &Error::new(build_response_header(), "500".into()));
+    };
+    let mut violations = Vec::new();
+    match serde_json::from_slice::<Value>(&bytes) {
+        Ok(value) => validator.check(schema, &value, Location::Response, "", &mut violations),
+        Err(_) => violations.push(Violation {
+            location: Location::Response,
+            path: String::new(),
+            keyword: "type",
+            message: "must be JSON".into(),
+        }),
+    }
+    if violations.is_empty() {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+    tracing::error!(operation = %operation.id, status = %status, ?violations, "response does not match the OpenAPI document");
+    record(&operation.id, &violations);
+    violation_response(StatusCode::INTERNAL_SERVER_ERROR, "RESPONSE_SCHEMA_VIOLATION", violations)
+}
+
+fn record(operation: &str, violations: &[Violation]) {
+    for violation in violations {
+        metrics::schema_violation(operation, violation.location.direction(), violation.keyword);
+    }
+}
+
+fn violation_response(status: StatusCode, code: &str, violations: Vec<Violation>) -> Response {
+    let mut error = Error::new(build_response_header(), code.into());
+    error.message = Some(match violations.len() {
+        1 => "1 schema violation".to_owned(),
+        n => format!("{n} schema violations"),
+    });
+    json_response(status, &ViolationResponse { error, violations })
+}
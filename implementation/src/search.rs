@@ -0,0 +1,121 @@
+//! Lookup of users by personal id and email, and fuzzy matching on names.
+
+use std::cmp::Ordering;
+
+use openapi::models::{RequestHeader, User};
+use serde::{Deserialize, Serialize};
+
+/// Names scoring below this are not a match.
+const MIN_SCORE: f64 = 0.75;
+
+pub const DEFAULT_LIMIT: usize = 20;
+pub const MAX_LIMIT: usize = 100;
+
+/// Criteria are combined: a user must match every one given. Sent in a POST body
+/// so that personal ids stay out of URLs and access logs.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchRequest {
+    pub request_header: RequestHeader,
+    /// Exact match.
+    pub personal_id: Option<String>,
+    /// Exact match, ignoring ASCII case.
+    pub email: Option<String>,
+    /// Fuzzy match, ignoring case and diacritics.
+    pub name: Option<String>,
+    /// Fuzzy match, ignoring case and diacritics.
+    pub surname: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl SearchRequest {
+    pub fn is_empty(&self) -> bool {
+        self.personal_id.is_none() && self.email.is_none() && self.name.is_none() && self.surname.is_none()
+    }
+
+    /// How well `user` matches, from 0 to 1, or None if it does not.
+    pub fn score(&self, user: &User) -> Option<f64> {
+        if self.personal_id.as_ref().is_some_and(|p| *p != user.personal_id) {
+            return None;
+        }
+        if let Some(email) = &self.email {
+            if !user.email.as_ref().is_some_and(|e| e.eq_ignore_ascii_case(email)) {
+                return None;
+            }
+        }
+        let mut scores = Vec::with_capacity(2);
+        for (query, value) in [(&self.name, &user.name), (&self.surname, &user.surname)] {
+            if let Some(query) = query {
+                let score = similarity(query, value);
+                if score < MIN_SCORE {
+                    return None;
+                }
+                scores.push(score);
+            }
+        }
+        if scores.is_empty() {
+            return Some(1.0);
+        }
+        Some(scores.iter().sum::<f64>() / scores.len() as f64)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub score: f64,
+    pub user: User,
+}
+
+/// Best matches first, then by surname and name.
+pub fn rank(hits: &mut [SearchHit]) {
+    hits.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.user.surname.cmp(&b.user.surname))
+            .then_with(|| a.user.name.cmp(&b.user.name))
+    });
+}
+
+/// 1 minus the edit distance between the folded strings, relative to the longer one.
+pub fn similarity(query: &str, value: &str) -> f64 {
+    let query: Vec<char> = fold(query.trim()).chars().collect();
+    let value: Vec<char> = fold(value.trim()).chars().collect();
+    let longest = query.len().max(value.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(&query, &value) as f64 / longest as f64
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Lowercase with Polish diacritics removed.
+pub fn fold(s: &str) -> String {
+    s.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'ą' => 'a',
+            'ć' => 'c',
+            'ę' => 'e',
+            'ł' => 'l',
+            'ń' => 'n',
+            'ó' => 'o',
+            'ś' => 's',
+            'ź' | 'ż' => 'z',
+            c => c,
+        })
+        .collect()
+}
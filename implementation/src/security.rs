@@ -0,0 +1,134 @@
+//! Hardening applied around the whole router: CORS, security headers, request
+//! timeouts and checks on what clients may send.
+
+use std::time::Duration;
+
+use axum::extract::{Request, State};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use http::header::{
+    AUTHORIZATION, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_SECURITY_POLICY, CONTENT_TYPE,
+    LINK, REFERRER_POLICY, RETRY_AFTER, STRICT_TRANSPORT_SECURITY, TRANSFER_ENCODING,
+    X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::set_header::SetResponseHeaderLayer;
+
+use crate::config::{LimitsConfig, SecurityConfig};
+use crate::error::ApiError;
+use crate::fixtures::Format;
+use crate::routes::{error_response, IMPORT_PATH};
+use crate::serve::TlsTerminated;
+use crate::v2::{DEPRECATION, SUNSET};
+
+/// Wraps `app` in the CORS policy, security headers and request timeout.
+pub fn harden(app: Router, security: &SecurityConfig, limits: &LimitsConfig) -> Router {
+    let timeout = Duration::from_secs(limits.request_timeout_secs);
+    let mut app = app
+        .layer(middleware::from_fn_with_state(timeout, limit_time))
+        .layer(cors(security))
+        .layer(header(X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .layer(header(X_FRAME_OPTIONS, "DENY"))
+        .layer(header(REFERRER_POLICY, "no-referrer"))
+        // The API explorer sets a policy of its own, admitting its inline script.
+        .layer(SetResponseHeaderLayer::if_not_present(
+            CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static("default-src 'none'; frame-ancestors 'none'"),
+        ));
+    if security.hsts_max_age_secs > 0 {
+        let hsts = format!("max-age={}; includeSubDomains", security.hsts_max_age_secs);
+        let hsts = HeaderValue::from_str(&hsts).expect("valid header value");
+        app = app.layer(middleware::from_fn_with_state(hsts, strict_transport));
+    }
+    app
+}
+
+/// Sends `Strict-Transport-Security` over HTTPS only: browsers ignore it on plain
+/// HTTP, where it would only advertise a policy the connection does not have.
+async fn strict_transport(State(hsts): State<HeaderValue>, request: Request, next: Next) -> Response {
+    let https = request.extensions().get::<TlsTerminated>().is_some() || forwarded_https(request.headers());
+    let mut response = next.run(request).await;
+    if https {
+        response.headers_mut().insert(STRICT_TRANSPORT_SECURITY, hsts);
+    }
+    response
+}
+
+/// Whether the proxy in front of the server received the request over HTTPS. The
+/// first `X-Forwarded-Proto` value is the one set by the proxy nearest the client.
+fn forwarded_https(headers: &HeaderMap) -> bool {
+    headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"))
+}
+
+/// Answers 503 with `Retry-After` when the response is not ready within
+/// `timeout`. Streamed bodies are not limited once their headers are sent.
+async fn limit_time(State(timeout): State<Duration>, request: Request, next: Next) -> Response {
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(elapsed) => ApiError::timeout("no response within the request timeout", elapsed).into_response(),
+    }
+}
+
+/// Refuses request bodies that are not JSON, except CSV and NDJSON sent to the
+/// bulk import, and keeps API responses, which carry
+/// personal data, out of caches.
+pub async fn check_request(request: Request, next: Next) -> Response {
+    let writes = matches!(*request.method(), Method::POST | Method::PUT | Method::PATCH);
+    if writes && has_body(&request) && !accepts_media_type(&request) {
+        return error_response(StatusCode::UNSUPPORTED_MEDIA_TYPE, "UNSUPPORTED_MEDIA_TYPE");
+    }
+    let api = request.uri().path().starts_with("/api/");
+    let mut response = next.run(request).await;
+    if api {
+        response
+            .headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    }
+    response
+}
+
+fn cors(security: &SecurityConfig) -> CorsLayer {
+    let origins = security
+        .cors_allowed_origins
+        .iter()
+        .map(|origin| HeaderValue::from_str(origin).expect("validated by Config::load"));
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers([AUTHORIZATION, CONTENT_TYPE, HeaderName::from_static("bearer")])
+        .expose_headers([RETRY_AFTER, DEPRECATION, SUNSET, LINK])
+        .max_age(Duration::from_secs(10 * 60))
+}
+
+fn header(name: HeaderName, value: &'static str) -> SetResponseHeaderLayer<HeaderValue> {
+    SetResponseHeaderLayer::overriding(name, HeaderValue::from_static(value))
+}
+
+fn has_body(request: &Request) -> bool {
+    let headers = request.headers();
+    headers.contains_key(TRANSFER_ENCODING)
+        || headers
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v != "0")
+}
+
+/// `application/json`, optionally with parameters such as `charset=utf-8`, or any
+/// import format on the import route.
+fn accepts_media_type(request: &Request) -> bool {
+    let Some(content_type) = request.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+    match Format::from_media_type(content_type) {
+        Some(Format::Json) => true,
+        Some(_) => request.uri().path().trim_end_matches('/') == IMPORT_PATH,
+        None => false,
+    }
+}
@@ -0,0 +1,140 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::ConnectInfo;
+use axum::Router;
+use http::{HeaderValue, Request};
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tower::Service;
+
+use crate::auth::CLIENT_CERT_SUBJECT;
+use crate::tls::{self, TlsReloader};
+
+/// How long the listener waits before accepting again after a failed accept.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Request extension set on requests that arrived over TLS terminated here.
+#[derive(Debug, Clone, Copy)]
+pub struct TlsTerminated;
+
+/// Connections still open when the listener closed, and how many of them
+/// outlived the drain timeout.
+pub struct Drained {
+    pub in_flight: usize,
+    pub abandoned: usize,
+}
+
+/// Accepts connections until `signal` completes, then gives the ones in flight up
+/// to `drain_timeout` to finish. With `tls` set every connection is TLS; the
+/// handshake uses whatever certificate is current when the connection is accepted.
+pub async fn run(
+    listener: TcpListener,
+    app: Router,
+    tls: Option<Arc<TlsReloader>>,
+    signal: impl Future<Output = ()>,
+    drain_timeout: Duration,
+) -> Drained {
+    let graceful = GracefulShutdown::new();
+    let open = Arc::new(AtomicUsize::new(0));
+    tokio::pin!(signal);
+    loop {
+        let (stream, remote) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Errors such as running out of file descriptors persist while
+                    // the connection waits in the backlog; retrying at once would spin.
+                    tracing::warn!("accept failed: {e}");
+                    tokio::select! {
+                        _ = tokio::time::sleep(ACCEPT_BACKOFF) => continue,
+                        _ = &mut signal => break,
+                    }
+                }
+            },
+            _ = &mut signal => break,
+        };
+        let app = app.clone();
+        let acceptor = tls.as_ref().map(|tls| tls.acceptor());
+        let watcher = graceful.watcher();
+        let guard = OpenConnection::new(&open);
+        tokio::spawn(async move {
+            let _guard = guard;
+            match acceptor {
+                None => serve_connection(stream, remote, false, None, app, watcher).await,
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => {
+                        let subject = tls::peer_subject(stream.get_ref().1);
+                        serve_connection(stream, remote, true, subject, app, watcher).await;
+                    }
+                    Err(e) => tracing::debug!(%remote, "TLS handshake failed: {e}"),
+                },
+            }
+        });
+    }
+    drop(listener);
+    let in_flight = open.load(Ordering::SeqCst);
+    tracing::info!(in_flight, "listener closed, draining connections");
+    let abandoned = match tokio::time::timeout(drain_timeout, graceful.shutdown()).await {
+        Ok(()) => 0,
+        Err(_) => open.load(Ordering::SeqCst),
+    };
+    Drained {
+        in_flight,
+        abandoned,
+    }
+}
+
+/// Counts a connection as open until dropped.
+struct OpenConnection(Arc<AtomicUsize>);
+
+impl OpenConnection {
+    fn new(open: &Arc<AtomicUsize>) -> Self {
+        open.fetch_add(1, Ordering::SeqCst);
+        OpenConnection(open.clone())
+    }
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+async fn serve_connection<S>(
+    stream: S,
+    remote: SocketAddr,
+    tls: bool,
+    subject: Option<String>,
+    app: Router,
+    watcher: hyper_util::server::graceful::Watcher,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let subject = subject.and_then(|s| HeaderValue::from_str(&s).ok());
+    let service = hyper::service::service_fn(move |mut request: Request<Incoming>| {
+        // Only the listener may vouch for a client certificate.
+        let headers = request.headers_mut();
+        headers.remove(CLIENT_CERT_SUBJECT);
+        if let Some(subject) = &subject {
+            headers.insert(CLIENT_CERT_SUBJECT, subject.clone());
+        }
+        request.extensions_mut().insert(ConnectInfo(remote));
+        if tls {
+            request.extensions_mut().insert(TlsTerminated);
+        }
+        app.clone().call(request)
+    });
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+    if let Err(e) = watcher.watch(connection.into_owned()).await {
+        tracing::debug!(%remote, "connection error: {e}");
+    }
+}
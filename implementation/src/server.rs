@@ -0,0 +1,771 @@
+use async_trait::async_trait;
+use axum::extract::Host;
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::CookieJar;
+use http::Method;
+use openapi::apis::users::{
+    CreateUserResponse, DeleteUserResponse, GetAllUsersResponse, GetUserByIdResponse,
+    UpdateUserResponse,
+};
+use openapi::apis::ApiKeyAuthHeader;
+use openapi::models::{
+    CreateRequest, DeleteUserPathParams, Error, GetAllUsersQueryParams, GetUserByIdPathParams,
+    RequestHeader, ResponseHeader, UpdateRequest, UpdateUserPathParams, User, UserListResponse,
+    UserResponse,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{watch, RwLock, RwLockReadGuard};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::audit::{AuditAction, AuditEntry, AuditLog};
+use crate::auth::{Authenticator, Claims, Identity, Scope};
+use crate::batch::{self, BatchMode, BatchOperation, BatchRequest, OperationResult};
+use crate::duplicates::{self, Candidate, MergeRequest};
+use crate::erasure::{ErasureOutcome, ErasureReceipt, ErasureReceiptPayload, ERASED_FIELDS};
+use crate::error::ApiError;
+use crate::feed::mask;
+use crate::history::Revision;
+use crate::import::{self, ImportOptions, ImportReport, ImportRow};
+use crate::keys::KeyMaterial;
+use crate::metrics;
+use crate::search::{self, SearchHit, SearchRequest};
+use crate::store::{Batch, StoreError, Tombstone, UserStore};
+use crate::v2::Profile;
+
+pub struct ServerImpl {
+    // database: sea_orm::DbConn,
+    users: Arc<RwLock<UserStore>>,
+    audit: Arc<RwLock<AuditLog>>,
+    auth: Authenticator,
+    keys: KeyMaterial,
+    /// Change feed position each outbox sink has taken.
+    sinks: Mutex<HashMap<&'static str, u64>>,
+}
+
+impl ServerImpl {
+    pub fn new(auth: Authenticator, keys: KeyMaterial, users: UserStore, audit: AuditLog) -> Self {
+        ServerImpl {
+            users: Arc::new(RwLock::new(users)),
+            audit: Arc::new(RwLock::new(audit)),
+            auth,
+            keys,
+            sinks: Default::default(),
+        }
+    }
+
+    /// Whether the store answers within `timeout` and can still persist writes.
+    pub async fn check_storage(&self, timeout: std::time::Duration) -> Result<(), String> {
+        self.read_users_within(timeout).await.map(drop)
+    }
+
+    /// Checks that the journal can still be written. Returns the number of entries
+    /// replayed from it, or None without a journal.
+    pub async fn check_journal(&self, timeout: std::time::Duration) -> Result<Option<usize>, String> {
+        let users = self.read_users_within(timeout).await?;
+        users.check().map_err(|e| e.to_string())?;
+        Ok(users.replayed())
+    }
+
+    /// The id of the receipt signing key, or the settings of the keys that
+    /// only live as long as the process.
+    pub fn check_keys(&self) -> Result<&str, &[&'static str]> {
+        match self.keys.ephemeral() {
+            [] => Ok(self.keys.key_id()),
+            ephemeral => Err(ephemeral),
+        }
+    }
+
+    async fn read_users_within(
+        &self,
+        timeout: std::time::Duration,
+    ) -> Result<RwLockReadGuard<'_, UserStore>, String> {
+        tokio::time::timeout(timeout, self.users.read())
+            .await
+            .map_err(|_| format!("store not available within {}ms", timeout.as_millis()))
+    }
+
+    /// Waits for the write in progress, if any, then syncs the store to disk.
+    pub async fn flush(&self) -> Result<(), StoreError> {
+        self.users.write().await.flush()
+    }
+
+    /// Live and soft-deleted users.
+    pub async fn user_counts(&self) -> (usize, usize) {
+        self.users.read().await.counts()
+    }
+
+    pub fn identify(&self, headers: &http::HeaderMap) -> Identity {
+        self.auth.identify(headers, "Bearer")
+    }
+
+    /// Irreversibly removes the user's personal data, keeping only a tombstone and
+    /// the pseudonymous audit trail.
+    pub async fn erase_user(&self, claims: &Claims, id: Uuid) -> Result<ErasureOutcome, ApiError> {
+        let mut users = self.users.write().await;
+        if users.tombstone(&id).is_some() {
+            return Ok(ErasureOutcome::AlreadyErased);
+        }
+        if users.record(&id).is_none() {
+            return Ok(ErasureOutcome::NotFound);
+        }
+
+        let subject_ref = self.keys.pseudonym(&id);
+        let tombstone = Tombstone {
+            erased_at: chrono::Utc::now(),
+            receipt_id: Uuid::new_v4(),
+        };
+        users.erase(id, tombstone.clone(), &claims.subject)?;
+
+        let mut audit = self.audit.write().await;
+        audit.record(&claims.subject, AuditAction::Erased, subject_ref.clone());
+
+        let payload = ErasureReceiptPayload {
+            receipt_id: tombstone.receipt_id,
+            user_id: id,
+            subject_ref: subject_ref.clone(),
+            erased_at: tombstone.erased_at,
+            actor: claims.subject.clone(),
+            erased_fields: ERASED_FIELDS.iter().map(|f| f.to_string()).collect(),
+            audit_records_retained: audit.count_for(&subject_ref),
+            key_id: self.keys.key_id().into(),
+            algorithm: "HMAC-SHA256".into(),
+        };
+        Ok(ErasureOutcome::Erased(ErasureReceipt::sign(payload, &self.keys)))
+    }
+
+    /// Brings back a soft-deleted user.
+    pub async fn restore_user(&self, claims: &Claims, id: Uuid) -> Result<RestoreOutcome, ApiError> {
+        let mut users = self.users.write().await;
+        let outcome = match users.record(&id) {
+            None => return Ok(RestoreOutcome::NotFound),
+            Some(record) if record.deleted_at.is_none() => return Ok(RestoreOutcome::NotDeleted),
+            Some(_) => {
+                let user = users.restore(&id, &claims.subject)?;
+                RestoreOutcome::Restored(user.cloned().expect("user was soft-deleted"))
+            }
+        };
+        drop(users);
+        self.audit(&claims.subject, AuditAction::Restored, &id).await;
+        Ok(outcome)
+    }
+
+    /// Imports users in one store write, or none at all when the mode and the
+    /// rejected rows say so.
+    pub async fn import_users(
+        &self,
+        actor: &str,
+        rows: Vec<ImportRow>,
+        options: ImportOptions,
+    ) -> Result<ImportReport, ApiError> {
+        let mut users = self.users.write().await;
+        let (mut report, accepted) = import::review(&users, rows, options);
+        if !report.should_commit() {
+            return Ok(report);
+        }
+        let ids: Vec<Uuid> = accepted.iter().map(|(id, _)| *id).collect();
+        users.insert_all(accepted, actor)?;
+        drop(users);
+        report.committed = true;
+        for id in &ids {
+            self.audit(actor, AuditAction::Created, id).await;
+        }
+        Ok(report)
+    }
+
+    /// Runs the operations in order. A transactional batch is written in one store
+    /// write if every operation succeeds and not at all otherwise; an independent
+    /// one writes each operation that succeeds as it goes.
+    pub async fn apply_batch(
+        &self,
+        claims: &Claims,
+        request: BatchRequest,
+    ) -> Result<(bool, Vec<OperationResult>), ApiError> {
+        let actor = claims.subject.as_str();
+        let mut users = self.users.write().await;
+        let mut results = Vec::with_capacity(request.operations.len());
+        let mut applied = Vec::new();
+        let mut batch = users.batch();
+        for (index, operation) in request.operations.into_iter().enumerate() {
+            let (result, change) = match operation {
+                BatchOperation::Create(body) => {
+                    let request_id = body.request_header.request_id;
+                    let (response, created) = stage_create(&mut batch, actor, body.request_header, body.user);
+                    let result = batch::create_result(index, request_id, response);
+                    (result, created.map(|id| (AuditAction::Created, id)))
+                }
+                BatchOperation::Update { id, request } => {
+                    let request_id = request.request_header.request_id;
+                    let response = stage_update(&mut batch, actor, id, request.user, request.request_header);
+                    let result = batch::update_result(index, request_id, response);
+                    let change = result.succeeded().then_some((AuditAction::Updated, id));
+                    (result, change)
+                }
+                BatchOperation::Delete { id, request_header } => {
+                    let response = stage_delete(&mut batch, actor, id);
+                    let result = batch::delete_result(index, request_header.request_id, response);
+                    let change = result.succeeded().then_some((AuditAction::Deleted, id));
+                    (result, change)
+                }
+            };
+            results.push(result);
+            if request.mode == BatchMode::Independent {
+                // Failed operations stage nothing, so every operation can be
+                // committed on its own.
+                let changes = batch.into_changes();
+                users.commit_batch(changes, "batch")?;
+                batch = users.batch();
+            }
+            applied.extend(change);
+        }
+
+        let committed = match request.mode {
+            BatchMode::Independent => true,
+            BatchMode::Transactional if results.iter().all(OperationResult::succeeded) => {
+                let changes = batch.into_changes();
+                users.commit_batch(changes, "batch")?;
+                true
+            }
+            BatchMode::Transactional => {
+                for result in results.iter_mut().filter(|r| r.succeeded()) {
+                    result.abort();
+                }
+                applied.clear();
+                false
+            }
+        };
+        drop(users);
+        for (action, id) in applied {
+            self.audit(actor, action, &id).await;
+        }
+        Ok((committed, results))
+    }
+
+    /// Permanently removes users that have been soft-deleted for longer than
+    /// `retention` and whose changes every sink has taken.
+    pub async fn purge_deleted(&self, retention: chrono::Duration) -> Result<usize, StoreError> {
+        let delivered = self.sinks.lock().unwrap().values().min().copied();
+        let purged = self
+            .users
+            .write()
+            .await
+            .purge_deleted_before(chrono::Utc::now() - retention, delivered)?;
+        for id in &purged {
+            self.audit("system", AuditAction::Purged, id).await;
+        }
+        Ok(purged.len())
+    }
+
+    /// Up to `limit` users with ids after `after`, in id order, with their ids.
+    pub async fn users_page(
+        &self,
+        after: Option<Uuid>,
+        limit: usize,
+        include_deleted: bool,
+    ) -> Vec<(Uuid, User, Profile)> {
+        let _timer = metrics::store_timer("list");
+        self.users.read().await.page(after, limit, include_deleted)
+    }
+
+    /// Records that `sink` has taken the change feed up to `position`.
+    pub fn sink_taken(&self, sink: &'static str, position: u64) {
+        self.sinks.lock().unwrap().insert(sink, position);
+    }
+
+    /// Latest change feed position, and a receiver woken up when it moves.
+    pub async fn subscribe_changes(&self) -> (u64, watch::Receiver<u64>) {
+        let users = self.users.read().await;
+        (users.sequence(), users.subscribe())
+    }
+
+    /// Up to `limit` changes after feed position `after`, with their user ids.
+    pub async fn changes_after(&self, after: u64, limit: usize) -> Vec<(Uuid, Revision)> {
+        let _timer = metrics::store_timer("changes_after");
+        self.users.read().await.changes_after(after, limit)
+    }
+
+    /// The change at feed position `sequence`, unless its user has been purged.
+    pub async fn change(&self, sequence: u64) -> Option<(Uuid, Revision)> {
+        self.users.read().await.change(sequence)
+    }
+
+    /// Live users matching the criteria, best first, masked as in the change
+    /// feed unless `unmasked`.
+    pub async fn search_users(&self, request: &SearchRequest, unmasked: bool) -> Vec<SearchHit> {
+        let _timer = metrics::store_timer("search");
+        let mut hits: Vec<SearchHit> = self
+            .users
+            .read()
+            .await
+            .list(false)
+            .filter_map(|user| {
+                let score = request.score(user)?;
+                Some(SearchHit {
+                    score,
+                    user: user.clone(),
+                })
+            })
+            .collect();
+        search::rank(&mut hits);
+        hits.truncate(request.limit.unwrap_or(search::DEFAULT_LIMIT).min(search::MAX_LIMIT));
+        if !unmasked {
+            for hit in &mut hits {
+                hit.user = mask(hit.user.clone());
+            }
+        }
+        hits
+    }
+
+    /// Pairs of live users that may be the same person, best first.
+    pub async fn duplicate_candidates(&self, min_score: f64, limit: usize) -> Vec<Candidate> {
+        let mut candidates = duplicates::candidates(self.users.read().await.list(false), min_score);
+        candidates.truncate(limit);
+        candidates
+    }
+
+    /// Merges `request.retired_id` into `survivor`. Both must be live users.
+    pub async fn merge_users(
+        &self,
+        claims: &Claims,
+        survivor: Uuid,
+        request: MergeRequest,
+    ) -> Result<MergeOutcome, ApiError> {
+        let retired = request.retired_id;
+        if survivor == retired {
+            return Ok(MergeOutcome::SameUser);
+        }
+        if let Some(user) = &request.user {
+            if let Err(e) = user.validate() {
+                metrics::validation_failures(&e);
+                return Ok(MergeOutcome::Invalid(e));
+            }
+        }
+        let mut users = self.users.write().await;
+        let (Some(kept), Some(gone)) = (users.get(&survivor), users.get(&retired)) else {
+            return Ok(MergeOutcome::NotFound);
+        };
+        let mut user = match request.user {
+            Some(user) => user,
+            None => duplicates::combine(kept, gone),
+        };
+        user.id = Some(survivor);
+        users.merge(survivor, retired, user.clone(), &claims.subject)?;
+        drop(users);
+        self.audit(&claims.subject, AuditAction::Merged, &survivor).await;
+        self.audit(&claims.subject, AuditAction::Merged, &retired).await;
+        Ok(MergeOutcome::Merged(user))
+    }
+
+    /// The id a merged-away user now lives under.
+    pub async fn merged_into(&self, id: &Uuid) -> Option<Uuid> {
+        self.users.read().await.merged_into(id)
+    }
+
+    /// All revisions of a user, including deleted and erased ones.
+    pub async fn user_revisions(&self, id: Uuid) -> Option<Vec<Revision>> {
+        let users = self.users.read().await;
+        users.history(&id).map(|h| h.revisions().to_vec())
+    }
+
+    pub async fn user_revision(&self, id: Uuid, revision: u64) -> Option<Revision> {
+        let users = self.users.read().await;
+        users.history(&id)?.get(revision).cloned()
+    }
+
+    /// The users collection as it was at `at`.
+    pub async fn users_as_of(&self, at: chrono::DateTime<chrono::Utc>) -> Vec<User> {
+        self.users.read().await.as_of(at)
+    }
+
+    /// Users with what only API v2 carries about them.
+    pub async fn users_with_profiles(&self, include_deleted: bool) -> Vec<(User, Profile)> {
+        let _timer = metrics::store_timer("list");
+        self.users
+            .read()
+            .await
+            .records(include_deleted)
+            .map(|r| (r.user.clone(), r.profile.clone()))
+            .collect()
+    }
+
+    /// The live user with its v2 profile.
+    pub async fn user_with_profile(&self, id: &Uuid) -> Option<(User, Profile)> {
+        let _timer = metrics::store_timer("get");
+        let users = self.users.read().await;
+        let record = users.record(id).filter(|r| r.deleted_at.is_none())?;
+        Some((record.user.clone(), record.profile.clone()))
+    }
+
+    /// Creates a user written through API v2, as its v1 data and v2 profile.
+    pub async fn create_user_with_profile(
+        &self,
+        claims: &Claims,
+        mut user: User,
+        profile: Profile,
+    ) -> Result<ProfileOutcome, ApiError> {
+        if let Err(e) = user.validate() {
+            metrics::validation_failures(&e);
+            return Ok(ProfileOutcome::Invalid(e));
+        }
+        let id = Uuid::new_v4();
+        user.id = Some(id);
+        let mut users = self.users.write().await;
+        let mut batch = users.batch();
+        if batch
+            .insert_with_profile(id, user.clone(), profile.clone(), &claims.subject)
+            .is_err()
+        {
+            return Ok(ProfileOutcome::Erased);
+        }
+        let changes = batch.into_changes();
+        users.commit_batch(changes, "insert")?;
+        drop(users);
+        self.audit(&claims.subject, AuditAction::Created, &id).await;
+        Ok(ProfileOutcome::Saved(user, profile))
+    }
+
+    /// Replaces a live user written through API v2.
+    pub async fn update_user_with_profile(
+        &self,
+        claims: &Claims,
+        id: Uuid,
+        mut user: User,
+        profile: Profile,
+    ) -> Result<ProfileOutcome, ApiError> {
+        user.id = Some(id);
+        if let Err(e) = user.validate() {
+            metrics::validation_failures(&e);
+            return Ok(ProfileOutcome::Invalid(e));
+        }
+        let mut users = self.users.write().await;
+        let mut batch = users.batch();
+        // Erased and merged-away users are not live either.
+        if batch.get(&id).is_none()
+            || batch
+                .insert_with_profile(id, user.clone(), profile.clone(), &claims.subject)
+                .is_err()
+        {
+            return Ok(ProfileOutcome::NotFound);
+        }
+        let changes = batch.into_changes();
+        users.commit_batch(changes, "insert")?;
+        drop(users);
+        self.audit(&claims.subject, AuditAction::Updated, &id).await;
+        Ok(ProfileOutcome::Saved(user, profile))
+    }
+
+    /// Soft-deletes a live user as DeleteUser does. Returns false if there is none.
+    pub async fn delete_user_as(&self, claims: &Claims, id: Uuid) -> Result<bool, ApiError> {
+        let deleted = self.users.write().await.soft_delete(&id, &claims.subject)?;
+        if deleted {
+            self.audit(&claims.subject, AuditAction::Deleted, &id).await;
+        }
+        Ok(deleted)
+    }
+
+    pub async fn audit_entries(&self, after: u64, limit: usize) -> Vec<AuditEntry> {
+        self.audit.read().await.entries_after(after, limit).to_vec()
+    }
+
+    async fn audit(&self, actor: &str, action: AuditAction, id: &Uuid) {
+        self.audit
+            .write()
+            .await
+            .record(actor, action, self.keys.pseudonym(id));
+    }
+}
+
+pub enum MergeOutcome {
+    Merged(User),
+    SameUser,
+    Invalid(validator::ValidationErrors),
+    NotFound,
+}
+
+pub enum ProfileOutcome {
+    Saved(User, Profile),
+    Invalid(validator::ValidationErrors),
+    NotFound,
+    Erased,
+}
+
+pub enum RestoreOutcome {
+    Restored(User),
+    NotDeleted,
+    NotFound,
+}
+
+pub(crate) fn build_response_header() -> ResponseHeader {
+    ResponseHeader {
+        request_id: Uuid::new_v4(),
+        send_date: chrono::Utc::now(),
+    }
+}
+
+pub(crate) fn build_request_header() -> RequestHeader {
+    RequestHeader {
+        request_id: Uuid::new_v4(),
+        send_date: chrono::Utc::now(),
+    }
+}
+
+fn insufficient_scope() -> Error {
+    Error::new(build_response_header(), "INSUFFICIENT_SCOPE".into())
+}
+
+#[allow(unused_variables)]
+#[async_trait]
+impl openapi::apis::users::Users for ServerImpl {
+    type Claims = Claims;
+    type Error = ApiError;
+
+    async fn handle_error(&self, error: ApiError) -> Response {
+        error.into_response()
+    }
+
+    async fn create_user(
+        &self,
+        method: Method,
+        host: Host,
+        cookies: CookieJar,
+        claims: Self::Claims,
+        mut body: CreateRequest,
+    ) -> Result<CreateUserResponse, ApiError> {
+        if !claims.has(Scope::Write) {
+            return Ok(CreateUserResponse::Status401_Unauthorized(insufficient_scope()));
+        }
+        let val = body.user.validate();
+        if let Err(e) = val {
+            metrics::validation_failures(&e);
+            return Ok(CreateUserResponse::Status400_BadRequest(Error::new(
+                build_response_header(),
+                e.to_string(),
+            )));
+        };
+        let uuid = Uuid::new_v4();
+        body.user.id = Some(uuid);
+        match self.users.write().await.insert(uuid, body.user.clone(), &claims.subject) {
+            Ok(_) => {}
+            Err(StoreError::Erased) => {
+                return Ok(CreateUserResponse::Status422_UnprocessableEntity(Error::new(
+                    build_response_header(),
+                    "USER_ERASED".into(),
+                )));
+            }
+            Err(e) => return Err(e.into()),
+        }
+        self.audit(&claims.subject, AuditAction::Created, &uuid).await;
+        Ok(CreateUserResponse::Status201_UserCreatedSuccessfully(
+            UserResponse {
+                response_header: body.request_header,
+                user: body.user,
+            },
+        ))
+    }
+
+    async fn delete_user(
+        &self,
+        method: Method,
+        host: Host,
+        cookies: CookieJar,
+        claims: Self::Claims,
+        path_params: DeleteUserPathParams,
+    ) -> Result<DeleteUserResponse, ApiError> {
+        if !claims.has(Scope::Write) {
+            return Ok(DeleteUserResponse::Status401_Unauthorized(insufficient_scope()));
+        }
+        if self.delete_user_as(&claims, path_params.id).await? {
+            Ok(DeleteUserResponse::Status204_NoContent)
+        } else {
+            Ok(DeleteUserResponse::Status404_UserNotFound(Error::new(
+                build_response_header(),
+                "404".into(),
+            )))
+        }
+    }
+
+    async fn get_all_users(
+        &self,
+        method: Method,
+        host: Host,
+        cookies: CookieJar,
+        claims: Self::Claims,
+        query_params: GetAllUsersQueryParams,
+    ) -> Result<GetAllUsersResponse, ApiError> {
+        let include_deleted = query_params.include_deleted.unwrap_or(false);
+        // Deleted users are kept for restoring and erasure, not for browsing.
+        if !claims.has(Scope::Read) || (include_deleted && !claims.has(Scope::Admin) && !claims.has(Scope::Pii)) {
+            return Ok(GetAllUsersResponse::Status401_Unauthorized(insufficient_scope()));
+        }
+        let unmasked = claims.has(Scope::Pii);
+        let _timer = metrics::store_timer("list");
+        Ok(GetAllUsersResponse::Status200_Success(UserListResponse {
+            response_header: build_request_header(),
+            users_list: self
+                .users
+                .read()
+                .await
+                .list(include_deleted)
+                .map(|user| if unmasked { user.clone() } else { mask(user.clone()) })
+                .collect(),
+        }))
+    }
+
+    async fn get_user_by_id(
+        &self,
+        method: Method,
+        host: Host,
+        cookies: CookieJar,
+        claims: Self::Claims,
+        path_params: GetUserByIdPathParams,
+    ) -> Result<GetUserByIdResponse, ApiError> {
+        if !claims.has(Scope::Read) {
+            return Ok(GetUserByIdResponse::Status401_Unauthorized(insufficient_scope()));
+        }
+        let _timer = metrics::store_timer("get");
+        match self.users.read().await.get(&path_params.id) {
+            None => Ok(GetUserByIdResponse::Status404_UserNotFound(Error::new(
+                build_response_header(),
+                "404".into(),
+            ))),
+            Some(user) => Ok(GetUserByIdResponse::Status200_Success(UserResponse {
+                response_header: build_request_header(),
+                user: if claims.has(Scope::Pii) { user.clone() } else { mask(user.clone()) },
+            })),
+        }
+    }
+
+    async fn update_user(
+        &self,
+        method: Method,
+        host: Host,
+        cookies: CookieJar,
+        claims: Self::Claims,
+        path_params: UpdateUserPathParams,
+        mut body: UpdateRequest,
+    ) -> Result<UpdateUserResponse, ApiError> {
+        if !claims.has(Scope::Write) {
+            return Ok(UpdateUserResponse::Status401_Unauthorized(insufficient_scope()));
+        }
+        let val = body.user.validate();
+        body.user.id = Some(path_params.id);
+        if let Err(e) = val {
+            metrics::validation_failures(&e);
+            return Ok(UpdateUserResponse::Status400_BadRequest(Error::new(
+                build_response_header(),
+                e.to_string(),
+            )));
+        };
+        let mut collection = self.users.write().await;
+        match collection.get(&path_params.id) {
+            None => Ok(UpdateUserResponse::Status404_UserNotFound(Error::new(
+                build_response_header(),
+                "404".into(),
+            ))),
+            Some(_) => {
+                collection.insert(path_params.id, body.user.clone(), &claims.subject)?;
+                drop(collection);
+                self.audit(&claims.subject, AuditAction::Updated, &path_params.id).await;
+                Ok(UpdateUserResponse::Status200_Success(UserResponse {
+                    response_header: build_request_header(),
+                    user: body.user.clone(),
+                }))
+            }
+        }
+    }
+}
+
+/// Validates and stages a new user. Answers as CreateUser does, with the id of
+/// the staged user.
+fn stage_create(
+    batch: &mut Batch,
+    actor: &str,
+    request_header: RequestHeader,
+    mut user: User,
+) -> (CreateUserResponse, Option<Uuid>) {
+    if let Err(e) = user.validate() {
+        metrics::validation_failures(&e);
+        let error = Error::new(build_response_header(), e.to_string());
+        return (CreateUserResponse::Status400_BadRequest(error), None);
+    }
+    let id = Uuid::new_v4();
+    user.id = Some(id);
+    match batch.insert(id, user.clone(), actor) {
+        Ok(_) => (
+            CreateUserResponse::Status201_UserCreatedSuccessfully(UserResponse {
+                response_header: request_header,
+                user,
+            }),
+            Some(id),
+        ),
+        Err(_) => (
+            CreateUserResponse::Status422_UnprocessableEntity(Error::new(
+                build_response_header(),
+                "USER_ERASED".into(),
+            )),
+            None,
+        ),
+    }
+}
+
+/// Validates and stages new data for a live user. Answers as UpdateUser does.
+fn stage_update(
+    batch: &mut Batch,
+    actor: &str,
+    id: Uuid,
+    mut user: User,
+    response_header: RequestHeader,
+) -> UpdateUserResponse {
+    let val = user.validate();
+    user.id = Some(id);
+    if let Err(e) = val {
+        metrics::validation_failures(&e);
+        return UpdateUserResponse::Status400_BadRequest(Error::new(
+            build_response_header(),
+            e.to_string(),
+        ));
+    }
+    if batch.get(&id).is_none() {
+        return UpdateUserResponse::Status404_UserNotFound(Error::new(
+            build_response_header(),
+            "404".into(),
+        ));
+    }
+    if batch.insert(id, user.clone(), actor).is_err() {
+        return UpdateUserResponse::Status404_UserNotFound(Error::new(
+            build_response_header(),
+            "404".into(),
+        ));
+    }
+    UpdateUserResponse::Status200_Success(UserResponse {
+        response_header,
+        user,
+    })
+}
+
+/// Stages the soft deletion of a live user. Answers as DeleteUser does.
+fn stage_delete(batch: &mut Batch, actor: &str, id: Uuid) -> DeleteUserResponse {
+    if batch.soft_delete(&id, actor) {
+        DeleteUserResponse::Status204_NoContent
+    } else {
+        DeleteUserResponse::Status404_UserNotFound(Error::new(build_response_header(), "404".into()))
+    }
+}
+
+#[async_trait]
+impl ApiKeyAuthHeader for ServerImpl {
+    type Claims = Claims;
+
+    async fn extract_claims_from_header(
+        &self,
+        headers: &axum::http::header::HeaderMap,
+        key: &str,
+    ) -> Option<Self::Claims> {
+        let claims = self.auth.authenticate(headers, key);
+        if claims.is_none() {
+            metrics::auth_failure();
+        }
+        claims
+    }
+}
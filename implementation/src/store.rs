@@ -0,0 +1,755 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Bound;
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use openapi::models::User;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+use crate::config::StorageConfig;
+use crate::history::{History, Revision, RevisionKind};
+use crate::journal::Journal;
+use crate::metrics;
+use crate::v2::Profile;
+
+/// What is left of an erased user. Its presence blocks any new user with the same id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tombstone {
+    pub erased_at: DateTime<Utc>,
+    pub receipt_id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRecord {
+    pub user: User,
+    /// Set while the user is soft-deleted.
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// What only API v2 carries about the user.
+    #[serde(default, skip_serializing_if = "Profile::is_empty")]
+    pub profile: Profile,
+}
+
+/// Everything the store holds about one id.
+#[derive(Debug, Clone)]
+struct Entry {
+    id: Uuid,
+    record: Option<UserRecord>,
+    history: History,
+    tombstone: Option<Tombstone>,
+    /// Set on an id whose user was merged into another one.
+    merged_into: Option<Uuid>,
+}
+
+impl Entry {
+    /// True once nothing is left of the id, as after a purge.
+    fn is_empty(&self) -> bool {
+        self.record.is_none()
+            && self.history.revisions().is_empty()
+            && self.tombstone.is_none()
+            && self.merged_into.is_none()
+    }
+}
+
+/// A line of the journal: the entries of one commit, so that a commit cut
+/// short by a crash is dropped as a whole. Compaction and older versions wrote
+/// one entry per line.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Line {
+    Commit(Vec<Written>),
+    Entry(Box<Written>),
+}
+
+/// An entry as written to the journal. Commits write only the revisions they
+/// add to the history; compaction writes the whole history.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Written {
+    id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    record: Option<UserRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    history: Option<History>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    added: Vec<Revision>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tombstone: Option<Tombstone>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    merged_into: Option<Uuid>,
+}
+
+impl Written {
+    /// The entry with the revisions after the first `known` ones.
+    fn delta(entry: &Entry, known: usize) -> Self {
+        Written {
+            id: entry.id,
+            record: entry.record.clone(),
+            history: None,
+            added: entry.history.revisions()[known.min(entry.history.revisions().len())..].to_vec(),
+            tombstone: entry.tombstone.clone(),
+            merged_into: entry.merged_into,
+        }
+    }
+
+    fn full(entry: Entry) -> Self {
+        Written {
+            id: entry.id,
+            record: entry.record,
+            history: Some(entry.history),
+            added: Vec::new(),
+            tombstone: entry.tombstone,
+            merged_into: entry.merged_into,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Erased,
+    Exists,
+    Io(std::io::Error),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Erased => f.write_str("user has been erased"),
+            StoreError::Exists => f.write_str("user already exists"),
+            StoreError::Io(e) => write!(f, "journal: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<std::io::Error> for StoreError {
+    fn from(e: std::io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+#[derive(Default)]
+pub struct UserStore {
+    /// Ordered by id so that exports can resume after the last id they sent.
+    users: BTreeMap<Uuid, UserRecord>,
+    tombstones: HashMap<Uuid, Tombstone>,
+    history: HashMap<Uuid, History>,
+    /// Retired id to the id it was merged into.
+    merges: HashMap<Uuid, Uuid>,
+    /// Change feed position to the user and revision number it refers to.
+    feed: BTreeMap<u64, (Uuid, u64)>,
+    /// Latest change feed position handed out.
+    sequence: u64,
+    /// Carries `sequence` to feed subscribers after every commit.
+    changes: watch::Sender<u64>,
+    journal: Option<Journal>,
+    /// Journal entries replayed at startup; None without a journal.
+    replayed: Option<usize>,
+}
+
+impl UserStore {
+    /// Opens the configured backend, replaying the journal if there is one.
+    pub fn open(config: &StorageConfig) -> Result<Self, StoreError> {
+        let mut store = UserStore::default();
+        if let Some(path) = config.journal_path() {
+            let _timer = metrics::store_timer("replay");
+            let (journal, lines) = Journal::open::<Line>(path)?;
+            store.replayed = Some(lines.len());
+            for line in lines {
+                let written = match line {
+                    Line::Commit(entries) => entries,
+                    Line::Entry(entry) => vec![*entry],
+                };
+                for written in written {
+                    let entry = store.replayed_entry(written);
+                    store.apply(entry);
+                }
+            }
+            store.journal = Some(journal);
+        }
+        store.changes.send_replace(store.sequence);
+        Ok(store)
+    }
+
+    /// True when the store has never held a user.
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty() && self.tombstones.is_empty() && self.history.is_empty()
+    }
+
+    /// Fails if the backing journal can no longer be written.
+    pub fn check(&self) -> Result<(), StoreError> {
+        if let Some(journal) = &self.journal {
+            journal.check()?;
+        }
+        Ok(())
+    }
+
+    /// Syncs the journal to disk, if there is one.
+    pub fn flush(&self) -> Result<(), StoreError> {
+        if let Some(journal) = &self.journal {
+            journal.sync()?;
+        }
+        Ok(())
+    }
+
+    pub fn replayed(&self) -> Option<usize> {
+        self.replayed
+    }
+
+    /// Returns the user unless it is soft-deleted.
+    pub fn get(&self, id: &Uuid) -> Option<&User> {
+        self.users
+            .get(id)
+            .filter(|r| r.deleted_at.is_none())
+            .map(|r| &r.user)
+    }
+
+    /// Returns the record whether or not it is soft-deleted.
+    pub fn record(&self, id: &Uuid) -> Option<&UserRecord> {
+        self.users.get(id)
+    }
+
+    /// Live and soft-deleted users.
+    pub fn counts(&self) -> (usize, usize) {
+        let deleted = self.users.values().filter(|r| r.deleted_at.is_some()).count();
+        (self.users.len() - deleted, deleted)
+    }
+
+    pub fn list(&self, include_deleted: bool) -> impl Iterator<Item = &User> {
+        self.users
+            .values()
+            .filter(move |r| include_deleted || r.deleted_at.is_none())
+            .map(|r| &r.user)
+    }
+
+    pub fn records(&self, include_deleted: bool) -> impl Iterator<Item = &UserRecord> {
+        self.users
+            .values()
+            .filter(move |r| include_deleted || r.deleted_at.is_none())
+    }
+
+    /// Up to `limit` users with ids after `after`, in id order, with their v2 profiles.
+    pub fn page(&self, after: Option<Uuid>, limit: usize, include_deleted: bool) -> Vec<(Uuid, User, Profile)> {
+        let start = match after {
+            Some(id) => Bound::Excluded(id),
+            None => Bound::Unbounded,
+        };
+        self.users
+            .range((start, Bound::Unbounded))
+            .filter(|(_, r)| include_deleted || r.deleted_at.is_none())
+            .take(limit)
+            .map(|(id, r)| (*id, r.user.clone(), r.profile.clone()))
+            .collect()
+    }
+
+    /// Inserts or replaces a user. Ids of erased users cannot be reused.
+    pub fn insert(&mut self, id: Uuid, user: User, actor: &str) -> Result<Option<User>, StoreError> {
+        let mut batch = self.batch();
+        let previous = batch.insert(id, user, actor)?;
+        let changes = batch.into_changes();
+        self.commit_batch(changes, "insert")?;
+        Ok(previous)
+    }
+
+    /// Hides the user until it is restored. Returns false if there is no live user with this id.
+    pub fn soft_delete(&mut self, id: &Uuid, actor: &str) -> Result<bool, StoreError> {
+        let mut batch = self.batch();
+        let deleted = batch.soft_delete(id, actor);
+        let changes = batch.into_changes();
+        self.commit_batch(changes, "soft_delete")?;
+        Ok(deleted)
+    }
+
+    /// Inserts new users in one journal write. Fails without inserting anything if
+    /// any id is already in use or belongs to an erased user.
+    pub fn insert_all(&mut self, users: Vec<(Uuid, User)>, actor: &str) -> Result<(), StoreError> {
+        let _timer = metrics::store_timer("insert_all");
+        let mut entries = Vec::with_capacity(users.len());
+        let mut ids = HashSet::with_capacity(users.len());
+        for (id, user) in users {
+            if self.tombstones.contains_key(&id) {
+                return Err(StoreError::Erased);
+            }
+            if self.users.contains_key(&id) || self.merges.contains_key(&id) || !ids.insert(id) {
+                return Err(StoreError::Exists);
+            }
+            let mut entry = self.entry(&id);
+            entry.history.push(actor, RevisionKind::Created, Some(user.clone()));
+            entry.record = Some(UserRecord {
+                user,
+                deleted_at: None,
+                profile: Profile::default(),
+            });
+            entries.push(entry);
+        }
+        self.commit(entries)
+    }
+
+    /// Starts staging changes that are written together by `commit_batch`.
+    pub fn batch(&self) -> Batch<'_> {
+        Batch {
+            store: self,
+            staged: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+
+    /// Writes staged changes in one journal append, timed as `operation`.
+    pub fn commit_batch(&mut self, changes: Changes, operation: &str) -> Result<(), StoreError> {
+        let _timer = metrics::store_timer(operation);
+        if changes.0.is_empty() {
+            return Ok(());
+        }
+        self.commit(changes.0)
+    }
+
+    /// Clears the deletion mark. Returns None if the user is not soft-deleted.
+    pub fn restore(&mut self, id: &Uuid, actor: &str) -> Result<Option<&User>, StoreError> {
+        let _timer = metrics::store_timer("restore");
+        let mut entry = self.entry(id);
+        let Some(record) = entry.record.as_mut().filter(|r| r.deleted_at.is_some()) else {
+            return Ok(None);
+        };
+        record.deleted_at = None;
+        let (user, profile) = (record.user.clone(), record.profile.clone());
+        entry.history.push(actor, RevisionKind::Restored, Some(user));
+        entry.history.set_profile(&profile);
+        self.commit(vec![entry])?;
+        Ok(self.get(id))
+    }
+
+    /// Permanently removes users that were soft-deleted before `cutoff`, together
+    /// with their history. Their changes leave the feed with them, so a user with
+    /// changes after `delivered`, the feed position every sink has taken, stays
+    /// until the sinks have taken them.
+    pub fn purge_deleted_before(
+        &mut self,
+        cutoff: DateTime<Utc>,
+        delivered: Option<u64>,
+    ) -> Result<Vec<Uuid>, StoreError> {
+        let _timer = metrics::store_timer("purge");
+        let taken = |id: &Uuid| {
+            let last = self
+                .history
+                .get(id)
+                .and_then(|h| h.revisions().iter().filter_map(|r| r.sequence).max());
+            match (last, delivered) {
+                (Some(last), Some(delivered)) => last <= delivered,
+                _ => true,
+            }
+        };
+        let expired: Vec<Uuid> = self
+            .users
+            .iter()
+            .filter(|(id, r)| r.deleted_at.is_some_and(|at| at < cutoff) && taken(id))
+            .map(|(id, _)| *id)
+            .collect();
+        if expired.is_empty() {
+            return Ok(expired);
+        }
+        let entries = expired
+            .iter()
+            .map(|id| Entry {
+                id: *id,
+                record: None,
+                history: History::default(),
+                tombstone: None,
+                merged_into: None,
+            })
+            .collect();
+        self.commit_and_compact(entries)?;
+        Ok(expired)
+    }
+
+    pub fn tombstone(&self, id: &Uuid) -> Option<&Tombstone> {
+        self.tombstones.get(id)
+    }
+
+    /// Drops the user record, deleted or not, and leaves a tombstone in its place.
+    /// Revisions are kept for their metadata but lose every copy of the user's data.
+    pub fn erase(&mut self, id: Uuid, tombstone: Tombstone, actor: &str) -> Result<Option<User>, StoreError> {
+        let _timer = metrics::store_timer("erase");
+        let mut entry = self.entry(&id);
+        let Some(record) = entry.record.take() else {
+            return Ok(None);
+        };
+        entry.history.anonymise();
+        entry.history.push(actor, RevisionKind::Erased, None);
+        entry.tombstone = Some(tombstone);
+        // Users merged into this one left copies of the same person's data in
+        // their own revisions.
+        let mut entries = vec![entry];
+        for retired in self.merged_into_transitively(id) {
+            let mut entry = self.entry(&retired);
+            entry.history.anonymise();
+            entries.push(entry);
+        }
+        self.commit_and_compact(entries)?;
+        Ok(Some(record.user))
+    }
+
+    /// Combines `retired` into `survivor`, which takes `user` as its data. The
+    /// retired id keeps its history and resolves to the survivor from then on.
+    pub fn merge(&mut self, survivor: Uuid, retired: Uuid, user: User, actor: &str) -> Result<(), StoreError> {
+        let _timer = metrics::store_timer("merge");
+        let mut kept = self.entry(&survivor);
+        let profile = match kept.record.take() {
+            Some(record) => record.profile.reconciled(&record.user, &user),
+            None => Profile::default(),
+        };
+        kept.history
+            .push_merge(actor, RevisionKind::Merged, Some(user.clone()), retired);
+        kept.history.set_profile(&profile);
+        kept.record = Some(UserRecord {
+            user,
+            deleted_at: None,
+            profile,
+        });
+        let mut gone = self.entry(&retired);
+        gone.record = None;
+        gone.history
+            .push_merge(actor, RevisionKind::MergedInto, None, survivor);
+        gone.merged_into = Some(survivor);
+        self.commit(vec![kept, gone])
+    }
+
+    /// The id a merged user now lives under, following merges of merges.
+    pub fn merged_into(&self, id: &Uuid) -> Option<Uuid> {
+        let mut current = *self.merges.get(id)?;
+        // Each merge retires an id for good, so chains end; the bound only guards
+        // against a corrupted journal.
+        for _ in 0..self.merges.len() {
+            match self.merges.get(&current) {
+                Some(next) => current = *next,
+                None => break,
+            }
+        }
+        Some(current)
+    }
+
+    /// Ids merged into `id`, directly or through other merges.
+    fn merged_into_transitively(&self, id: Uuid) -> Vec<Uuid> {
+        let mut found = Vec::new();
+        let mut pending = vec![id];
+        while let Some(current) = pending.pop() {
+            for (retired, survivor) in &self.merges {
+                if *survivor == current && !found.contains(retired) {
+                    found.push(*retired);
+                    pending.push(*retired);
+                }
+            }
+        }
+        found
+    }
+
+    pub fn history(&self, id: &Uuid) -> Option<&History> {
+        self.history.get(id)
+    }
+
+    /// The collection as it was at `at`, rebuilt from revisions.
+    pub fn as_of(&self, at: DateTime<Utc>) -> Vec<User> {
+        self.history
+            .values()
+            .filter_map(|h| h.as_of(at))
+            .filter(|r| r.is_live())
+            .filter_map(|r| r.user.clone())
+            .collect()
+    }
+
+    /// Latest change feed position.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Wakes up after commits that move the change feed forward.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.changes.subscribe()
+    }
+
+    /// Up to `limit` revisions with a change feed position after `after`, in feed
+    /// order. Revisions of purged users are gone from the feed.
+    pub fn changes_after(&self, after: u64, limit: usize) -> Vec<(Uuid, Revision)> {
+        self.feed
+            .range((Bound::Excluded(after), Bound::Unbounded))
+            .filter_map(|(_, (id, revision))| Some((*id, self.history.get(id)?.get(*revision)?.clone())))
+            .take(limit)
+            .collect()
+    }
+
+    /// The revision at change feed position `sequence`, with its user id.
+    pub fn change(&self, sequence: u64) -> Option<(Uuid, Revision)> {
+        let (id, revision) = self.feed.get(&sequence)?;
+        Some((*id, self.history.get(id)?.get(*revision)?.clone()))
+    }
+
+    fn entry(&self, id: &Uuid) -> Entry {
+        Entry {
+            id: *id,
+            record: self.users.get(id).cloned(),
+            history: self.history.get(id).cloned().unwrap_or_default(),
+            tombstone: self.tombstones.get(id).cloned(),
+            merged_into: self.merges.get(id).copied(),
+        }
+    }
+
+    /// The entry a journal line leaves behind, given what was replayed before it.
+    fn replayed_entry(&self, written: Written) -> Entry {
+        let history = match written.history {
+            Some(history) => history,
+            None => {
+                let mut history = self.history.get(&written.id).cloned().unwrap_or_default();
+                history.extend(written.added);
+                history
+            }
+        };
+        Entry {
+            id: written.id,
+            record: written.record,
+            history,
+            tombstone: written.tombstone,
+            merged_into: written.merged_into,
+        }
+    }
+
+    fn apply(&mut self, mut entry: Entry) {
+        let id = entry.id;
+        let known = match self.history.get(&id) {
+            Some(history) if entry.history.revisions().is_empty() => {
+                for revision in history.revisions() {
+                    if let Some(sequence) = revision.sequence {
+                        self.feed.remove(&sequence);
+                    }
+                }
+                0
+            }
+            Some(history) => history.revisions().len(),
+            None => 0,
+        };
+        for revision in entry.history.added_since(known).iter() {
+            if let Some(sequence) = revision.sequence {
+                self.feed.insert(sequence, (id, revision.revision));
+                self.sequence = self.sequence.max(sequence);
+            }
+        }
+        match entry.record {
+            Some(record) => self.users.insert(id, record),
+            None => self.users.remove(&id),
+        };
+        if entry.history.revisions().is_empty() {
+            self.history.remove(&id);
+        } else {
+            self.history.insert(id, entry.history);
+        }
+        match entry.tombstone {
+            Some(tombstone) => self.tombstones.insert(id, tombstone),
+            None => self.tombstones.remove(&id),
+        };
+        match entry.merged_into {
+            Some(survivor) => self.merges.insert(id, survivor),
+            None => self.merges.remove(&id),
+        };
+    }
+
+    /// Gives the revisions the entries add their change feed positions and event
+    /// ids. They are written with the change itself and the feed only moves once
+    /// the change is applied, which makes the feed an outbox: a change is
+    /// published if and only if it was committed. A failed commit hands its
+    /// positions out again.
+    fn number(&mut self, entries: &mut [Entry]) {
+        for entry in entries {
+            let known = self.history.get(&entry.id).map_or(0, |h| h.revisions().len());
+            for revision in entry.history.added_since(known) {
+                self.sequence += 1;
+                revision.sequence = Some(self.sequence);
+                revision.event_id = Some(Uuid::new_v4());
+            }
+        }
+    }
+
+    /// Writes the entries to the journal, then applies them in memory. Entries
+    /// may only add revisions; anything else goes through `commit_and_compact`.
+    fn commit(&mut self, mut entries: Vec<Entry>) -> Result<(), StoreError> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let sequence = self.sequence;
+        self.number(&mut entries);
+        if let Some(journal) = &mut self.journal {
+            let line = Line::Commit(
+                entries
+                    .iter()
+                    .map(|e| Written::delta(e, self.history.get(&e.id).map_or(0, |h| h.revisions().len())))
+                    .collect(),
+            );
+            if let Err(e) = journal.append([&line]) {
+                self.sequence = sequence;
+                return Err(e.into());
+            }
+        }
+        for entry in entries {
+            self.apply(entry);
+        }
+        self.changes.send_replace(self.sequence);
+        Ok(())
+    }
+
+    /// Rewrites the journal from the state the entries lead to, so data they
+    /// remove does not survive in older journal lines, then applies them in
+    /// memory. Nothing changes if the journal cannot be rewritten.
+    fn commit_and_compact(&mut self, mut entries: Vec<Entry>) -> Result<(), StoreError> {
+        let sequence = self.sequence;
+        self.number(&mut entries);
+        if self.journal.is_some() {
+            let snapshot = self.snapshot(&entries);
+            if let Some(Err(e)) = self.journal.as_mut().map(|j| j.compact(snapshot)) {
+                self.sequence = sequence;
+                return Err(e.into());
+            }
+        }
+        for entry in entries {
+            self.apply(entry);
+        }
+        self.changes.send_replace(self.sequence);
+        Ok(())
+    }
+
+    /// Rewrites the journal from the current state, one line per id in the
+    /// current format. Returns the number of lines written, 0 without a journal.
+    pub fn rewrite(&mut self) -> Result<usize, StoreError> {
+        if self.journal.is_none() {
+            return Ok(0);
+        }
+        let snapshot = self.snapshot(&[]);
+        let written = snapshot.len();
+        if let Some(journal) = &mut self.journal {
+            journal.compact(snapshot)?;
+        }
+        Ok(written)
+    }
+
+    /// The whole state, in id order, as it will be once `changed` is applied.
+    fn snapshot(&self, changed: &[Entry]) -> Vec<Written> {
+        let changed: HashMap<Uuid, &Entry> = changed.iter().map(|e| (e.id, e)).collect();
+        let ids: BTreeSet<Uuid> = self
+            .users
+            .keys()
+            .chain(self.tombstones.keys())
+            .chain(self.history.keys())
+            .chain(self.merges.keys())
+            .chain(changed.keys())
+            .copied()
+            .collect();
+        ids.iter()
+            .map(|id| match changed.get(id) {
+                Some(entry) => (*entry).clone(),
+                None => self.entry(id),
+            })
+            .filter(|entry| !entry.is_empty())
+            .map(Written::full)
+            .collect()
+    }
+}
+
+/// Changes staged on top of a store. Reads through the batch see the changes
+/// staged before them; nothing is written until `UserStore::commit_batch`.
+pub struct Batch<'a> {
+    store: &'a UserStore,
+    staged: HashMap<Uuid, Entry>,
+    /// Ids in the order they were first changed.
+    order: Vec<Uuid>,
+}
+
+/// Staged changes, ready to be committed.
+pub struct Changes(Vec<Entry>);
+
+impl Batch<'_> {
+    /// Returns the user unless it is soft-deleted.
+    pub fn get(&self, id: &Uuid) -> Option<&User> {
+        self.record(id).map(|r| &r.user)
+    }
+
+    /// Inserts or replaces a user. Ids of erased users cannot be reused. A
+    /// replaced user keeps what of its profile still agrees with the new data.
+    pub fn insert(&mut self, id: Uuid, user: User, actor: &str) -> Result<Option<User>, StoreError> {
+        let profile = match self.record(&id) {
+            Some(record) => record.profile.reconciled(&record.user, &user),
+            None => Profile::default(),
+        };
+        self.insert_with_profile(id, user, profile, actor)
+    }
+
+    /// Inserts or replaces a user together with its v2 profile.
+    pub fn insert_with_profile(
+        &mut self,
+        id: Uuid,
+        user: User,
+        profile: Profile,
+        actor: &str,
+    ) -> Result<Option<User>, StoreError> {
+        if self.store.tombstones.contains_key(&id) {
+            return Err(StoreError::Erased);
+        }
+        if self.store.merges.contains_key(&id) {
+            return Err(StoreError::Exists);
+        }
+        let entry = self.entry(id);
+        let previous = entry.record.take().map(|r| r.user);
+        let kind = match previous {
+            Some(_) => RevisionKind::Updated,
+            None => RevisionKind::Created,
+        };
+        entry.history.push(actor, kind, Some(user.clone()));
+        entry.history.set_profile(&profile);
+        entry.record = Some(UserRecord {
+            user,
+            deleted_at: None,
+            profile,
+        });
+        Ok(previous)
+    }
+
+    /// Returns the record of the user unless it is soft-deleted.
+    pub fn record(&self, id: &Uuid) -> Option<&UserRecord> {
+        match self.staged.get(id) {
+            Some(entry) => entry.record.as_ref(),
+            None => self.store.users.get(id),
+        }
+        .filter(|r| r.deleted_at.is_none())
+    }
+
+    /// Hides the user until it is restored. Returns false if there is no live user with this id.
+    pub fn soft_delete(&mut self, id: &Uuid, actor: &str) -> bool {
+        if self.get(id).is_none() {
+            return false;
+        }
+        let entry = self.entry(*id);
+        let record = entry.record.as_mut().expect("user is live");
+        record.deleted_at = Some(Utc::now());
+        let (user, profile) = (record.user.clone(), record.profile.clone());
+        entry.history.push(actor, RevisionKind::Deleted, Some(user));
+        entry.history.set_profile(&profile);
+        true
+    }
+
+    pub fn into_changes(self) -> Changes {
+        let mut staged = self.staged;
+        Changes(
+            self.order
+                .iter()
+                .map(|id| staged.remove(id).expect("every ordered id is staged"))
+                .collect(),
+        )
+    }
+
+    fn entry(&mut self, id: Uuid) -> &mut Entry {
+        if !self.staged.contains_key(&id) {
+            self.order.push(id);
+        }
+        let store = self.store;
+        self.staged.entry(id).or_insert_with(|| store.entry(&id))
+    }
+}
@@ -0,0 +1,154 @@
+//! Realistic fake users for load tests and demo environments.
+
+use std::collections::HashSet;
+
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use openapi::models::User;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::search::fold;
+
+const MALE_NAMES: &[&str] = &[
+    "Adam", "Andrzej", "Bartosz", "Jakub", "Jan", "Kacper", "Krzysztof", "Łukasz", "Marcin",
+    "Marek", "Michał", "Mikołaj", "Paweł", "Piotr", "Stanisław", "Szymon", "Tomasz", "Wojciech",
+];
+
+const FEMALE_NAMES: &[&str] = &[
+    "Agnieszka", "Aleksandra", "Anna", "Barbara", "Ewa", "Joanna", "Julia", "Katarzyna", "Magdalena",
+    "Małgorzata", "Maria", "Monika", "Natalia", "Zofia", "Zuzanna", "Wiktoria", "Hanna", "Alicja",
+];
+
+/// Masculine and feminine forms of each surname.
+const SURNAMES: &[(&str, &str)] = &[
+    ("Nowak", "Nowak"),
+    ("Kowalski", "Kowalska"),
+    ("Wiśniewski", "Wiśniewska"),
+    ("Wójcik", "Wójcik"),
+    ("Kowalczyk", "Kowalczyk"),
+    ("Kamiński", "Kamińska"),
+    ("Lewandowski", "Lewandowska"),
+    ("Zieliński", "Zielińska"),
+    ("Szymański", "Szymańska"),
+    ("Woźniak", "Woźniak"),
+    ("Dąbrowski", "Dąbrowska"),
+    ("Kozłowski", "Kozłowska"),
+    ("Jankowski", "Jankowska"),
+    ("Mazur", "Mazur"),
+    ("Krawczyk", "Krawczyk"),
+    ("Piotrowski", "Piotrowska"),
+    ("Grabowski", "Grabowska"),
+    ("Pawłowski", "Pawłowska"),
+];
+
+const MIN_AGE: i64 = 18;
+const MAX_AGE: i64 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sex {
+    Female,
+    Male,
+}
+
+/// A PESEL for someone born on `birth`. `serial` (0..=999) tells people born on
+/// the same day apart.
+pub fn pesel(birth: NaiveDate, serial: u16, sex: Sex, rng: &mut impl Rng) -> String {
+    let century_offset = match birth.year() {
+        1800..=1899 => 80,
+        1900..=1999 => 0,
+        2000..=2099 => 20,
+        2100..=2199 => 40,
+        _ => 60,
+    };
+    let sex_digit = match sex {
+        Sex::Female => rng.gen_range(0..5) * 2,
+        Sex::Male => rng.gen_range(0..5) * 2 + 1,
+    };
+    let mut digits = format!(
+        "{:02}{:02}{:02}{:03}{}",
+        birth.year() % 100,
+        birth.month() + century_offset,
+        birth.day(),
+        serial % 1000,
+        sex_digit
+    );
+    digits.push(char::from(b'0' + checksum(&digits)));
+    digits
+}
+
+/// Check digit over the first ten digits of a PESEL.
+pub fn checksum(digits: &str) -> u8 {
+    const WEIGHTS: [u32; 10] = [1, 3, 7, 9, 1, 3, 7, 9, 1, 3];
+    let sum: u32 = digits
+        .bytes()
+        .zip(WEIGHTS)
+        .map(|(digit, weight)| u32::from(digit - b'0') * weight)
+        .sum();
+    ((10 - sum % 10) % 10) as u8
+}
+
+pub struct Generator {
+    rng: StdRng,
+    today: NaiveDate,
+    personal_ids: HashSet<String>,
+}
+
+impl Generator {
+    /// The same seed always produces the same users, ids included.
+    pub fn new(seed: Option<u64>) -> Self {
+        Generator {
+            rng: match seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
+            today: Utc::now().date_naive(),
+            personal_ids: HashSet::new(),
+        }
+    }
+
+    /// A new adult Polish citizen whose age and PESEL agree. PESELs are unique
+    /// among the users of one generator.
+    pub fn user(&mut self) -> User {
+        let sex = if self.rng.gen_bool(0.5) { Sex::Female } else { Sex::Male };
+        let (name, surname) = match sex {
+            Sex::Female => (
+                *FEMALE_NAMES.choose(&mut self.rng).expect("not empty"),
+                SURNAMES.choose(&mut self.rng).expect("not empty").1,
+            ),
+            Sex::Male => (
+                *MALE_NAMES.choose(&mut self.rng).expect("not empty"),
+                SURNAMES.choose(&mut self.rng).expect("not empty").0,
+            ),
+        };
+        let days = self.rng.gen_range(MIN_AGE * 365..(MAX_AGE + 1) * 365);
+        let birth = self.today - Duration::days(days);
+        let personal_id = loop {
+            let candidate = pesel(birth, self.rng.gen_range(0..1000), sex, &mut self.rng);
+            if self.personal_ids.insert(candidate.clone()) {
+                break candidate;
+            }
+        };
+        let email = format!(
+            "{}.{}{}@example.com",
+            fold(name),
+            fold(surname),
+            self.rng.gen_range(1..100)
+        );
+        User {
+            id: Some(uuid::Builder::from_random_bytes(self.rng.gen()).into_uuid()),
+            name: name.into(),
+            surname: surname.into(),
+            age: age(birth, self.today),
+            personal_id,
+            citizenship: "PL".into(),
+            email: Some(email),
+        }
+    }
+}
+
+/// Completed years between `birth` and `today`, which is not before it.
+pub(crate) fn age(birth: NaiveDate, today: NaiveDate) -> u32 {
+    let had_birthday = (today.month(), today.day()) >= (birth.month(), birth.day());
+    (today.year() - birth.year()) as u32 - u32::from(!had_birthday)
+}
@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::{ServerConnection, WebPkiClientVerifier};
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::TlsConfig;
+
+/// TLS settings for the listener. The certificate and key can be reloaded while
+/// the server runs; connections already established keep the settings they were
+/// accepted with.
+pub struct TlsReloader {
+    settings: TlsConfig,
+    current: RwLock<Arc<ServerConfig>>,
+}
+
+impl TlsReloader {
+    pub fn load(settings: &TlsConfig) -> Result<Self, String> {
+        Ok(TlsReloader {
+            settings: settings.clone(),
+            current: RwLock::new(Arc::new(build(settings)?)),
+        })
+    }
+
+    /// Re-reads the PEM files. The previous settings stay in use if they are invalid.
+    pub fn reload(&self) -> Result<(), String> {
+        let config = Arc::new(build(&self.settings)?);
+        *self.current.write().expect("TLS config lock poisoned") = config;
+        Ok(())
+    }
+
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.current.read().expect("TLS config lock poisoned").clone())
+    }
+}
+
+/// Reloads the certificate and key every time the process receives SIGHUP.
+#[cfg(unix)]
+pub fn reload_on_sighup(tls: Arc<TlsReloader>) -> Result<(), String> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup()).map_err(|e| format!("cannot listen for SIGHUP: {e}"))?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match tls.reload() {
+                Ok(()) => tracing::info!("reloaded TLS certificate"),
+                Err(e) => tracing::error!("keeping previous TLS certificate: {e}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn reload_on_sighup(_tls: Arc<TlsReloader>) -> Result<(), String> {
+    Ok(())
+}
+
+/// Subject of the verified client certificate, e.g. `CN=billing, O=Example`.
+pub fn peer_subject(connection: &ServerConnection) -> Option<String> {
+    let der = connection.peer_certificates()?.first()?;
+    let (_, certificate) = x509_parser::parse_x509_certificate(der.as_ref()).ok()?;
+    Some(certificate.subject().to_string())
+}
+
+fn build(settings: &TlsConfig) -> Result<ServerConfig, String> {
+    let (Some(cert_file), Some(key_file)) = (&settings.cert_file, &settings.key_file) else {
+        return Err("tls: cert_file and key_file must be set together".into());
+    };
+    let provider = Arc::new(ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("tls: {e}"))?;
+    let builder = match &settings.client_ca_file {
+        Some(ca_file) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in read_certificates(ca_file)? {
+                roots
+                    .add(certificate)
+                    .map_err(|e| format!("tls.client_ca_file: {}: {e}", ca_file.display()))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(|e| format!("tls.client_ca_file: {}: {e}", ca_file.display()))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder
+        .with_single_cert(read_certificates(cert_file)?, read_private_key(key_file)?)
+        .map_err(|e| format!("tls: {e}"))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+fn read_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = File::open(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid PEM in {}: {e}", path.display()))?;
+    if certificates.is_empty() {
+        return Err(format!("no certificate found in {}", path.display()));
+    }
+    Ok(certificates)
+}
+
+fn read_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    let file = File::open(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("invalid PEM in {}: {e}", path.display()))?
+        .ok_or_else(|| format!("no private key found in {}", path.display()))
+}
+
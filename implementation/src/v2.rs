@@ -0,0 +1,435 @@
+//! Version 2 of the user operations, served under `/api/v2` from the same store
+//! as v1. The store keeps users in their v1 form; what only v2 carries is kept
+//! next to it as a `Profile` and dropped as soon as a v1 write contradicts it.
+
+use std::sync::Arc;
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{MatchedPath, Path, Query, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{NaiveDate, Utc};
+use http::header::LINK;
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use lazy_static::lazy_static;
+use openapi::models::{Error, RequestHeader, ResponseHeader, User};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::auth::Scope;
+use crate::config::VersioningConfig;
+use crate::error::ApiError;
+use crate::feed::{mask, mask_profile};
+use crate::metrics;
+use crate::routes::{authorize, error_response, invalid_body, json_response};
+use crate::server::{build_response_header, ProfileOutcome, ServerImpl};
+use crate::synthetic;
+
+pub const BASE_PATH: &str = "/api/v2";
+
+/// RFC 9745 header announcing when an operation was deprecated.
+pub const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+/// RFC 8594 header announcing when an operation stops being served.
+pub const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+lazy_static! {
+    static ref RE_COUNTRY: Regex = Regex::new(r"^[A-Z]{2}$").unwrap();
+}
+
+/// What v2 knows about a user beyond its v1 data.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    /// The given names v1 joins into `name`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub given_names: Vec<String>,
+    /// The birth date v1 `age` was computed from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub birth_date: Option<NaiveDate>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<Address>,
+}
+
+impl Profile {
+    pub fn is_empty(&self) -> bool {
+        self == &Profile::default()
+    }
+
+    /// The profile once `previous` is replaced by `user` through v1: given names
+    /// and birth date no longer hold once the name or age they back changed.
+    pub fn reconciled(&self, previous: &User, user: &User) -> Profile {
+        Profile {
+            given_names: if previous.name == user.name {
+                self.given_names.clone()
+            } else {
+                Vec::new()
+            },
+            birth_date: self.birth_date.filter(|_| previous.age == user.age),
+            addresses: self.addresses.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressKind {
+    Home,
+    Work,
+    Postal,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct Address {
+    pub kind: AddressKind,
+    #[validate(length(min = 1))]
+    pub street: String,
+    #[validate(length(min = 1))]
+    pub city: String,
+    #[validate(length(min = 1))]
+    pub postal_code: String,
+    /// ISO 3166-1 alpha-2 code
+    #[validate(regex(path = *RE_COUNTRY))]
+    pub country: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct UserV2 {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    #[validate(length(min = 1), custom(function = "validate_given_names"))]
+    pub given_names: Vec<String>,
+    #[validate(length(min = 1))]
+    pub family_name: String,
+    /// Absent for users whose age was last set through v1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub birth_date: Option<NaiveDate>,
+    pub personal_id: String,
+    pub citizenship: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub addresses: Vec<Address>,
+}
+
+/// Each given name is one word, so that v1 can join them with spaces.
+fn validate_given_names(names: &[String]) -> Result<(), ValidationError> {
+    if names.iter().any(|n| n.is_empty() || n.contains(char::is_whitespace)) {
+        return Err(ValidationError::new("given_name"));
+    }
+    Ok(())
+}
+
+/// The v1 user and profile a v2 user is stored as. `previous` is the user it
+/// replaces, whose age is kept when no birth date is given.
+pub fn into_v1(user: UserV2, previous: Option<&User>, today: NaiveDate) -> Result<(User, Profile), ValidationErrors> {
+    user.validate()?;
+    let age = match (user.birth_date, previous) {
+        (Some(birth_date), _) if birth_date <= today => synthetic::age(birth_date, today),
+        (Some(_), _) => return Err(field_error("birth_date", "not_in_future")),
+        (None, Some(previous)) => previous.age,
+        (None, None) => return Err(field_error("birth_date", "required")),
+    };
+    let v1 = User {
+        id: user.id,
+        name: user.given_names.join(" "),
+        surname: user.family_name,
+        age,
+        personal_id: user.personal_id,
+        citizenship: user.citizenship,
+        email: user.email,
+    };
+    let profile = Profile {
+        given_names: user.given_names,
+        birth_date: user.birth_date,
+        addresses: user.addresses,
+    };
+    Ok((v1, profile))
+}
+
+/// The v2 form of a stored user. Without given names from v2, the v1 name is
+/// split on whitespace.
+pub fn from_v1(user: User, profile: Profile) -> UserV2 {
+    let given_names = if profile.given_names.is_empty() {
+        let words: Vec<String> = user.name.split_whitespace().map(str::to_owned).collect();
+        if words.is_empty() {
+            vec![user.name]
+        } else {
+            words
+        }
+    } else {
+        profile.given_names
+    };
+    UserV2 {
+        id: user.id,
+        given_names,
+        family_name: user.surname,
+        birth_date: profile.birth_date,
+        personal_id: user.personal_id,
+        citizenship: user.citizenship,
+        email: user.email,
+        addresses: profile.addresses,
+    }
+}
+
+/// The user as `from_v1` gives it, masked as in the change feed unless
+/// `unmasked`.
+fn view(user: User, profile: Profile, unmasked: bool) -> UserV2 {
+    if unmasked {
+        from_v1(user, profile)
+    } else {
+        from_v1(mask(user), mask_profile(profile))
+    }
+}
+
+fn field_error(field: &'static str, code: &'static str) -> ValidationErrors {
+    let mut errors = ValidationErrors::new();
+    errors.add(field, ValidationError::new(code));
+    errors
+}
+
+pub fn router(api_impl: Arc<ServerImpl>) -> Router {
+    Router::new()
+        .route(&format!("{BASE_PATH}/users"), get(list_users).post(create_user))
+        .route(
+            &format!("{BASE_PATH}/users/:id"),
+            get(get_user_by_id).put(update_user).delete(delete_user),
+        )
+        .with_state(api_impl)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserV2Request {
+    pub request_header: RequestHeader,
+    pub user: UserV2,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserV2Response {
+    pub response_header: ResponseHeader,
+    pub user: UserV2,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserV2ListResponse {
+    pub response_header: ResponseHeader,
+    pub users: Vec<UserV2>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListUsersV2QueryParams {
+    #[serde(rename = "includeDeleted")]
+    include_deleted: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserV2PathParams {
+    id: Uuid,
+}
+
+/// ListUsersV2 - GET /api/v2/users?includeDeleted={includeDeleted}
+#[tracing::instrument(skip_all)]
+async fn list_users(
+    headers: HeaderMap,
+    Query(query_params): Query<ListUsersV2QueryParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Read).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let include_deleted = query_params.include_deleted.unwrap_or(false);
+    let unmasked = claims.has(Scope::Pii);
+    // Deleted users are kept for restoring and erasure, not for browsing.
+    if include_deleted && !unmasked && !claims.has(Scope::Admin) {
+        return error_response(StatusCode::FORBIDDEN, "INSUFFICIENT_SCOPE");
+    }
+    let users = api_impl.users_with_profiles(include_deleted).await;
+    json_response(
+        StatusCode::OK,
+        &UserV2ListResponse {
+            response_header: build_response_header(),
+            users: users.into_iter().map(|(user, profile)| view(user, profile, unmasked)).collect(),
+        },
+    )
+}
+
+/// CreateUserV2 - POST /api/v2/users
+#[tracing::instrument(skip_all)]
+async fn create_user(
+    headers: HeaderMap,
+    State(api_impl): State<Arc<ServerImpl>>,
+    body: Result<Json<UserV2Request>, JsonRejection>,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Write).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let Json(request) = match body {
+        Ok(body) => body,
+        Err(rejection) => return invalid_body(rejection),
+    };
+    let (user, profile) = match into_v1(request.user, None, Utc::now().date_naive()) {
+        Ok(converted) => converted,
+        Err(e) => return invalid_user(&e),
+    };
+    let outcome = api_impl.create_user_with_profile(&claims, user, profile).await;
+    saved(StatusCode::CREATED, request.request_header, outcome)
+}
+
+/// GetUserByIdV2 - GET /api/v2/users/{id}
+#[tracing::instrument(skip_all)]
+async fn get_user_by_id(
+    headers: HeaderMap,
+    Path(path_params): Path<UserV2PathParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Read).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    match api_impl.user_with_profile(&path_params.id).await {
+        Some((user, profile)) => json_response(
+            StatusCode::OK,
+            &UserV2Response {
+                response_header: build_response_header(),
+                user: view(user, profile, claims.has(Scope::Pii)),
+            },
+        ),
+        None => error_response(StatusCode::NOT_FOUND, "404"),
+    }
+}
+
+/// UpdateUserV2 - PUT /api/v2/users/{id}
+#[tracing::instrument(skip_all)]
+async fn update_user(
+    headers: HeaderMap,
+    Path(path_params): Path<UserV2PathParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+    body: Result<Json<UserV2Request>, JsonRejection>,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Write).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let Json(request) = match body {
+        Ok(body) => body,
+        Err(rejection) => return invalid_body(rejection),
+    };
+    let Some((previous, _)) = api_impl.user_with_profile(&path_params.id).await else {
+        return error_response(StatusCode::NOT_FOUND, "404");
+    };
+    let (user, profile) = match into_v1(request.user, Some(&previous), Utc::now().date_naive()) {
+        Ok(converted) => converted,
+        Err(e) => return invalid_user(&e),
+    };
+    let outcome = api_impl
+        .update_user_with_profile(&claims, path_params.id, user, profile)
+        .await;
+    saved(StatusCode::OK, request.request_header, outcome)
+}
+
+/// DeleteUserV2 - DELETE /api/v2/users/{id}
+#[tracing::instrument(skip_all)]
+async fn delete_user(
+    headers: HeaderMap,
+    Path(path_params): Path<UserV2PathParams>,
+    State(api_impl): State<Arc<ServerImpl>>,
+) -> Response {
+    let claims = match authorize(&api_impl, &headers, Scope::Write).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    match api_impl.delete_user_as(&claims, path_params.id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => error_response(StatusCode::NOT_FOUND, "404"),
+        Err(e) => e.into_response(),
+    }
+}
+
+fn saved(status: StatusCode, request_header: RequestHeader, outcome: Result<ProfileOutcome, ApiError>) -> Response {
+    match outcome {
+        Ok(ProfileOutcome::Saved(user, profile)) => json_response(
+            status,
+            &UserV2Response {
+                response_header: ResponseHeader {
+                    request_id: request_header.request_id,
+                    send_date: Utc::now(),
+                },
+                user: from_v1(user, profile),
+            },
+        ),
+        Ok(ProfileOutcome::Invalid(e)) => invalid_user(&e),
+        Ok(ProfileOutcome::NotFound) => error_response(StatusCode::NOT_FOUND, "404"),
+        Ok(ProfileOutcome::Erased) => error_response(StatusCode::UNPROCESSABLE_ENTITY, "USER_ERASED"),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// 400 naming the fields that failed validation.
+fn invalid_user(errors: &ValidationErrors) -> Response {
+    metrics::validation_failures(errors);
+    let mut error = Error::new(build_response_header(), "INVALID_USER".into());
+    error.message = Some(errors.to_string());
+    json_response(StatusCode::BAD_REQUEST, &error)
+}
+
+/// Headers announcing the retirement of the v1 operations v2 replaces.
+#[derive(Debug, Clone)]
+pub struct Deprecation {
+    deprecated_at: Option<HeaderValue>,
+    sunset_at: Option<HeaderValue>,
+}
+
+impl Deprecation {
+    /// None until a deprecation or sunset date is configured.
+    pub fn new(config: &VersioningConfig) -> Option<Self> {
+        if config.v1_deprecated_at.is_none() && config.v1_sunset_at.is_none() {
+            return None;
+        }
+        let value = |text: String| HeaderValue::from_str(&text).expect("valid header value");
+        Some(Deprecation {
+            deprecated_at: config.v1_deprecated_at.map(|at| value(format!("@{}", at.timestamp()))),
+            sunset_at: config
+                .v1_sunset_at
+                .map(|at| value(at.format("%a, %d %b %Y %H:%M:%S GMT").to_string())),
+        })
+    }
+}
+
+/// Route layer adding `Deprecation`, `Sunset` and a `Link` to the v2 successor
+/// to the responses of the v1 operations v2 replaces.
+pub async fn deprecate_v1(State(deprecation): State<Arc<Deprecation>>, request: Request, next: Next) -> Response {
+    let replaced = request
+        .extensions()
+        .get::<MatchedPath>()
+        .is_some_and(|path| matches!(path.as_str(), "/api/users" | "/api/users/:id"));
+    if !replaced {
+        return next.run(request).await;
+    }
+    let successor = request
+        .uri()
+        .path()
+        .replacen(openapi::BASE_PATH, BASE_PATH, 1);
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    if let Some(value) = &deprecation.deprecated_at {
+        headers.insert(DEPRECATION, value.clone());
+    }
+    if let Some(value) = &deprecation.sunset_at {
+        headers.insert(SUNSET, value.clone());
+    }
+    if let Ok(link) = HeaderValue::try_from(format!("<{successor}>; rel=\"successor-version\"")) {
+        headers.append(LINK, link);
+    }
+    response
+}
@@ -0,0 +1,721 @@
+//! Outbound webhooks. Changes relayed from the outbox are queued for every
+//! subscription that wants them and POSTed to its URL, signed with the
+//! subscription's secret, with retries and exponential backoff.
+//!
+//! Subscriptions, queued deliveries and the feed position reached are kept in
+//! their own journal, so deliveries survive restarts. Payloads are not: they are
+//! rendered from the change when sent, so a user erased in the meantime is
+//! delivered without personal data.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Path, Query, State};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use hmac::{Hmac, Mac};
+use http::header::{CONTENT_TYPE, USER_AGENT};
+use http::{HeaderMap, StatusCode, Uri};
+use http_body_util::Full;
+use openapi::client::{error_chain, http_client, HttpClient};
+use openapi::models::{RequestHeader, ResponseHeader};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::{Mutex, Notify};
+use uuid::Uuid;
+
+use crate::auth::{self, Scope};
+use crate::config::WebhooksConfig;
+use crate::error::ApiError;
+use crate::feed::{ChangeEvent, ChangeType};
+use crate::journal::Journal;
+use crate::metrics;
+use crate::outbox::Sink;
+use crate::routes::{authorize, error_response, invalid_body, json_response};
+use crate::server::{build_response_header, ServerImpl};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Deliveries attempted at the same time.
+const CONCURRENCY: usize = 16;
+/// Longest sleep of the dispatcher when nothing is due.
+const IDLE: Duration = Duration::from_secs(60);
+/// Journal lines written before it is rewritten from the current state.
+const COMPACT_AFTER: usize = 10_000;
+/// Shortest accepted subscription secret, in bytes.
+const MIN_SECRET_BYTES: usize = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Subscription {
+    id: Uuid,
+    url: String,
+    events: BTreeSet<ChangeType>,
+    secret: String,
+    include_personal_data: bool,
+    created_at: DateTime<Utc>,
+    created_by: String,
+    /// Feed position when the subscription was made; earlier changes are not
+    /// delivered to it.
+    since: u64,
+}
+
+impl Subscription {
+    fn wants(&self, sequence: u64, change: ChangeType) -> bool {
+        sequence > self.since && self.events.contains(&change)
+    }
+}
+
+/// A subscription as shown by the API: everything but the secret.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SubscriptionView<'a> {
+    id: Uuid,
+    url: &'a str,
+    events: &'a BTreeSet<ChangeType>,
+    include_personal_data: bool,
+    created_at: DateTime<Utc>,
+    created_by: &'a str,
+}
+
+impl<'a> From<&'a Subscription> for SubscriptionView<'a> {
+    fn from(s: &'a Subscription) -> Self {
+        SubscriptionView {
+            id: s.id,
+            url: &s.url,
+            events: &s.events,
+            include_personal_data: s.include_personal_data,
+            created_at: s.created_at,
+            created_by: &s.created_by,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    /// Given up after the last attempt, or because the change was purged.
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Attempt {
+    at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status_code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Delivery {
+    id: Uuid,
+    subscription_id: Uuid,
+    sequence: u64,
+    #[serde(rename = "type")]
+    change: ChangeType,
+    user_id: Uuid,
+    status: DeliveryStatus,
+    attempts: u32,
+    created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_attempt_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_attempt: Option<Attempt>,
+}
+
+/// A line of the webhook journal. The state is the result of applying every
+/// line in order.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "entry", rename_all = "snake_case")]
+enum Entry {
+    Subscription(Subscription),
+    Unsubscribed { id: Uuid },
+    Delivery(Delivery),
+    Cursor { sequence: u64 },
+}
+
+#[derive(Default)]
+struct WebhookState {
+    subscriptions: BTreeMap<Uuid, Subscription>,
+    deliveries: BTreeMap<Uuid, Delivery>,
+    /// Feed position up to which changes have been queued.
+    cursor: Option<u64>,
+    journal: Option<Journal>,
+    /// Lines appended since the journal was last rewritten.
+    appended: usize,
+}
+
+impl WebhookState {
+    fn apply(&mut self, entry: Entry) {
+        match entry {
+            Entry::Subscription(subscription) => {
+                self.subscriptions.insert(subscription.id, subscription);
+            }
+            Entry::Unsubscribed { id } => {
+                self.subscriptions.remove(&id);
+                self.deliveries.retain(|_, d| d.subscription_id != id);
+            }
+            Entry::Delivery(delivery) => {
+                self.deliveries.insert(delivery.id, delivery);
+            }
+            Entry::Cursor { sequence } => self.cursor = Some(sequence),
+        }
+    }
+
+    /// Writes the entries to the journal, then applies them in memory.
+    fn commit(&mut self, entries: Vec<Entry>) -> io::Result<()> {
+        if let Some(journal) = &mut self.journal {
+            journal.append(&entries)?;
+            self.appended += entries.len();
+        }
+        for entry in entries {
+            self.apply(entry);
+        }
+        if self.appended > COMPACT_AFTER {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the journal from the current state, dropping superseded lines.
+    fn compact(&mut self) -> io::Result<()> {
+        let Some(journal) = &mut self.journal else {
+            return Ok(());
+        };
+        let snapshot = self
+            .subscriptions
+            .values()
+            .cloned()
+            .map(Entry::Subscription)
+            .chain(self.deliveries.values().cloned().map(Entry::Delivery))
+            .chain(self.cursor.map(|sequence| Entry::Cursor { sequence }));
+        journal.compact(snapshot)?;
+        self.appended = 0;
+        Ok(())
+    }
+
+    /// Forgets the oldest finished deliveries of each subscription beyond `keep`.
+    /// Pending deliveries are always kept.
+    fn trim(&mut self, keep: usize) {
+        let mut finished: BTreeMap<Uuid, Vec<(u64, Uuid)>> = BTreeMap::new();
+        for delivery in self.deliveries.values() {
+            if delivery.status != DeliveryStatus::Pending {
+                finished
+                    .entry(delivery.subscription_id)
+                    .or_default()
+                    .push((delivery.sequence, delivery.id));
+            }
+        }
+        for mut deliveries in finished.into_values() {
+            if deliveries.len() > keep {
+                deliveries.sort_unstable();
+                let excess = deliveries.len() - keep;
+                for (_, id) in &deliveries[..excess] {
+                    self.deliveries.remove(id);
+                }
+            }
+        }
+    }
+}
+
+pub struct Webhooks {
+    api_impl: Arc<ServerImpl>,
+    config: WebhooksConfig,
+    state: Mutex<WebhookState>,
+    /// Woken when deliveries are queued.
+    queued: Notify,
+    client: HttpClient,
+}
+
+impl Webhooks {
+    /// Replays the webhook journal, if one is configured. A new journal starts at
+    /// the current end of the change feed.
+    pub async fn open(api_impl: Arc<ServerImpl>, config: &WebhooksConfig) -> Result<Self, String> {
+        let mut state = WebhookState::default();
+        match &config.path {
+            Some(path) => {
+                let (journal, entries) =
+                    Journal::open::<Entry>(path).map_err(|e| format!("webhooks: {e}"))?;
+                for entry in entries {
+                    state.apply(entry);
+                }
+                state.journal = Some(journal);
+            }
+            None => tracing::warn!("webhooks.path is not configured, webhook subscriptions are kept in memory"),
+        }
+        if state.cursor.is_none() {
+            state.cursor = Some(api_impl.subscribe_changes().await.0);
+        }
+        state.trim(config.delivery_log_size);
+        state.compact().map_err(|e| format!("webhooks: {e}"))?;
+
+        Ok(Webhooks {
+            api_impl,
+            config: config.clone(),
+            state: Mutex::new(state),
+            queued: Notify::new(),
+            client: http_client(),
+        })
+    }
+
+    /// Runs the dispatcher, which sends due deliveries, one at a time per
+    /// subscription and up to `CONCURRENCY` in all, and records each outcome as
+    /// it comes, so a slow receiver holds up only its own deliveries. The outbox
+    /// relay queues them.
+    pub fn spawn(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut running = FuturesUnordered::new();
+            // Subscriptions with an attempt in flight.
+            let mut busy = HashSet::new();
+            loop {
+                let (due, next) = self.due(&busy, CONCURRENCY - running.len()).await;
+                for (delivery, subscription) in due {
+                    busy.insert(subscription.id);
+                    running.push(self.attempt(delivery, subscription));
+                }
+                let wait = next.map_or(IDLE, |at| (at - Utc::now()).to_std().unwrap_or(Duration::ZERO).min(IDLE));
+                tokio::select! {
+                    Some(delivery) = running.next() => {
+                        busy.remove(&delivery.subscription_id);
+                        if let Err(e) = self.record(delivery).await {
+                            tracing::error!(error = %e, "recording webhook deliveries failed");
+                        }
+                    }
+                    _ = self.queued.notified() => {}
+                    _ = tokio::time::sleep(wait) => {}
+                }
+            }
+        });
+    }
+
+    /// Up to `room` due deliveries, the earliest change of each subscription
+    /// not in `busy`, and when the next delivery of the others falls due. With
+    /// more due than room, that is left to the next attempt to finish.
+    async fn due(
+        &self,
+        busy: &HashSet<Uuid>,
+        room: usize,
+    ) -> (Vec<(Delivery, Subscription)>, Option<DateTime<Utc>>) {
+        let state = self.state.lock().await;
+        let now = Utc::now();
+        let mut first: BTreeMap<Uuid, &Delivery> = BTreeMap::new();
+        for delivery in state.deliveries.values() {
+            if busy.contains(&delivery.subscription_id) || delivery.next_attempt_at.is_none_or(|at| at > now) {
+                continue;
+            }
+            let earliest = first.entry(delivery.subscription_id).or_insert(delivery);
+            if delivery.sequence < earliest.sequence {
+                *earliest = delivery;
+            }
+        }
+        let next = if first.len() > room {
+            None
+        } else {
+            state
+                .deliveries
+                .values()
+                .filter(|d| !busy.contains(&d.subscription_id) && !first.contains_key(&d.subscription_id))
+                .filter_map(|d| d.next_attempt_at)
+                .min()
+        };
+        let mut due: Vec<&Delivery> = first.into_values().collect();
+        due.sort_by_key(|d| d.next_attempt_at);
+        let due = due
+            .into_iter()
+            .filter_map(|d| Some((d.clone(), state.subscriptions.get(&d.subscription_id)?.clone())))
+            .take(room)
+            .collect();
+        (due, next)
+    }
+
+    /// Records the outcome of an attempt.
+    async fn record(&self, delivery: Delivery) -> io::Result<()> {
+        let mut state = self.state.lock().await;
+        // A subscription removed meanwhile took its deliveries with it.
+        if !state.subscriptions.contains_key(&delivery.subscription_id) {
+            return Ok(());
+        }
+        state.commit(vec![Entry::Delivery(delivery)])?;
+        state.trim(self.config.delivery_log_size);
+        Ok(())
+    }
+
+    /// Sends the delivery once and returns it updated with the outcome.
+    async fn attempt(&self, mut delivery: Delivery, subscription: Subscription) -> Delivery {
+        let started = Instant::now();
+        let at = Utc::now();
+        delivery.attempts += 1;
+        // Purged users are gone from the feed; no retry brings them back.
+        let mut gone = false;
+        let (status_code, error) = match self.api_impl.change(delivery.sequence).await {
+            Some((user_id, revision)) => {
+                let event = ChangeEvent::new(user_id, revision, subscription.include_personal_data);
+                match self.send(&delivery, &subscription, &event).await {
+                    Ok(status) if status.is_success() => (Some(status.as_u16()), None),
+                    Ok(status) => (Some(status.as_u16()), Some(format!("receiver answered {status}"))),
+                    Err(e) => (None, Some(e)),
+                }
+            }
+            None => {
+                gone = true;
+                (None, Some("change no longer available".into()))
+            }
+        };
+        let result = if error.is_none() {
+            delivery.status = DeliveryStatus::Delivered;
+            delivery.next_attempt_at = None;
+            "delivered"
+        } else if gone || delivery.attempts >= self.config.max_attempts {
+            delivery.status = DeliveryStatus::Failed;
+            delivery.next_attempt_at = None;
+            "failed"
+        } else {
+            delivery.next_attempt_at = Some(at + self.backoff(delivery.attempts));
+            "retried"
+        };
+        metrics::webhook_attempt(result);
+        if let Some(e) = &error {
+            tracing::warn!(delivery = %delivery.id, subscription = %subscription.id, attempts = delivery.attempts, error = %e, result, "webhook delivery failed");
+        }
+        delivery.last_attempt = Some(Attempt {
+            at,
+            status_code,
+            error,
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+        delivery
+    }
+
+    /// Wait before the attempt after attempt number `attempts`.
+    fn backoff(&self, attempts: u32) -> chrono::Duration {
+        let factor = 1u64.checked_shl(attempts.saturating_sub(1)).unwrap_or(u64::MAX);
+        let secs = self
+            .config
+            .initial_backoff_secs
+            .saturating_mul(factor)
+            .min(self.config.max_backoff_secs);
+        chrono::Duration::seconds(i64::try_from(secs).unwrap_or(i64::MAX))
+    }
+
+    /// POSTs the event. The receiver can check that it came from this server with
+    /// the `webhook-signature` header: `v1=` followed by the hex HMAC-SHA256, keyed
+    /// with the subscription secret, of the `webhook-timestamp` header value, a
+    /// `.` and the body. Rejecting old timestamps guards against replays.
+    async fn send(
+        &self,
+        delivery: &Delivery,
+        subscription: &Subscription,
+        event: &ChangeEvent,
+    ) -> Result<StatusCode, String> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Payload<'a> {
+            delivery_id: Uuid,
+            subscription_id: Uuid,
+            event: &'a ChangeEvent,
+        }
+
+        let body = serde_json::to_vec(&Payload {
+            delivery_id: delivery.id,
+            subscription_id: subscription.id,
+            event,
+        })
+        .map_err(|e| e.to_string())?;
+        let timestamp = Utc::now().timestamp().to_string();
+        let request = http::Request::post(&subscription.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(USER_AGENT, concat!("users-api-webhooks/", env!("CARGO_PKG_VERSION")))
+            .header("webhook-id", delivery.id.to_string())
+            .header("webhook-timestamp", &timestamp)
+            .header("webhook-signature", signature(&subscription.secret, &timestamp, &body))
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| e.to_string())?;
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        match tokio::time::timeout(timeout, self.client.request(request)).await {
+            Ok(Ok(response)) => Ok(response.status()),
+            Ok(Err(e)) => Err(error_chain(&e)),
+            Err(_) => Err(format!("no answer within {}s", timeout.as_secs())),
+        }
+    }
+}
+
+/// Queues a delivery per matching subscription, together with the new cursor,
+/// so a change is queued once however often the relay offers it.
+#[async_trait]
+impl Sink for Webhooks {
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    async fn position(&self) -> io::Result<u64> {
+        Ok(self.state.lock().await.cursor.unwrap_or_default())
+    }
+
+    async fn publish(&self, events: &[ChangeEvent]) -> Result<(), String> {
+        let mut state = self.state.lock().await;
+        let cursor = state.cursor.unwrap_or_default();
+        let Some(last) = events.last().map(|e| e.sequence).filter(|s| *s > cursor) else {
+            return Ok(());
+        };
+        let now = Utc::now();
+        let mut entries = Vec::new();
+        for event in events.iter().filter(|e| e.sequence > cursor) {
+            for subscription in state.subscriptions.values().filter(|s| s.wants(event.sequence, event.change)) {
+                entries.push(Entry::Delivery(Delivery {
+                    id: Uuid::new_v4(),
+                    subscription_id: subscription.id,
+                    sequence: event.sequence,
+                    change: event.change,
+                    user_id: event.user_id,
+                    status: DeliveryStatus::Pending,
+                    attempts: 0,
+                    created_at: now,
+                    next_attempt_at: Some(now),
+                    last_attempt: None,
+                }));
+            }
+        }
+        entries.push(Entry::Cursor { sequence: last });
+        state.commit(entries).map_err(|e| format!("webhooks: {e}"))?;
+        self.queued.notify_one();
+        Ok(())
+    }
+}
+
+/// `v1=` and the hex HMAC-SHA256 of `{timestamp}.{body}`.
+pub fn signature(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("v1={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+pub fn router(webhooks: Arc<Webhooks>) -> Router {
+    Router::new()
+        .route("/api/webhooks", get(list_webhooks).post(create_webhook))
+        .route("/api/webhooks/:id", get(get_webhook).delete(delete_webhook))
+        .route("/api/webhooks/:id/deliveries", get(get_webhook_deliveries))
+        .with_state(webhooks)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateWebhookRequest {
+    request_header: RequestHeader,
+    /// An http or https URL.
+    url: String,
+    /// Every change type when empty.
+    #[serde(default)]
+    events: BTreeSet<ChangeType>,
+    /// Generated when not given.
+    secret: Option<String>,
+    /// Sends personal data unmasked, as in the change feed with the `pii` scope,
+    /// which the caller needs to ask for it.
+    #[serde(default)]
+    include_personal_data: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookResponse<'a> {
+    response_header: ResponseHeader,
+    subscription: SubscriptionView<'a>,
+    /// Only in the answer to CreateWebhook.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookListResponse<'a> {
+    response_header: ResponseHeader,
+    subscriptions: Vec<SubscriptionView<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeliveryListResponse<'a> {
+    response_header: ResponseHeader,
+    deliveries: Vec<&'a Delivery>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookPathParams {
+    id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeliveriesQueryParams {
+    status: Option<DeliveryStatus>,
+}
+
+/// CreateWebhook - POST /api/webhooks
+#[tracing::instrument(skip_all)]
+async fn create_webhook(
+    headers: HeaderMap,
+    State(webhooks): State<Arc<Webhooks>>,
+    body: Result<Json<CreateWebhookRequest>, JsonRejection>,
+) -> Response {
+    let claims = match authorize(&webhooks.api_impl, &headers, Scope::Admin).await {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let Json(request) = match body {
+        Ok(body) => body,
+        Err(rejection) => return invalid_body(rejection),
+    };
+    if request.include_personal_data && !claims.has(Scope::Pii) {
+        return error_response(StatusCode::FORBIDDEN, "INSUFFICIENT_SCOPE");
+    }
+    if !is_webhook_url(&request.url) {
+        return error_response(StatusCode::BAD_REQUEST, "INVALID_WEBHOOK_URL");
+    }
+    if request.secret.as_ref().is_some_and(|s| s.len() < MIN_SECRET_BYTES) {
+        return error_response(StatusCode::BAD_REQUEST, "WEBHOOK_SECRET_TOO_SHORT");
+    }
+    let events = if request.events.is_empty() {
+        [ChangeType::Created, ChangeType::Updated, ChangeType::Deleted].into()
+    } else {
+        request.events
+    };
+    let subscription = Subscription {
+        id: Uuid::new_v4(),
+        url: request.url,
+        events,
+        secret: request.secret.unwrap_or_else(auth::generate_secret),
+        include_personal_data: request.include_personal_data,
+        created_at: Utc::now(),
+        created_by: claims.subject,
+        since: webhooks.api_impl.subscribe_changes().await.0,
+    };
+    let mut state = webhooks.state.lock().await;
+    if let Err(e) = state.commit(vec![Entry::Subscription(subscription.clone())]) {
+        return ApiError::unavailable("cannot record webhook subscription", e).into_response();
+    }
+    json_response(
+        StatusCode::CREATED,
+        &WebhookResponse {
+            response_header: ResponseHeader::new(request.request_header.request_id, Utc::now()),
+            subscription: (&subscription).into(),
+            secret: Some(&subscription.secret),
+        },
+    )
+}
+
+/// ListWebhooks - GET /api/webhooks
+#[tracing::instrument(skip_all)]
+async fn list_webhooks(headers: HeaderMap, State(webhooks): State<Arc<Webhooks>>) -> Response {
+    if let Err(response) = authorize(&webhooks.api_impl, &headers, Scope::Admin).await {
+        return response;
+    }
+    let state = webhooks.state.lock().await;
+    json_response(
+        StatusCode::OK,
+        &WebhookListResponse {
+            response_header: build_response_header(),
+            subscriptions: state.subscriptions.values().map(SubscriptionView::from).collect(),
+        },
+    )
+}
+
+/// GetWebhook - GET /api/webhooks/{id}
+#[tracing::instrument(skip_all)]
+async fn get_webhook(
+    headers: HeaderMap,
+    Path(path_params): Path<WebhookPathParams>,
+    State(webhooks): State<Arc<Webhooks>>,
+) -> Response {
+    if let Err(response) = authorize(&webhooks.api_impl, &headers, Scope::Admin).await {
+        return response;
+    }
+    let state = webhooks.state.lock().await;
+    let Some(subscription) = state.subscriptions.get(&path_params.id) else {
+        return error_response(StatusCode::NOT_FOUND, "404");
+    };
+    json_response(
+        StatusCode::OK,
+        &WebhookResponse {
+            response_header: build_response_header(),
+            subscription: subscription.into(),
+            secret: None,
+        },
+    )
+}
+
+/// DeleteWebhook - DELETE /api/webhooks/{id}
+///
+/// Pending deliveries are dropped with the subscription.
+#[tracing::instrument(skip_all)]
+async fn delete_webhook(
+    headers: HeaderMap,
+    Path(path_params): Path<WebhookPathParams>,
+    State(webhooks): State<Arc<Webhooks>>,
+) -> Response {
+    if let Err(response) = authorize(&webhooks.api_impl, &headers, Scope::Admin).await {
+        return response;
+    }
+    let mut state = webhooks.state.lock().await;
+    if !state.subscriptions.contains_key(&path_params.id) {
+        return error_response(StatusCode::NOT_FOUND, "404");
+    }
+    if let Err(e) = state.commit(vec![Entry::Unsubscribed { id: path_params.id }]) {
+        return ApiError::unavailable("cannot record webhook removal", e).into_response();
+    }
+    let mut response = Response::default();
+    *response.status_mut() = StatusCode::NO_CONTENT;
+    response
+}
+
+/// GetWebhookDeliveries - GET /api/webhooks/{id}/deliveries?status={status}
+///
+/// Pending deliveries and the latest finished ones, newest first.
+#[tracing::instrument(skip_all)]
+async fn get_webhook_deliveries(
+    headers: HeaderMap,
+    Path(path_params): Path<WebhookPathParams>,
+    Query(query_params): Query<DeliveriesQueryParams>,
+    State(webhooks): State<Arc<Webhooks>>,
+) -> Response {
+    if let Err(response) = authorize(&webhooks.api_impl, &headers, Scope::Admin).await {
+        return response;
+    }
+    let state = webhooks.state.lock().await;
+    if !state.subscriptions.contains_key(&path_params.id) {
+        return error_response(StatusCode::NOT_FOUND, "404");
+    }
+    let mut deliveries: Vec<&Delivery> = state
+        .deliveries
+        .values()
+        .filter(|d| d.subscription_id == path_params.id)
+        .filter(|d| query_params.status.is_none_or(|s| d.status == s))
+        .collect();
+    deliveries.sort_by(|a, b| b.sequence.cmp(&a.sequence).then(b.created_at.cmp(&a.created_at)));
+    json_response(
+        StatusCode::OK,
+        &DeliveryListResponse {
+            response_header: build_response_header(),
+            deliveries,
+        },
+    )
+}
+
+fn is_webhook_url(url: &str) -> bool {
+    url.parse::<Uri>().is_ok_and(|uri| {
+        matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some_and(|h| !h.is_empty())
+    })
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use serde_json::{json, Value};
+
+use common::{admin, Server, Workdir};
+
+fn user() -> Value {
+    json!({"name": "Adam", "surname": "Mickiewicz", "age": 30, "personalId": "98122412345", "citizenship": "PL"})
+}
+
+/// Stdout of a successful run as JSON.
+fn json_output(output: std::process::Output) -> Value {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn offline_commands_layer_the_environment_over_the_config_file() {
+    let server = Server::start(&[]);
+    let id = server.create_user(user());
+    let workdir = server.crash();
+    // The file points somewhere else; the environment wins, as for the server.
+    let config = workdir.path().join("config.toml");
+    std::fs::write(&config, "[storage]\nbackend = \"journal\"\npath = \"elsewhere.journal\"\n").unwrap();
+
+    let (journal, keys) = (workdir.journal(), workdir.keys());
+    let (signing, pseudonym) = (workdir.signing_key(), workdir.pseudonym_key());
+    let env = [
+        ("USERS_STORAGE_PATH", journal.as_path()),
+        ("USERS_API_KEYS_FILE", keys.as_path()),
+        ("USERS_SIGNING_KEY_FILE", signing.as_path()),
+        ("USERS_PSEUDONYM_KEY_FILE", pseudonym.as_path()),
+    ];
+    let listed = json_output(admin(&["--config", config.to_str().unwrap(), "--output", "json", "list"], &env));
+    assert_eq!(listed[0]["id"], id.as_str(), "{listed}");
+}
+
+#[test]
+fn offline_commands_refuse_a_journal_a_server_has_open() {
+    let server = Server::start(&[]);
+    let (journal, keys) = (server.workdir.journal(), server.workdir.keys());
+    let (signing, pseudonym) = (server.workdir.signing_key(), server.workdir.pseudonym_key());
+    let env = [
+        ("USERS_STORAGE_PATH", journal.as_path()),
+        ("USERS_API_KEYS_FILE", keys.as_path()),
+        ("USERS_SIGNING_KEY_FILE", signing.as_path()),
+        ("USERS_PSEUDONYM_KEY_FILE", pseudonym.as_path()),
+    ];
+
+    let output = admin(&["list"], &env);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("in use by another process"), "{stderr}");
+
+    let _workdir = server.crash();
+    assert!(admin(&["list"], &env).status.success());
+}
+
+#[test]
+fn offline_commands_refuse_the_memory_backend() {
+    let workdir = Workdir::new();
+    let (keys, signing, pseudonym) = (workdir.keys(), workdir.signing_key(), workdir.pseudonym_key());
+    let env = [
+        ("USERS_STORAGE_BACKEND", std::path::Path::new("memory")),
+        ("USERS_API_KEYS_FILE", keys.as_path()),
+        ("USERS_SIGNING_KEY_FILE", signing.as_path()),
+        ("USERS_PSEUDONYM_KEY_FILE", pseudonym.as_path()),
+    ];
+    let output = admin(&["list"], &env);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("memory backend"), "{stderr}");
+}
+
+#[test]
+fn the_audit_log_is_verified_against_a_running_server() {
+    let server = Server::start(&[]);
+    server.create_user(user());
+    let url = format!("http://{}", server.addr);
+    let output = admin(
+        &["--url", &url, "--api-key", common::ADMIN_KEY, "--output", "json", "audit", "verify"],
+        &[],
+    );
+    let verified = json_output(output);
+    assert_eq!(verified["entries"], 1, "{verified}");
+    assert_eq!(verified["intact"], true);
+}
+
+#[test]
+fn the_audit_log_is_verified_from_its_journal_while_a_server_appends_to_it() {
+    let server = Server::start(&[]);
+    server.create_user(user());
+    let (journal, keys) = (server.workdir.journal(), server.workdir.keys());
+    let (signing, pseudonym) = (server.workdir.signing_key(), server.workdir.pseudonym_key());
+    let env = [
+        ("USERS_STORAGE_PATH", journal.as_path()),
+        ("USERS_API_KEYS_FILE", keys.as_path()),
+        ("USERS_SIGNING_KEY_FILE", signing.as_path()),
+        ("USERS_PSEUDONYM_KEY_FILE", pseudonym.as_path()),
+    ];
+    let verified = json_output(admin(&["--output", "json", "audit", "verify"], &env));
+    assert_eq!(verified["entries"], 1, "{verified}");
+    assert_eq!(verified["intact"], true);
+
+    let workdir = server.crash();
+    let audit = workdir.audit();
+    let mut entry: Value = serde_json::from_str(std::fs::read_to_string(&audit).unwrap().trim()).unwrap();
+    entry["actor"] = json!("someone-else");
+    std::fs::write(&audit, format!("{entry}\n")).unwrap();
+    let output = admin(&["audit", "verify"], &env);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("audit chain broken at entry 1"), "{stderr}");
+}
@@ -0,0 +1,115 @@
+//! Authentication is required unless open mode is asked for, and changes are
+//! recorded under the subject of the key that made them.
+
+mod common;
+
+use common::{header, Server, AUDITOR_KEY, READER_KEY, WRITER_KEY};
+use serde_json::{json, Value};
+
+fn user() -> Value {
+    json!({"name": "Adam", "surname": "Mickiewicz", "age": 30, "personalId": "98122412345", "citizenship": "PL"})
+}
+
+#[test]
+fn server_refuses_to_start_without_keys_unless_open() {
+    let output = common::run(&["--storage-backend", "memory"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("auth.api_keys_file: required"), "{stderr}");
+
+    let workdir = common::Workdir::new();
+    let keys = workdir.keys();
+    let output = common::run(&["--auth-open", "true", "--api-keys-file", keys.to_str().unwrap(), "--print-config"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not both"));
+
+    // Receipts and pseudonyms need keys that outlive the process.
+    let output = common::run(&["--api-keys-file", keys.to_str().unwrap(), "--print-config"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("auth.signing_key_file: required unless auth.open is set"), "{stderr}");
+    assert!(stderr.contains("auth.pseudonym_key_file: required unless auth.open is set"), "{stderr}");
+}
+
+#[test]
+fn creates_need_the_write_scope_and_record_the_subject() {
+    let server = Server::start(&[]);
+    let body = json!({"requestHeader": header(), "user": user()});
+
+    assert_eq!(server.request_as(None, "POST", "/api/users", Some(&body)).status, 401);
+    let refused = server.request_as(Some(READER_KEY), "POST", "/api/users", Some(&body));
+    assert_eq!(refused.status, 401);
+    assert_eq!(refused.json()["code"], "INSUFFICIENT_SCOPE");
+
+    let created = server.request_as(Some(WRITER_KEY), "POST", "/api/users", Some(&body));
+    assert_eq!(created.status, 201, "{}", created.text);
+    let id = created.json()["user"]["id"].as_str().unwrap().to_owned();
+
+    let revisions = server.request("GET", &format!("/api/users/{id}/revisions"), None).json();
+    assert_eq!(revisions["revisions"][0]["actor"], "writer", "{revisions}");
+    let audit = server.request("GET", "/api/audit", None).json();
+    assert_eq!(audit["entries"][0]["actor"], "writer", "{audit}");
+    assert_eq!(audit["entries"][0]["action"], "created");
+}
+
+#[test]
+fn open_mode_serves_callers_without_a_key() {
+    let server = Server::start(&["--auth-open", "true"]);
+    let body = json!({"requestHeader": header(), "user": user()});
+    let created = server.request_as(None, "POST", "/api/users", Some(&body));
+    assert_eq!(created.status, 201, "{}", created.text);
+    let id = created.json()["user"]["id"].as_str().unwrap().to_owned();
+    let revisions = server.request_as(None, "GET", &format!("/api/users/{id}/revisions"), None).json();
+    assert_eq!(revisions["revisions"][0]["actor"], "anonymous", "{revisions}");
+}
+
+#[test]
+fn listing_deleted_users_needs_the_pii_scope() {
+    let server = Server::start(&[]);
+    let id = server.create_user(user());
+    let deleted = server.request("DELETE", &format!("/api/users/{id}"), Some(&json!({"requestHeader": header()})));
+    assert_eq!(deleted.status, 204);
+
+    let listed = |key: Option<&str>, query: &str| server.request_as(key, "GET", &format!("/api/users{query}"), None);
+    assert_eq!(listed(None, "").status, 401);
+    assert_eq!(listed(Some(READER_KEY), "").json()["usersList"], json!([]));
+
+    let refused = listed(Some(WRITER_KEY), "?includeDeleted=true");
+    assert_eq!(refused.status, 401);
+    assert_eq!(refused.json()["code"], "INSUFFICIENT_SCOPE");
+    for key in [AUDITOR_KEY, common::ADMIN_KEY] {
+        let answer = listed(Some(key), "?includeDeleted=true");
+        assert_eq!(answer.status, 200, "{}", answer.text);
+        assert_eq!(answer.json()["usersList"][0]["id"], id.as_str());
+    }
+}
+
+#[test]
+fn reads_mask_personal_data_without_the_pii_scope() {
+    let server = Server::start(&["--validation-mode", "strict"]);
+    let mut user = user();
+    user["email"] = json!("mickiewicz@o2.pl");
+    let id = server.create_user(user);
+
+    let paths = [
+        "/api/users".to_owned(),
+        format!("/api/users/{id}"),
+        "/api/v2/users".to_owned(),
+        format!("/api/v2/users/{id}"),
+        "/api/users/export".to_owned(),
+    ];
+    for path in &paths {
+        let answer = server.request_as(Some(READER_KEY), "GET", path, None);
+        assert_eq!(answer.status, 200, "{path}: {}", answer.text);
+        assert!(!answer.text.contains("98122412345") && !answer.text.contains("mickiewicz@"), "{path}: {}", answer.text);
+        assert!(answer.text.contains("*******2345") && answer.text.contains("m***@o2.pl"), "{path}: {}", answer.text);
+    }
+    for path in &paths {
+        let answer = server.request_as(Some(AUDITOR_KEY), "GET", path, None);
+        assert!(answer.text.contains("98122412345"), "{path}: {}", answer.text);
+    }
+
+    let refused = server.request_as(Some(READER_KEY), "GET", "/api/v2/users?includeDeleted=true", None);
+    assert_eq!(refused.status, 403);
+    assert_eq!(refused.json()["code"], "INSUFFICIENT_SCOPE");
+}
@@ -0,0 +1,107 @@
+//! ApplyBatch: operations run in order, and a transactional batch is written
+//! whole or not at all.
+
+mod common;
+
+use serde_json::{json, Value};
+
+use common::{header, Server, AUDITOR_KEY};
+
+fn user(surname: &str) -> Value {
+    json!({"name": "Adam", "surname": surname, "age": 30, "personalId": "98122412345", "citizenship": "PL"})
+}
+
+fn batch(server: &Server, mode: &str, operations: Value) -> (u16, Value) {
+    let body = json!({"requestHeader": header(), "mode": mode, "operations": operations});
+    let answer = server.request("POST", "/api/users/batch", Some(&body));
+    (answer.status, answer.json())
+}
+
+fn surnames(server: &Server) -> Vec<String> {
+    let mut surnames: Vec<String> = server.request_as(Some(AUDITOR_KEY), "GET", "/api/users", None).json()["usersList"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|u| u["surname"].as_str().unwrap().to_owned())
+        .collect();
+    surnames.sort();
+    surnames
+}
+
+fn statuses(answer: &Value) -> Vec<u64> {
+    answer["results"].as_array().unwrap().iter().map(|r| r["status"].as_u64().unwrap()).collect()
+}
+
+#[test]
+fn a_failing_operation_rolls_back_a_transactional_batch() {
+    let server = Server::start(&[]);
+    let kept = server.create_user(user("Mickiewicz"));
+    let deleted = server.create_user(user("Słowacki"));
+    let operations = json!([
+        {"operation": "create", "requestHeader": header(), "user": user("Norwid")},
+        {"operation": "update", "id": kept, "requestHeader": header(), "user": user("Krasiński")},
+        {"operation": "delete", "id": deleted, "requestHeader": header()},
+        // The last operation fails: there is no such user.
+        {"operation": "delete", "id": "67e55044-10b1-426f-9247-bb680e5fe0c8", "requestHeader": header()},
+    ]);
+
+    let (status, answer) = batch(&server, "transactional", operations);
+    assert_eq!(status, 422, "{answer}");
+    assert_eq!(answer["committed"], false);
+    assert_eq!(statuses(&answer), [424, 424, 424, 404]);
+    assert_eq!(answer["results"][0]["body"]["code"], "BATCH_ABORTED");
+
+    // None of the operations before the failing one reached the store.
+    assert_eq!(surnames(&server), ["Mickiewicz", "Słowacki"]);
+    for id in [&kept, &deleted] {
+        let revisions = server.request("GET", &format!("/api/users/{id}/revisions"), None).json();
+        assert_eq!(revisions["revisions"].as_array().unwrap().len(), 1, "{revisions}");
+    }
+}
+
+#[test]
+fn a_transactional_batch_sees_its_own_earlier_operations() {
+    let server = Server::start(&[]);
+    let id = server.create_user(user("Mickiewicz"));
+    let operations = json!([
+        {"operation": "update", "id": id, "requestHeader": header(), "user": user("Krasiński")},
+        {"operation": "delete", "id": id, "requestHeader": header()},
+        // Already deleted earlier in the batch.
+        {"operation": "update", "id": id, "requestHeader": header(), "user": user("Norwid")},
+    ]);
+    let (status, answer) = batch(&server, "transactional", operations);
+    assert_eq!(status, 422, "{answer}");
+    assert_eq!(statuses(&answer), [424, 424, 404]);
+    assert_eq!(surnames(&server), ["Mickiewicz"]);
+
+    let operations = json!([
+        {"operation": "update", "id": id, "requestHeader": header(), "user": user("Krasiński")},
+        {"operation": "create", "requestHeader": header(), "user": user("Norwid")},
+        {"operation": "delete", "id": id, "requestHeader": header()},
+    ]);
+    let (status, answer) = batch(&server, "transactional", operations);
+    assert_eq!(status, 200, "{answer}");
+    assert_eq!(answer["committed"], true);
+    assert_eq!(statuses(&answer), [200, 201, 204]);
+    assert_eq!(surnames(&server), ["Norwid"]);
+    let revisions = server.request("GET", &format!("/api/users/{id}/revisions"), None).json();
+    assert_eq!(revisions["revisions"].as_array().unwrap().len(), 3, "{revisions}");
+}
+
+#[test]
+fn an_independent_batch_keeps_the_operations_that_succeed() {
+    let server = Server::start(&[]);
+    let mut invalid = user("Norwid");
+    invalid["personalId"] = json!("not a personal id");
+    let operations = json!([
+        {"operation": "create", "requestHeader": header(), "user": user("Mickiewicz")},
+        {"operation": "create", "requestHeader": header(), "user": invalid},
+        {"operation": "create", "requestHeader": header(), "user": user("Słowacki")},
+    ]);
+
+    let (status, answer) = batch(&server, "independent", operations);
+    assert_eq!(status, 200, "{answer}");
+    assert_eq!(answer["committed"], true);
+    assert_eq!(statuses(&answer), [201, 400, 201]);
+    assert_eq!(surnames(&server), ["Mickiewicz", "Słowacki"]);
+}
@@ -0,0 +1,141 @@
+//! `UsersClient` against local servers: retries of idempotent calls only,
+//! per-attempt timeouts and the credentials it sends.
+
+mod common;
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use openapi::apis::users::{CreateUserResponse, DeleteUserResponse, GetUserByIdResponse};
+use openapi::client::{Auth, ClientError, UsersClient};
+use openapi::models::User;
+use serde_json::json;
+use uuid::Uuid;
+
+use common::{responder, silent, Server};
+
+const ID: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+
+fn user() -> User {
+    User::new("Adam".into(), "Mickiewicz".into(), 30, "12345678903".into(), "PL".into())
+}
+
+fn user_response() -> String {
+    json!({
+        "responseHeader": {"requestId": "6ba7b810-9dad-11d1-80b4-00c04fd430c8", "sendDate": "2024-01-01T00:00:00Z"},
+        "user": {"id": ID, "name": "Adam", "surname": "Mickiewicz", "age": 30, "personalId": "12345678903", "citizenship": "PL"}
+    })
+    .to_string()
+}
+
+fn client(addr: &str) -> UsersClient {
+    UsersClient::new(&format!("http://{addr}"))
+        .unwrap()
+        .with_retries(2, Duration::from_millis(10))
+}
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Runtime::new().unwrap().block_on(future)
+}
+
+#[test]
+fn reads_are_retried_after_unavailable_answers() {
+    let (addr, received) = responder(vec![(503, String::new()), (502, String::new()), (200, user_response())]);
+    let id = Uuid::parse_str(ID).unwrap();
+
+    let response = block_on(client(&addr).get_user_by_id(id)).unwrap();
+    assert!(matches!(response, GetUserByIdResponse::Status200_Success(_)), "{response:?}");
+    let requests: Vec<_> = received.try_iter().collect();
+    assert_eq!(requests.len(), 3);
+    assert!(requests.iter().all(|r| r.method == "GET" && r.path == format!("/api/users/{ID}")));
+}
+
+#[test]
+fn deletes_are_retried_and_give_up_after_the_last_retry() {
+    let (addr, received) = responder(vec![(503, String::new()); 3]);
+    let id = Uuid::parse_str(ID).unwrap();
+
+    match block_on(client(&addr).delete_user(id)) {
+        Err(ClientError::UnexpectedStatus { status, .. }) => assert_eq!(status, 503),
+        other => panic!("{other:?}"),
+    }
+    assert_eq!(received.try_iter().count(), 3);
+
+    let (addr, _received) = responder(vec![(502, String::new()), (204, String::new())]);
+    let response = block_on(client(&addr).delete_user(id)).unwrap();
+    assert!(matches!(response, DeleteUserResponse::Status204_NoContent));
+}
+
+#[test]
+fn creates_are_sent_once() {
+    let (addr, received) = responder(vec![(503, String::new()), (201, user_response())]);
+
+    match block_on(client(&addr).create_user(user())) {
+        Err(ClientError::UnexpectedStatus { status, .. }) => assert_eq!(status, 503),
+        other => panic!("{other:?}"),
+    }
+    assert_eq!(received.try_iter().count(), 1);
+}
+
+#[test]
+fn each_attempt_is_limited_by_the_timeout() {
+    let (addr, accepted) = silent();
+    let client = client(&addr).with_timeout(Duration::from_millis(200));
+    let id = Uuid::parse_str(ID).unwrap();
+
+    match block_on(client.get_user_by_id(id)) {
+        Err(ClientError::Timeout(timeout)) => assert_eq!(timeout, Duration::from_millis(200)),
+        other => panic!("{other:?}"),
+    }
+    assert_eq!(accepted.load(Ordering::SeqCst), 3);
+
+    let (addr, accepted) = silent();
+    let client = UsersClient::new(&format!("http://{addr}"))
+        .unwrap()
+        .with_timeout(Duration::from_millis(200));
+    assert!(matches!(block_on(client.create_user(user())), Err(ClientError::Timeout(_))));
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn credentials_are_sent_as_configured() {
+    let found = |client: UsersClient| {
+        let response = block_on(client.get_user_by_id(Uuid::parse_str(ID).unwrap())).unwrap();
+        assert!(matches!(response, GetUserByIdResponse::Status200_Success(_)), "{response:?}");
+    };
+    let (addr, received) = responder(vec![(200, user_response()); 3]);
+
+    found(client(&addr).with_auth(Auth::ApiKey("key".into())));
+    let request = received.recv().unwrap();
+    assert_eq!(request.headers.get("bearer").map(String::as_str), Some("key"));
+    assert!(!request.headers.contains_key("authorization"));
+
+    found(client(&addr).with_auth(Auth::Bearer("token".into())));
+    let request = received.recv().unwrap();
+    assert_eq!(request.headers.get("authorization").map(String::as_str), Some("Bearer token"));
+
+    found(client(&addr));
+    let request = received.recv().unwrap();
+    assert!(!request.headers.contains_key("authorization") && !request.headers.contains_key("bearer"));
+}
+
+#[test]
+fn the_server_answers_the_client_with_the_documented_responses() {
+    let server = Server::start(&[]);
+    let url = format!("http://{}", server.addr);
+
+    let anonymous = UsersClient::new(&url).unwrap();
+    let refused = block_on(anonymous.create_user(user())).unwrap();
+    assert!(matches!(refused, CreateUserResponse::Status401_Unauthorized(_)), "{refused:?}");
+
+    let admin = UsersClient::new(&url).unwrap().with_auth(Auth::Bearer(common::ADMIN_KEY.into()));
+    let CreateUserResponse::Status201_UserCreatedSuccessfully(created) = block_on(admin.create_user(user())).unwrap() else {
+        panic!("not created");
+    };
+    let id = created.user.id.unwrap();
+    let found = block_on(admin.get_user_by_id(id)).unwrap();
+    assert!(matches!(found, GetUserByIdResponse::Status200_Success(r) if r.user.surname == "M***"));
+    let auditor = UsersClient::new(&url).unwrap().with_auth(Auth::Bearer(common::AUDITOR_KEY.into()));
+    let found = block_on(auditor.get_user_by_id(id)).unwrap();
+    assert!(matches!(found, GetUserByIdResponse::Status200_Success(r) if r.user.surname == "Mickiewicz"));
+}
@@ -0,0 +1,456 @@
+//! Harness shared by the tests that run the server binary: each server gets a
+//! working directory with its journal and API keys, listens on a port of its
+//! own choosing and reports it in the "listening" line of its JSON log.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+pub const ADMIN_KEY: &str = "admin-secret";
+pub const WRITER_KEY: &str = "writer-secret";
+pub const READER_KEY: &str = "reader-secret";
+/// Reads personal data unmasked, without write access.
+pub const AUDITOR_KEY: &str = "auditor-secret";
+
+const KEYS: &str = r#"[
+  {"subject":"admin","secretSha256":"16175223c8ddce5ace0493c948569c211b03c4c6bb3d3e484434999448cffe01","scopes":["admin"]},
+  {"subject":"writer","secretSha256":"ef80202ea99d7c668a9677d9242456057ac10488311cb8757674490e194a56e1","scopes":["read","write"]},
+  {"subject":"reader","secretSha256":"f03319dee240faa729e0cfa7ab5ffd80a1d64a127e3643f239009abff6382914","scopes":["read"]},
+  {"subject":"auditor","secretSha256":"0510bbdc800a8133002bb0d89df94045ad9f85f041755a9ac81de67d0df80fc4","scopes":["read","pii"]}
+]"#;
+
+/// A temporary directory holding the API keys file, the receipt and pseudonym
+/// keys and the journals, removed when dropped. Servers started in the same
+/// directory share their users.
+pub struct Workdir {
+    path: PathBuf,
+}
+
+impl Workdir {
+    pub fn new() -> Arc<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "users-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(path.join("keys.json"), KEYS).unwrap();
+        std::fs::write(path.join("signing.key"), "signing-key-of-the-test-servers!").unwrap();
+        std::fs::write(path.join("pseudonym.key"), "pseudonym-key-of-the-test-server").unwrap();
+        Arc::new(Workdir { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn journal(&self) -> PathBuf {
+        self.path.join("users.journal")
+    }
+
+    pub fn keys(&self) -> PathBuf {
+        self.path.join("keys.json")
+    }
+
+    pub fn signing_key(&self) -> PathBuf {
+        self.path.join("signing.key")
+    }
+
+    pub fn pseudonym_key(&self) -> PathBuf {
+        self.path.join("pseudonym.key")
+    }
+
+    pub fn audit(&self) -> PathBuf {
+        self.path.join("audit.journal")
+    }
+}
+
+impl Drop for Workdir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+pub struct Server {
+    child: Child,
+    pub addr: String,
+    pub workdir: Arc<Workdir>,
+    logs: Arc<Mutex<Vec<Value>>>,
+}
+
+/// Status, headers (lowercased names) and body of a response.
+pub struct Answer {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub text: String,
+}
+
+impl Answer {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// The body as JSON, or `null` when it is not.
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.text).unwrap_or(Value::Null)
+    }
+}
+
+impl Server {
+    /// Starts a server in a new working directory.
+    pub fn start(args: &[&str]) -> Self {
+        Server::start_in(Workdir::new(), args)
+    }
+
+    /// Starts a server with the journal and keys of `workdir`, as `command`
+    /// runs it.
+    pub fn start_in(workdir: Arc<Workdir>, args: &[&str]) -> Self {
+        let command = Server::command(&workdir, args);
+        Server::spawn(workdir, command)
+    }
+
+    /// The server command with the journal and keys of `workdir`. Journal
+    /// storage, the keys file, JSON logs and a disabled rate limiter are the
+    /// defaults for whatever `args` does not set.
+    pub fn command(workdir: &Workdir, args: &[&str]) -> Command {
+        let sets = |flag: &str| args.contains(&flag);
+        let mut command = Command::new(env!("CARGO_BIN_EXE_implementation"));
+        command.args(["--bind", "127.0.0.1:0", "--log-format", "json"]);
+        if !sets("--storage-backend") {
+            command.args(["--storage-backend", "journal"]);
+            command.arg("--storage-path").arg(workdir.journal());
+        }
+        if !sets("--api-keys-file") && !sets("--auth-open") {
+            command.arg("--api-keys-file").arg(workdir.keys());
+        }
+        if !sets("--signing-key-file") && !sets("--auth-open") {
+            command.arg("--signing-key-file").arg(workdir.signing_key());
+            command.arg("--pseudonym-key-file").arg(workdir.pseudonym_key());
+        }
+        if !sets("--rate-limit-enabled") {
+            command.args(["--rate-limit-enabled", "false"]);
+        }
+        command.args(args).env_clear();
+        command
+    }
+
+    /// Runs `command`, which has to start a server logging JSON to standard
+    /// output, and waits until it listens.
+    pub fn spawn(workdir: Arc<Workdir>, mut command: Command) -> Self {
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .unwrap();
+
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let (listening, addr) = mpsc::channel();
+        collect_logs(child.stdout.take().unwrap(), logs.clone(), listening);
+        let addr = match addr.recv_timeout(Duration::from_secs(10)) {
+            Ok(addr) => addr,
+            Err(_) => {
+                let _ = child.kill();
+                panic!("server did not start: {:?}", logs.lock().unwrap());
+            }
+        };
+        Server {
+            child,
+            addr,
+            workdir,
+            logs,
+        }
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Log lines written so far, as parsed JSON.
+    pub fn logs(&self) -> Vec<Value> {
+        self.logs.lock().unwrap().clone()
+    }
+
+    pub fn connect(&self) -> Option<TcpStream> {
+        let stream = TcpStream::connect(&self.addr).ok()?;
+        stream.set_read_timeout(Some(Duration::from_secs(10))).ok()?;
+        Some(stream)
+    }
+
+    /// Sends `raw` as is and returns the whole response, or None if the server
+    /// does not answer.
+    pub fn raw(&self, raw: &str) -> Option<String> {
+        let mut stream = self.connect()?;
+        stream.write_all(raw.as_bytes()).ok()?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).ok()?;
+        Some(response)
+    }
+
+    /// Sends a request with the given API key, if any, and extra headers.
+    pub fn send(
+        &self,
+        key: Option<&str>,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Option<&str>,
+    ) -> Answer {
+        let mut request = format!("{method} {path} HTTP/1.1\r\nHost: test\r\nConnection: close\r\n");
+        if let Some(key) = key {
+            request.push_str(&format!("Authorization: Bearer {key}\r\n"));
+        }
+        for (name, value) in headers {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if let Some(body) = body {
+            if !headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-type")) {
+                request.push_str("Content-Type: application/json\r\n");
+            }
+            request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        request.push_str("\r\n");
+        request.push_str(body.unwrap_or_default());
+        let response = self.raw(&request).expect("server answers");
+        parse(&response)
+    }
+
+    /// Sends a request with the admin key and a JSON body.
+    pub fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Answer {
+        self.request_as(Some(ADMIN_KEY), method, path, body)
+    }
+
+    pub fn request_as(&self, key: Option<&str>, method: &str, path: &str, body: Option<&Value>) -> Answer {
+        let body = body.map(Value::to_string);
+        self.send(key, method, path, &[], body.as_deref())
+    }
+
+    /// Creates a user with the admin key and returns its id.
+    pub fn create_user(&self, user: Value) -> String {
+        let created = self.request("POST", "/api/users", Some(&json!({"requestHeader": header(), "user": user})));
+        assert_eq!(created.status, 201, "create: {}", created.text);
+        created.json()["user"]["id"].as_str().unwrap().to_owned()
+    }
+
+    pub fn signal(&self, signal: &str) {
+        let status = Command::new("kill")
+            .args([&format!("-{signal}"), &self.child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    /// Waits up to `timeout` for the server to exit.
+    pub fn wait(&mut self, timeout: Duration) -> ExitStatus {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "server did not exit");
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Kills the server without letting it flush or shut down.
+    pub fn crash(mut self) -> Arc<Workdir> {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        self.workdir.clone()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Keeps every log line and sends the address of the "listening" line.
+fn collect_logs(stdout: ChildStdout, logs: Arc<Mutex<Vec<Value>>>, listening: mpsc::Sender<String>) {
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            let Ok(entry) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            if entry["fields"]["message"] == "listening" {
+                if let Some(addr) = entry["fields"]["addr"].as_str() {
+                    let _ = listening.send(addr.to_owned());
+                }
+            }
+            logs.lock().unwrap().push(entry);
+        }
+    });
+}
+
+pub fn parse(response: &str) -> Answer {
+    let (head, text) = response.split_once("\r\n\r\n").unwrap_or((response, ""));
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse().ok())
+        .unwrap_or_else(|| panic!("not an HTTP response: {response:?}"));
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.trim().to_owned()))
+        .collect::<Vec<_>>();
+    let chunked = headers
+        .iter()
+        .any(|(name, value)| name == "transfer-encoding" && value == "chunked");
+    Answer {
+        status,
+        headers,
+        text: if chunked { dechunk(text) } else { text.to_owned() },
+    }
+}
+
+/// The body of a `Transfer-Encoding: chunked` response.
+fn dechunk(mut text: &str) -> String {
+    let mut body = String::new();
+    while let Some((size, rest)) = text.split_once("\r\n") {
+        let size = usize::from_str_radix(size.trim(), 16).unwrap();
+        if size == 0 {
+            break;
+        }
+        body.push_str(&rest[..size]);
+        text = &rest[size + 2..];
+    }
+    body
+}
+
+/// Runs the server binary to completion, as for a configuration error.
+pub fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_implementation"))
+        .args(args)
+        .env_clear()
+        .output()
+        .unwrap()
+}
+
+/// Runs the admin tool with only the given environment variables.
+pub fn admin(args: &[&str], env: &[(&str, &Path)]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_admin"))
+        .args(args)
+        .env_clear()
+        .envs(env.iter().copied())
+        .output()
+        .unwrap()
+}
+
+pub fn header() -> Value {
+    json!({"requestId": "6ba7b810-9dad-11d1-80b4-00c04fd430c8", "sendDate": "2024-01-01T00:00:00Z"})
+}
+
+/// Polls `check` every 50ms until it returns something, for up to ten seconds.
+pub fn eventually<T>(what: &str, mut check: impl FnMut() -> Option<T>) -> T {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        if let Some(found) = check() {
+            return found;
+        }
+        assert!(Instant::now() < deadline, "timed out waiting for {what}");
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// A request taken by `receiver`, with lowercased header names.
+pub struct Received {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+/// Listens on a port of its own, answers the n-th request with `statuses[n]`
+/// and passes every request on. Returns the address it listens on.
+pub fn receiver(statuses: Vec<u16>) -> (String, mpsc::Receiver<Received>) {
+    responder(statuses.into_iter().map(|status| (status, String::new())).collect())
+}
+
+/// Like `receiver`, answering with a JSON body as well.
+pub fn responder(responses: Vec<(u16, String)>) -> (String, mpsc::Receiver<Received>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let (sender, received) = mpsc::channel();
+    thread::spawn(move || {
+        for (stream, (status, body)) in listener.incoming().zip(responses) {
+            let mut stream = stream.unwrap();
+            let request = read_request(&mut stream);
+            write!(
+                stream,
+                "HTTP/1.1 {status} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            if sender.send(request).is_err() {
+                return;
+            }
+        }
+    });
+    (addr, received)
+}
+
+/// Accepts connections and never answers. Returns the address and the number
+/// of connections taken so far.
+pub fn silent() -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let count = accepted.clone();
+    thread::spawn(move || {
+        let mut open = Vec::new();
+        for stream in listener.incoming() {
+            count.fetch_add(1, Ordering::SeqCst);
+            open.push(stream);
+        }
+    });
+    (addr, accepted)
+}
+
+fn read_request(stream: &mut TcpStream) -> Received {
+    let mut reader = BufReader::new(stream);
+    let mut headers = HashMap::new();
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let mut request_line = line.split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let path = request_line.next().unwrap_or_default().to_owned();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        let Some((name, value)) = line.trim_end().split_once(": ") else {
+            break;
+        };
+        headers.insert(name.to_ascii_lowercase(), value.to_owned());
+    }
+    let length = headers.get("content-length").map_or(0, |l| l.parse().unwrap());
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    Received {
+        method,
+        path,
+        headers,
+        body: String::from_utf8(body).unwrap(),
+    }
+}
+
+/// An address nothing listens on.
+pub fn closed_port() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
@@ -0,0 +1,61 @@
+//! The command of the container image: docker/entrypoint.sh next to the
+//! binaries and docker/config.toml, as the Dockerfile lays them out.
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use common::{Server, Workdir};
+
+fn image(workdir: &Workdir) -> PathBuf {
+    let app = workdir.path().join("app");
+    fs::create_dir_all(&app).unwrap();
+    symlink(env!("CARGO_BIN_EXE_implementation"), app.join("implementation")).unwrap();
+    symlink(env!("CARGO_BIN_EXE_admin"), app.join("admin")).unwrap();
+    for file in ["config.toml", "entrypoint.sh"] {
+        fs::copy(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("docker").join(file), app.join(file)).unwrap();
+    }
+    app.join("entrypoint.sh")
+}
+
+fn start(workdir: &Arc<Workdir>, entrypoint: &Path) -> Server {
+    let mut command = Command::new(entrypoint);
+    command
+        .env_clear()
+        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+        .env("USERS_DATA_DIR", workdir.path().join("data"))
+        .env("USERS_BIND", "127.0.0.1:0");
+    Server::spawn(workdir.clone(), command)
+}
+
+#[test]
+fn the_container_command_starts_with_an_empty_volume_and_keeps_its_keys() {
+    let workdir = Workdir::new();
+    let entrypoint = image(&workdir);
+    let data = workdir.path().join("data");
+
+    let server = start(&workdir, &entrypoint);
+    let ready = server.send(None, "GET", "/health/ready", &[], None);
+    assert_eq!(ready.status, 200, "{}", ready.text);
+    assert_eq!(server.send(None, "GET", "/api/users", &[], None).status, 401);
+    assert!(data.join("users.journal").exists());
+    let api_keys = fs::read(data.join("api-keys.json")).unwrap();
+    let keys: Value = serde_json::from_slice(&api_keys).unwrap();
+    assert_eq!(keys[0]["subject"], "admin", "{keys}");
+    assert_eq!(keys[0]["scopes"], json!(["admin"]));
+    let signing_key = fs::read(data.join("keys/signing.key")).unwrap();
+    assert_eq!(signing_key.len(), 32);
+    drop(server);
+
+    // A restart finds the volume set up and changes nothing in it.
+    let server = start(&workdir, &entrypoint);
+    assert_eq!(server.send(None, "GET", "/health/ready", &[], None).status, 200);
+    assert_eq!(fs::read(data.join("api-keys.json")).unwrap(), api_keys);
+    assert_eq!(fs::read(data.join("keys/signing.key")).unwrap(), signing_key);
+}
@@ -0,0 +1,108 @@
+//! Near-duplicate scoring, merging duplicates, and what becomes of the ids
+//! merged away: GetUserById redirects them, following merges of merges, and
+//! erasing the survivor erases them too.
+
+mod common;
+
+use implementation::duplicates::candidates;
+use openapi::models::User;
+use serde_json::{json, Value};
+
+use common::{header, Server};
+
+fn user(name: &str, surname: &str, personal_id: &str, email: Option<&str>, age: u32) -> User {
+    let mut user = User::new(name.into(), surname.into(), age, personal_id.into(), "PL".into());
+    user.email = email.map(str::to_owned);
+    user
+}
+
+#[test]
+fn pairs_score_by_weighted_field_similarity() {
+    let users = [
+        user("Adam", "Mickiewicz", "98122412345", Some("adam@example.com"), 30),
+        // Two neighbouring digits of the personal id swapped, a year older.
+        user("Adam", "Mickiewicz", "98122412354", Some("ADAM@example.com"), 31),
+        user("Juliusz", "Słowacki", "09240112345", Some("juliusz@example.com"), 40),
+    ];
+
+    let found = candidates(users.iter(), 0.8);
+    assert_eq!(found.len(), 1);
+    let pair = &found[0];
+    // 0.2 name + 0.2 surname + 0.3 × 0.8 personal id + 0.2 email + 0.1 × 0.5 age
+    assert!((pair.score - 0.89).abs() < 1e-9, "{}", pair.score);
+    assert_eq!(pair.reasons, ["same_name", "same_surname", "similar_personal_id", "same_email"]);
+    assert_eq!(pair.first.personal_id, "98122412345");
+    assert!(candidates(users.iter(), 0.9).is_empty());
+
+    // Names are compared without case and diacritics: 0.2 + 0.2, no personal id,
+    // 0.2 × 0.5 for a missing email and 0.1 for the same age.
+    let folded = [
+        user("Łukasz", "Żółkiewski", "98122412345", None, 30),
+        user("lukasz", "zolkiewski", "90010112345", None, 30),
+    ];
+    let found = candidates(folded.iter(), 0.0);
+    assert_eq!(found.len(), 1);
+    assert!((found[0].score - 0.6).abs() < 1e-9, "{}", found[0].score);
+    assert!(found[0].reasons.contains(&"missing_email"), "{:?}", found[0].reasons);
+}
+
+fn create(server: &Server, surname: &str, email: &str) -> String {
+    server.create_user(json!({
+        "name": "Adam", "surname": surname, "age": 30, "personalId": "98122412345",
+        "citizenship": "PL", "email": email
+    }))
+}
+
+fn merge(server: &Server, survivor: &str, retired: &str) -> Value {
+    let merged = server.request(
+        "POST",
+        &format!("/api/users/{survivor}/merge"),
+        Some(&json!({"requestHeader": header(), "retiredId": retired})),
+    );
+    assert_eq!(merged.status, 200, "merge: {}", merged.text);
+    merged.json()
+}
+
+#[test]
+fn merged_ids_redirect_to_the_surviving_user() {
+    let server = Server::start(&[]);
+    let first = create(&server, "Mickiewicz", "adam@example.com");
+    let second = create(&server, "Mickiewicz", "adam@example.com");
+    let third = create(&server, "Mickiewicz", "adam.m@example.com");
+
+    let found = server.request("GET", "/api/users/duplicates", None).json();
+    let pairs = found["candidates"].as_array().unwrap();
+    assert!(
+        pairs.iter().any(|c| c["score"] == 1.0 && [&c["first"]["id"], &c["second"]["id"]].contains(&&json!(first))),
+        "{found}"
+    );
+
+    let merged = merge(&server, &second, &first);
+    assert_eq!(merged["user"]["id"], second.as_str());
+    let moved = server.request("GET", &format!("/api/users/{first}"), None);
+    assert_eq!(moved.status, 301);
+    assert_eq!(moved.header("location"), Some(format!("/api/users/{second}").as_str()));
+    assert_eq!(moved.json()["code"], "USER_MERGED");
+    assert_eq!(moved.json()["mergedInto"], second.as_str());
+    let moved = server.request("GET", &format!("/api/v2/users/{first}"), None);
+    assert_eq!(moved.status, 301);
+    assert_eq!(moved.header("location"), Some(format!("/api/v2/users/{second}").as_str()));
+
+    // Only GetUserById redirects; the merged-away id keeps its own history.
+    let revisions = server.request("GET", &format!("/api/users/{first}/revisions"), None);
+    assert_eq!(revisions.status, 200, "{}", revisions.text);
+    assert_eq!(revisions.json()["revisions"][1]["kind"], "merged_into");
+
+    // Merges of merges lead to the last survivor.
+    merge(&server, &third, &second);
+    let moved = server.request("GET", &format!("/api/users/{first}"), None);
+    assert_eq!(moved.status, 301);
+    assert_eq!(moved.header("location"), Some(format!("/api/users/{third}").as_str()));
+
+    // Erasing the survivor erases what was merged into it, directly or not.
+    assert_eq!(server.request("POST", &format!("/api/users/{third}/erasure"), None).status, 200);
+    for id in [&first, &second] {
+        let revisions = server.request("GET", &format!("/api/users/{id}/revisions/1"), None).json();
+        assert!(revisions["revision"].get("user").is_none(), "{id}: {revisions}");
+    }
+}
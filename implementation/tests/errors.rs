@@ -0,0 +1,94 @@
+//! Operations that fail answer 500 or 503 with an `Error` body whose request id
+//! correlates the answer with the server log, and with nothing of the cause.
+
+mod common;
+
+use std::io;
+
+use axum::body::to_bytes;
+use axum::response::{IntoResponse, Response};
+use implementation::error::{ApiError, ErrorKind};
+use implementation::store::StoreError;
+use serde_json::{json, Value};
+
+use common::Server;
+
+fn body(response: Response) -> Value {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let bytes = runtime.block_on(to_bytes(response.into_body(), usize::MAX)).unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[test]
+fn storage_failures_answer_503_without_the_cause() {
+    let error = ApiError::from(StoreError::Io(io::Error::other("No space left on /var/lib/users")));
+    assert_eq!(error.kind(), ErrorKind::Unavailable);
+    assert_eq!(format!("{error:?}"), "user store failure: journal: No space left on /var/lib/users");
+
+    let response = error.into_response();
+    assert_eq!(response.status(), 503);
+    let body = body(response);
+    assert_eq!(body["code"], "SERVICE_UNAVAILABLE");
+    assert!(body["responseHeader"]["requestId"].is_string(), "{body}");
+    assert!(!body.to_string().contains("/var/lib/users"), "{body}");
+}
+
+#[test]
+fn bugs_answer_500_and_keep_the_cause_chain() {
+    let error = ApiError::internal("stage user", ApiError::from(StoreError::Exists));
+    assert_eq!(error.kind(), ErrorKind::Internal);
+    assert_eq!(format!("{error:?}"), "stage user: user store failure: user already exists");
+
+    let response = error.into_response();
+    assert_eq!(response.status(), 500);
+    let body = body(response);
+    assert_eq!(body["code"], "INTERNAL_ERROR");
+    assert!(body.get("message").is_none(), "{body}");
+}
+
+#[test]
+fn timeouts_answer_503_with_retry_after() {
+    let response = ApiError::timeout("slow", io::Error::other("deadline has elapsed")).into_response();
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.headers()["retry-after"], "5");
+    assert_eq!(body(response)["code"], "TIMEOUT");
+}
+
+#[test]
+fn a_store_failure_reaches_the_client_as_503_with_a_correlation_id() {
+    let server = Server::start(&[]);
+    let user = json!({"name": "Adam", "surname": "Mickiewicz", "age": 30, "personalId": "98122412345", "citizenship": "PL"});
+    let id = server.create_user(user);
+    // Erasure rewrites the journal through a temporary file next to it.
+    std::fs::create_dir(server.workdir.path().join("users.compact")).unwrap();
+
+    let failed = server.request("POST", &format!("/api/users/{id}/erasure"), None);
+    assert_eq!(failed.status, 503);
+    assert_eq!(failed.header("retry-after"), Some("5"));
+    let body = failed.json();
+    assert_eq!(body["code"], "SERVICE_UNAVAILABLE");
+    assert!(!failed.text.contains("users.compact"), "{}", failed.text);
+    let correlation_id = body["responseHeader"]["requestId"].as_str().unwrap().to_owned();
+
+    let logged = common::eventually("the failure in the log", || {
+        server
+            .logs()
+            .into_iter()
+            .find(|l| l["fields"]["correlation_id"] == correlation_id.as_str())
+    });
+    assert_eq!(logged["fields"]["status"], 503);
+    assert!(logged["fields"]["error"].as_str().unwrap().contains("user store failure: journal"), "{logged}");
+}
+
+#[test]
+fn a_request_not_answered_in_time_gets_503_timeout() {
+    let server = Server::start(&["--request-timeout-secs", "1"]);
+    // The body never arrives, so the handler waits for it until the timeout.
+    let response = server
+        .raw("POST /api/users HTTP/1.1\r\nHost: test\r\nAuthorization: Bearer admin-secret\r\nContent-Type: application/json\r\nContent-Length: 100\r\n\r\n{")
+        .expect("server answers");
+    let answer = common::parse(&response);
+    assert_eq!(answer.status, 503, "{response}");
+    assert_eq!(answer.header("retry-after"), Some("5"));
+    assert_eq!(answer.json()["code"], "TIMEOUT");
+}
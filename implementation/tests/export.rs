@@ -0,0 +1,55 @@
+//! Exports stream users masked like the change feed unless the caller has the
+//! `pii` scope, and need the same scopes as listing users.
+
+mod common;
+
+use common::{Server, AUDITOR_KEY, READER_KEY, WRITER_KEY};
+use serde_json::{json, Value};
+
+fn export(server: &Server, key: Option<&str>, query: &str) -> common::Answer {
+    server.send(key, "GET", &format!("/api/users/export{query}"), &[], None)
+}
+
+fn rows(answer: &common::Answer) -> Vec<Value> {
+    answer.text.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+#[test]
+fn exports_are_masked_without_the_pii_scope() {
+    let server = Server::start(&[]);
+    server.create_user(json!({
+        "name": "Adam", "surname": "Mickiewicz", "age": 30, "personalId": "98122412345",
+        "citizenship": "PL", "email": "adam@example.com"
+    }));
+
+    let masked = export(&server, Some(READER_KEY), "");
+    assert_eq!(masked.status, 200, "{}", masked.text);
+    let user = &rows(&masked)[0];
+    assert_eq!(user["name"], "A***");
+    assert_eq!(user["surname"], "M***");
+    assert_eq!(user["personalId"], "*******2345");
+    assert_eq!(user["email"], "a***@example.com");
+
+    let csv = export(&server, Some(READER_KEY), "?format=csv");
+    assert!(!csv.text.contains("Mickiewicz"), "{}", csv.text);
+
+    let unmasked = export(&server, Some(AUDITOR_KEY), "");
+    assert_eq!(rows(&unmasked)[0]["personalId"], "98122412345");
+}
+
+#[test]
+fn reading_users_needs_the_read_scope_everywhere() {
+    let server = Server::start(&[]);
+    let id = server.create_user(json!({
+        "name": "Adam", "surname": "Mickiewicz", "age": 30, "personalId": "98122412345", "citizenship": "PL"
+    }));
+
+    for path in ["/api/users".to_owned(), format!("/api/users/{id}"), "/api/users/export".to_owned()] {
+        let answer = server.send(None, "GET", &path, &[], None);
+        assert_eq!(answer.status, 401, "{path} without a key");
+        let answer = server.send(Some(READER_KEY), "GET", &path, &[], None);
+        assert_eq!(answer.status, 200, "{path} with the read scope");
+    }
+    assert_eq!(export(&server, Some(WRITER_KEY), "?includeDeleted=true").status, 403);
+    assert_eq!(export(&server, Some(AUDITOR_KEY), "?includeDeleted=true").status, 200);
+}
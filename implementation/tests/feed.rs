@@ -0,0 +1,65 @@
+//! The change feed masks personal data unless the caller was granted `pii`,
+//! which open mode never grants, and resumes after the `Last-Event-ID` sent
+//! by a reconnecting client.
+
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+
+use common::{Server, ADMIN_KEY, AUDITOR_KEY};
+use serde_json::{json, Value};
+
+fn user(surname: &str) -> Value {
+    json!({"name": "Adam", "surname": surname, "age": 30, "personalId": "98122412345", "citizenship": "PL"})
+}
+
+/// The first `count` events of the SSE feed, as (id, data).
+fn events(server: &Server, key: Option<&str>, headers: &str, count: usize) -> Vec<(u64, Value)> {
+    let mut stream = server.connect().unwrap();
+    let auth = key.map(|key| format!("Authorization: Bearer {key}\r\n")).unwrap_or_default();
+    write!(stream, "GET /api/users/changes?after=0 HTTP/1.1\r\nHost: test\r\n{auth}{headers}\r\n").unwrap();
+    let mut events = Vec::new();
+    let (mut id, mut data) = (None, None);
+    for line in BufReader::new(stream).lines() {
+        let line = line.unwrap();
+        if let Some(value) = line.strip_prefix("data:") {
+            data = Some(serde_json::from_str(value.trim()).unwrap());
+        } else if let Some(value) = line.strip_prefix("id:") {
+            id = Some(value.trim().parse().unwrap());
+        } else if line.is_empty() {
+            if let (Some(id), Some(data)) = (id.take(), data.take()) {
+                events.push((id, data));
+            }
+            if events.len() == count {
+                break;
+            }
+        }
+    }
+    events
+}
+
+#[test]
+fn open_mode_feed_is_masked_and_resumes_after_the_last_event_id() {
+    let server = Server::start(&["--auth-open", "true"]);
+    for surname in ["Mickiewicz", "Slowacki", "Norwid"] {
+        server.create_user(user(surname));
+    }
+
+    let all = events(&server, None, "", 3);
+    assert_eq!(all.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [1, 2, 3]);
+    let first = &all[0].1["user"];
+    assert_eq!(first["surname"], "M***", "{first}");
+    assert_eq!(first["personalId"], "*******2345");
+
+    let resumed = events(&server, None, "Last-Event-ID: 1\r\n", 2);
+    assert_eq!(resumed.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [2, 3]);
+    assert_eq!(resumed[0].1["user"]["surname"], "S***");
+}
+
+#[test]
+fn only_the_pii_scope_unmasks_the_feed() {
+    let server = Server::start(&[]);
+    server.create_user(user("Mickiewicz"));
+    assert_eq!(events(&server, Some(ADMIN_KEY), "", 1)[0].1["user"]["surname"], "M***");
+    assert_eq!(events(&server, Some(AUDITOR_KEY), "", 1)[0].1["user"]["surname"], "Mickiewicz");
+}
@@ -0,0 +1,72 @@
+//! Seed data and the validation it goes through.
+
+mod common;
+
+use std::path::Path;
+
+use implementation::synthetic::{self, Generator};
+use openapi::models::User;
+use validator::Validate;
+
+use common::{Server, AUDITOR_KEY};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/users.ndjson");
+
+fn valid_pesel(personal_id: &str) -> bool {
+    personal_id.len() == 11
+        && personal_id.bytes().all(|b| b.is_ascii_digit())
+        && personal_id.as_bytes()[10] - b'0' == synthetic::checksum(&personal_id[..10])
+}
+
+#[test]
+fn fixture_users_are_valid_and_have_correct_pesels() {
+    let users = implementation::fixtures::load(Path::new(FIXTURE)).unwrap();
+    assert!(!users.is_empty());
+    for user in users {
+        assert!(user.validate().is_ok(), "{user:?}");
+        assert!(valid_pesel(&user.personal_id), "{}", user.personal_id);
+    }
+}
+
+#[test]
+fn synthetic_users_are_valid_and_have_correct_pesels() {
+    let mut generator = Generator::new(Some(42));
+    for _ in 0..200 {
+        let user = generator.user();
+        assert!(user.validate().is_ok(), "{user:?}");
+        assert!(valid_pesel(&user.personal_id), "{}", user.personal_id);
+    }
+}
+
+#[test]
+fn emails_are_checked_against_the_documented_pattern() {
+    let with_email = |email: &str| User {
+        email: Some(email.into()),
+        ..User::new("Adam".into(), "Mickiewicz".into(), 30, "12345678903".into(), "PL".into())
+    };
+    for email in ["mickiewicz@o2.pl", "adam.mickiewicz@poczta.example.com", "a-b_c@x-y.info"] {
+        assert!(with_email(email).validate().is_ok(), "{email}");
+    }
+    for email in ["mickiewicz", "mickiewicz@o2", "a\\b@o2.pl", "mickiewicz@o2.museum"] {
+        assert!(with_email(email).validate().is_err(), "{email}");
+    }
+}
+
+#[test]
+fn an_empty_store_is_seeded_from_the_fixture_file() {
+    let server = Server::start(&["--seed-file", FIXTURE]);
+    let seeded = server.request_as(Some(AUDITOR_KEY), "GET", "/api/users/67e55044-10b1-426f-9247-bb680e5fe0c8", None);
+    assert_eq!(seeded.status, 200, "{}", seeded.text);
+    assert_eq!(seeded.json()["user"]["personalId"], "12345678903");
+}
+
+#[test]
+fn a_seeded_store_is_not_seeded_again() {
+    let server = Server::start(&["--seed-synthetic-users", "3", "--seed-random-seed", "7"]);
+    let listed = server.request("GET", "/api/users", None).json();
+    assert_eq!(listed["usersList"].as_array().unwrap().len(), 3);
+
+    let server = Server::start_in(server.crash(), &["--seed-synthetic-users", "3", "--seed-random-seed", "8"]);
+    let listed = server.request("GET", "/api/users", None).json();
+    assert_eq!(listed["usersList"].as_array().unwrap().len(), 3);
+}
@@ -0,0 +1,63 @@
+//! Readiness reports each check: the store answering, its journal still
+//! being writable and the keys being configured.
+
+mod common;
+
+use std::fs::{self, Permissions};
+use std::os::unix::fs::PermissionsExt;
+
+use common::Server;
+
+#[test]
+fn readiness_reports_a_journal_that_can_no_longer_be_written() {
+    let server = Server::start(&[]);
+    server.create_user(serde_json::json!({
+        "name": "Adam", "surname": "Mickiewicz", "age": 30, "personalId": "98122412345", "citizenship": "PL"
+    }));
+
+    let ready = server.send(None, "GET", "/health/ready", &[], None);
+    assert_eq!(ready.status, 200, "{}", ready.text);
+    let report = ready.json();
+    assert_eq!(report["checks"]["storage"]["status"], "up");
+    assert_eq!(report["checks"]["journal"]["status"], "up");
+    assert_eq!(report["checks"]["keys"]["status"], "up");
+    assert_eq!(report["checks"]["shutdown"]["status"], "up");
+    assert_eq!(report["checks"].as_object().unwrap().len(), 4, "{report}");
+
+    let journal = server.workdir.journal();
+    fs::set_permissions(&journal, Permissions::from_mode(0o444)).unwrap();
+    let ready = server.send(None, "GET", "/health/ready", &[], None);
+    assert_eq!(ready.status, 503, "{}", ready.text);
+    let report = ready.json();
+    assert_eq!(report["status"], "down");
+    assert_eq!(report["checks"]["journal"]["status"], "down");
+    assert_eq!(report["checks"]["storage"]["status"], "up");
+
+    fs::set_permissions(&journal, Permissions::from_mode(0o644)).unwrap();
+    fs::remove_file(&journal).unwrap();
+    let ready = server.send(None, "GET", "/health/ready", &[], None);
+    assert_eq!(ready.status, 503, "{}", ready.text);
+    assert_eq!(ready.json()["checks"]["journal"]["status"], "down");
+}
+
+#[test]
+fn readiness_without_a_journal_checks_the_store_only() {
+    let server = Server::start(&["--storage-backend", "memory"]);
+    let ready = server.send(None, "GET", "/health/ready", &[], None);
+    assert_eq!(ready.status, 200, "{}", ready.text);
+    let report = ready.json();
+    assert_eq!(report["checks"]["journal"]["detail"], "no journal configured");
+    assert_eq!(report["checks"]["storage"]["status"], "up");
+}
+
+#[test]
+fn readiness_with_ephemeral_keys_is_degraded() {
+    let server = Server::start(&["--auth-open", "true"]);
+    let ready = server.send(None, "GET", "/health/ready", &[], None);
+    assert_eq!(ready.status, 200, "{}", ready.text);
+    let report = ready.json();
+    assert_eq!(report["status"], "degraded");
+    assert_eq!(report["checks"]["keys"]["status"], "degraded");
+    let detail = report["checks"]["keys"]["detail"].as_str().unwrap();
+    assert!(detail.contains("auth.signing_key and auth.pseudonym_key"), "{detail}");
+}
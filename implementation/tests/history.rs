@@ -0,0 +1,86 @@
+//! Version history: numbered revisions with their time and actor, single
+//! revisions, diffs between two of them, and the collection as of a moment,
+//! all for administrators only.
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+use common::{header, Server, READER_KEY, WRITER_KEY};
+
+fn user(surname: &str) -> Value {
+    json!({"name": "Adam", "surname": surname, "age": 30, "personalId": "98122412345", "citizenship": "PL"})
+}
+
+/// Now, with a moment's gap on either side so that it falls between two changes.
+fn between() -> String {
+    thread::sleep(Duration::from_millis(20));
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+    thread::sleep(Duration::from_millis(20));
+    now
+}
+
+fn surnames_as_of(server: &Server, at: &str) -> Vec<String> {
+    let answer = server.request("GET", &format!("/api/users/history?asOf={at}"), None);
+    assert_eq!(answer.status, 200, "{}", answer.text);
+    answer.json()["usersList"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|u| u["surname"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+#[test]
+fn every_change_is_kept_as_a_numbered_revision() {
+    let server = Server::start(&[]);
+    let before = between();
+    let id = server.create_user(user("Mickiewicz"));
+    let created = between();
+    let path = format!("/api/users/{id}");
+    let body = json!({"requestHeader": header(), "user": user("Krasiński")});
+    assert_eq!(server.request_as(Some(WRITER_KEY), "PUT", &path, Some(&body)).status, 200);
+    let updated = between();
+    assert_eq!(server.request_as(Some(WRITER_KEY), "DELETE", &path, None).status, 204);
+
+    let revisions = server.request("GET", &format!("{path}/revisions"), None).json();
+    let revisions = revisions["revisions"].as_array().unwrap();
+    let listed: Vec<_> = revisions
+        .iter()
+        .map(|r| (r["revision"].as_u64().unwrap(), r["kind"].as_str().unwrap(), r["actor"].as_str().unwrap()))
+        .collect();
+    assert_eq!(listed, [(1, "created", "admin"), (2, "updated", "writer"), (3, "deleted", "writer")]);
+    assert!(revisions.iter().all(|r| r.get("user").is_none()), "{revisions:?}");
+    let times: Vec<DateTime<Utc>> = revisions.iter().map(|r| r["at"].as_str().unwrap().parse().unwrap()).collect();
+    let (created_at, updated_at): (DateTime<Utc>, DateTime<Utc>) = (created.parse().unwrap(), updated.parse().unwrap());
+    assert!(created_at > times[0] && created_at < times[1] && updated_at < times[2], "{times:?}");
+
+    let second = server.request("GET", &format!("{path}/revisions/2"), None).json();
+    assert_eq!(second["revision"]["user"]["surname"], "Krasiński");
+    assert_eq!(server.request("GET", &format!("{path}/revisions/4"), None).status, 404);
+
+    let diff = server.request("GET", &format!("{path}/revisions/diff?from=1&to=2"), None).json();
+    assert_eq!(diff["changes"], json!([{"field": "surname", "from": "Mickiewicz", "to": "Krasiński"}]));
+    assert_eq!(server.request("GET", &format!("{path}/revisions/diff?from=1&to=4"), None).status, 404);
+
+    assert!(surnames_as_of(&server, &before).is_empty());
+    assert_eq!(surnames_as_of(&server, &created), ["Mickiewicz"]);
+    assert_eq!(surnames_as_of(&server, &updated), ["Krasiński"]);
+    assert!(surnames_as_of(&server, &between()).is_empty());
+}
+
+#[test]
+fn history_is_for_administrators_only() {
+    let server = Server::start(&[]);
+    let id = server.create_user(user("Mickiewicz"));
+
+    assert_eq!(server.request_as(Some(READER_KEY), "GET", &format!("/api/users/{id}/revisions"), None).status, 403);
+    assert_eq!(server.request_as(Some(READER_KEY), "GET", &format!("/api/users/{id}/revisions/1"), None).status, 403);
+    let at = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    assert_eq!(server.request_as(Some(READER_KEY), "GET", &format!("/api/users/history?asOf={at}"), None).status, 403);
+    assert_eq!(server.request("GET", &format!("/api/users/{}/revisions", uuid::Uuid::new_v4()), None).status, 404);
+}
@@ -0,0 +1,81 @@
+//! ImportUsers: per-row reports, all-or-nothing, best-effort and dry runs, and
+//! the byte and row limits of one import.
+
+mod common;
+
+use serde_json::{json, Value};
+
+use common::{Server, ADMIN_KEY};
+
+const NDJSON: (&str, &str) = ("Content-Type", "application/x-ndjson");
+
+fn rows(personal_ids: &[&str]) -> String {
+    personal_ids
+        .iter()
+        .map(|personal_id| {
+            json!({"name": "Adam", "surname": "Mickiewicz", "age": 30, "personalId": personal_id, "citizenship": "PL"})
+                .to_string()
+                + "\n"
+        })
+        .collect()
+}
+
+fn import(server: &Server, query: &str, body: &str) -> (u16, Value) {
+    let answer = server.send(Some(ADMIN_KEY), "POST", &format!("/api/users/import{query}"), &[NDJSON], Some(body));
+    (answer.status, answer.json())
+}
+
+fn count(server: &Server) -> usize {
+    server.request("GET", "/api/users", None).json()["usersList"].as_array().unwrap().len()
+}
+
+#[test]
+fn all_or_nothing_imports_nothing_when_a_row_is_rejected() {
+    let server = Server::start(&[]);
+    // The third row repeats the personal id of the first.
+    let body = rows(&["98122412345", "98122412346", "98122412345"]);
+
+    let (status, answer) = import(&server, "", &body);
+    assert_eq!(status, 422, "{answer}");
+    let report = &answer["report"];
+    assert_eq!(report["committed"], false);
+    assert_eq!((report["accepted"].as_u64(), report["rejected"].as_u64()), (Some(2), Some(1)));
+    assert_eq!(report["rows"][2]["status"], "rejected");
+    assert_eq!(report["rows"][2]["errors"][0], "personalId: same as row 1");
+    assert_eq!(count(&server), 0);
+
+    let (status, answer) = import(&server, "?mode=best_effort", &body);
+    assert_eq!(status, 200, "{answer}");
+    assert_eq!(answer["report"]["committed"], true);
+    assert_eq!(count(&server), 2);
+}
+
+#[test]
+fn a_dry_run_writes_nothing() {
+    let server = Server::start(&[]);
+    let (status, answer) = import(&server, "?dryRun=true", &rows(&["98122412345"]));
+    assert_eq!(status, 200, "{answer}");
+    assert_eq!(answer["report"]["committed"], false);
+    assert_eq!(answer["report"]["rows"][0]["status"], "accepted");
+    assert_eq!(count(&server), 0);
+}
+
+#[test]
+fn imports_over_the_row_or_byte_limit_are_refused() {
+    let server = Server::start(&["--max-import-rows", "2", "--max-import-bytes", "1024"]);
+
+    let (status, answer) = import(&server, "", &rows(&["98122412345", "98122412346"]));
+    assert_eq!(status, 200, "{answer}");
+
+    let (status, answer) = import(&server, "?mode=best_effort", &rows(&["98122412347", "98122412348", "98122412349"]));
+    assert_eq!(status, 413, "{answer}");
+    assert_eq!(answer["code"], "TOO_MANY_ROWS");
+
+    let mut large = rows(&["98122412350"]);
+    large.push_str(&" ".repeat(1024));
+    let (status, answer) = import(&server, "", &large);
+    assert_eq!(status, 413, "{answer}");
+    assert_eq!(answer["code"], "PAYLOAD_TOO_LARGE");
+
+    assert_eq!(count(&server), 2);
+}
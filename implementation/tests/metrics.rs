@@ -0,0 +1,51 @@
+//! Requests are counted by the route they matched, never by the path sent, and
+//! store reads are timed like writes.
+
+mod common;
+
+use common::Server;
+
+#[test]
+fn requests_are_labelled_with_the_matched_route() {
+    let server = Server::start(&[]);
+    for id in ["6ba7b810-9dad-11d1-80b4-00c04fd430c8", "6ba7b811-9dad-11d1-80b4-00c04fd430c8"] {
+        assert_eq!(server.request("GET", &format!("/api/users/{id}"), None).status, 404);
+    }
+    assert_eq!(server.request("GET", "/api/users", None).status, 200);
+
+    let metrics = server.request("GET", "/metrics", None).text;
+    let count = |labels: &str| {
+        metrics
+            .lines()
+            .find(|line| line.starts_with(&format!("users_http_requests_total{{{labels}}}")))
+            .and_then(|line| line.rsplit(' ').next())
+            .unwrap_or_else(|| panic!("{labels} not counted:\n{metrics}"))
+            .to_owned()
+    };
+    assert_eq!(
+        count(r#"method="GET",response="Status404_UserNotFound",route="/api/users/:id",status="404""#),
+        "2"
+    );
+    assert_eq!(count(r#"method="GET",response="Status200_Success",route="/api/users",status="200""#), "1");
+    assert!(!metrics.contains("6ba7b810"), "ids leak into labels");
+}
+
+#[test]
+fn hand_written_operations_and_store_reads_are_measured() {
+    // The outbox relay logging events reads the change feed.
+    let server = Server::start(&["--outbox-log-events", "true"]);
+    let body = serde_json::json!({"requestHeader": common::header(), "surname": "Mickiewicz"});
+    assert_eq!(server.request("POST", "/api/users/search", Some(&body)).status, 200);
+    assert_eq!(server.request("GET", "/api/users", None).status, 200);
+    assert_eq!(server.request("GET", "/api/users/6ba7b810-9dad-11d1-80b4-00c04fd430c8", None).status, 404);
+
+    let metrics = server.request("GET", "/metrics", None).text;
+    assert!(
+        metrics.contains(r#"method="POST",response="Status200",route="/api/users/search",status="200""#),
+        "{metrics}"
+    );
+    for operation in ["list", "get", "search", "changes_after"] {
+        let line = format!(r#"users_store_operation_duration_seconds_count{{operation="{operation}"}}"#);
+        assert!(metrics.contains(&line), "{operation} not timed:\n{metrics}");
+    }
+}
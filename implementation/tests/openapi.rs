@@ -0,0 +1,158 @@
+//! Checks the served OpenAPI document against the running server by probing its
+//! router: every documented operation is routed with exactly the documented
+//! methods, the paths leading to them route nothing undocumented, and only the
+//! operations documented without security serve callers without an API key.
+
+mod common;
+
+use std::collections::BTreeSet;
+
+use common::Server;
+use serde_json::Value;
+
+const METHODS: [&str; 4] = ["GET", "POST", "PUT", "DELETE"];
+
+/// Status code, headers and body of a request sent without an API key.
+fn request(server: &Server, method: &str, path: &str) -> (u16, Vec<(String, String)>, String) {
+    let answer = server.send(None, method, path, &[], None);
+    (answer.status, answer.headers, answer.text)
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> &'a str {
+    headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+        .unwrap_or_default()
+}
+
+/// A request path for a documented path, with its parameters filled in.
+fn request_path(path: &str) -> String {
+    path.replace("{id}", "6ba7b810-9dad-11d1-80b4-00c04fd430c8")
+        .replace("{revision}", "1")
+}
+
+/// (method, path, operation id, secured) of every documented operation.
+fn documented_operations(document: &Value) -> Vec<(String, String, String, bool)> {
+    let default_security = document
+        .get("security")
+        .is_some_and(|s| !s.as_array().unwrap().is_empty());
+    let mut operations = Vec::new();
+    for (path, item) in document["paths"].as_object().unwrap() {
+        for method in METHODS {
+            let Some(operation) = item.get(method.to_ascii_lowercase()) else {
+                continue;
+            };
+            let secured = operation
+                .get("security")
+                .map_or(default_security, |s| !s.as_array().unwrap().is_empty());
+            let id = operation["operationId"].as_str().unwrap().to_owned();
+            operations.push((method.to_owned(), path.clone(), id, secured));
+        }
+    }
+    operations
+}
+
+#[test]
+fn served_document_covers_every_route() {
+    let server = Server::start(&[]);
+
+    let (status, headers, body) = request(&server, "GET", "/api/openapi.json");
+    assert_eq!(status, 200);
+    assert_eq!(header(&headers, "content-type"), "application/json");
+    let document: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(&document, openapi::spec::document());
+    assert_eq!(document["info"]["version"], openapi::API_VERSION);
+
+    let (status, headers, body) = request(&server, "GET", "/api/openapi.yaml");
+    assert_eq!(status, 200);
+    assert_eq!(header(&headers, "content-type"), "application/yaml");
+    assert_eq!(body, openapi::spec::yaml());
+
+    let yaml: Value = serde_yaml::from_str(&body).unwrap();
+    assert_eq!(&yaml, openapi::spec::document(), "YAML reads back as the document");
+
+    let documented = documented_operations(&document);
+    for (method, path, id, secured) in &documented {
+        let (status, _, body) = request(&server, method, &request_path(path));
+        assert!(!unrouted(status, &body), "{id} is not routed");
+        if *secured {
+            // Operations with a body may reject the missing body before asking for a key.
+            assert!(!(200..300).contains(&status), "{id} answered {status} without an API key");
+        } else {
+            assert_ne!(status, 401, "{id} needs an API key but is documented without security");
+        }
+    }
+
+    let paths: BTreeSet<&String> = documented.iter().map(|(_, path, _, _)| path).collect();
+    for path in &paths {
+        for method in METHODS {
+            if documented.iter().any(|(m, p, _, _)| m == method && p == *path) {
+                continue;
+            }
+            let (status, _, _) = request(&server, method, &request_path(path));
+            assert_eq!(status, 405, "{method} {path} is routed but not documented");
+        }
+    }
+    // Routes hiding between the documented ones, such as `/api/users/{id}/revisions`
+    // without its own operation, are answered by the router's 404.
+    let prefixes: BTreeSet<String> = paths
+        .iter()
+        .flat_map(|path| {
+            let segments: Vec<&str> = path.split('/').collect();
+            (2..segments.len()).map(move |n| segments[..n].join("/"))
+        })
+        .filter(|prefix| !paths.contains(prefix))
+        .collect();
+    for prefix in prefixes {
+        for method in METHODS {
+            let (status, _, body) = request(&server, method, &request_path(&prefix));
+            assert!(unrouted(status, &body), "{method} {prefix} is routed but not documented");
+        }
+    }
+
+    // The routes the requests above matched, as the metrics label them, are
+    // exactly the documented paths. A scrape is counted once it is answered.
+    request(&server, "GET", "/metrics");
+    let (_, _, metrics) = request(&server, "GET", "/metrics");
+    let matched: BTreeSet<String> = metrics
+        .lines()
+        .filter(|line| line.starts_with("users_http_requests_total{"))
+        .filter_map(|line| line.split("route=\"").nth(1)?.split('"').next())
+        .map(document_path)
+        .collect();
+    assert_eq!(matched, paths.into_iter().cloned().collect::<BTreeSet<_>>());
+}
+
+/// `/api/users/:id` as the document writes it, `/api/users/{id}`.
+fn document_path(route: &str) -> String {
+    route
+        .split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(name) => format!("{{{name}}}"),
+            None => segment.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The router's own answer to an unknown path: a 404 without a body. The
+/// operations' 404s have one.
+fn unrouted(status: u16, body: &str) -> bool {
+    status == 404 && body.is_empty()
+}
+
+#[test]
+fn explorer_is_self_contained() {
+    let server = Server::start(&[]);
+    let (status, headers, body) = request(&server, "GET", "/api/docs");
+    assert_eq!(status, 200);
+    assert!(header(&headers, "content-type").starts_with("text/html"));
+    let policy = header(&headers, "content-security-policy");
+    assert!(policy.starts_with("default-src 'none'"), "{policy}");
+    assert!(policy.contains("script-src 'sha256-"), "{policy}");
+    assert!(!body.contains("{{"), "unfilled template");
+    for reference in ["src=\"http", "href=\"http", "src=\"//", "href=\"//"] {
+        assert!(!body.contains(reference), "loads {reference}");
+    }
+}
@@ -0,0 +1,135 @@
+//! The change feed as an outbox: what is committed reaches the sinks, even across
+//! a crash, and what fails to commit never does.
+
+mod common;
+
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use common::{closed_port, eventually, header, receiver, Server};
+
+const SECRET: &str = "outbox-secret-0123456789";
+
+fn user(surname: &str) -> Value {
+    json!({"name": "Adam", "surname": surname, "age": 30, "personalId": "98122412345", "citizenship": "PL"})
+}
+
+/// Flags relaying changes to a webhook at `addr`, remembering its position in the workdir.
+fn webhook_sink(server_dir: &Path, addr: &str) -> Vec<String> {
+    vec![
+        "--outbox-webhook-url".into(),
+        format!("http://{addr}/events"),
+        "--outbox-webhook-secret".into(),
+        SECRET.into(),
+        "--outbox-position-file".into(),
+        server_dir.join("outbox.position").to_str().unwrap().into(),
+    ]
+}
+
+fn events(path: &Path) -> Vec<Value> {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn changes_committed_before_a_crash_are_delivered_after_restart() {
+    let workdir = common::Workdir::new();
+    let args = webhook_sink(workdir.path(), &closed_port());
+    let server = Server::start_in(workdir, &args.iter().map(String::as_str).collect::<Vec<_>>());
+    let id = server.create_user(user("Mickiewicz"));
+    let revisions = server.request("GET", &format!("/api/users/{id}/revisions"), None).json();
+    let event_id = revisions["revisions"][0]["eventId"].clone();
+
+    let workdir = server.crash();
+    let (addr, received) = receiver(vec![200]);
+    let args = webhook_sink(workdir.path(), &addr);
+    let server = Server::start_in(workdir, &args.iter().map(String::as_str).collect::<Vec<_>>());
+
+    let delivery = received.recv_timeout(Duration::from_secs(10)).expect("delivered after restart");
+    assert_eq!(delivery.path, "/events");
+    let delivered: Value = serde_json::from_str(&delivery.body).unwrap();
+    assert_eq!(delivered["events"][0]["sequence"], 1);
+    assert_eq!(delivered["events"][0]["eventId"], event_id);
+    let position = server.workdir.path().join("outbox.position");
+    eventually("the position to be kept", || {
+        (std::fs::read_to_string(&position).ok()?.trim() == "1").then_some(())
+    });
+}
+
+#[test]
+fn a_change_that_fails_to_commit_is_never_published() {
+    let workdir = common::Workdir::new();
+    let file = workdir.path().join("events.ndjson");
+    let server = Server::start_in(workdir, &["--outbox-file", file.to_str().unwrap()]);
+    let id = server.create_user(user("Mickiewicz"));
+    eventually("the first event", || (events(&file).len() == 1).then_some(()));
+
+    // Erasure compacts the journal through a temporary file next to it.
+    let blocker = server.workdir.path().join("users.compact");
+    std::fs::create_dir(&blocker).unwrap();
+    let failed = server.request("POST", &format!("/api/users/{id}/erasure"), None);
+    assert_eq!(failed.status, 503);
+    assert!(failed.json()["responseHeader"]["requestId"].is_string(), "{}", failed.text);
+    std::fs::remove_dir(&blocker).unwrap();
+
+    // The failed erasure's feed position goes to the next change.
+    server.create_user(user("Norwid"));
+    let published = eventually("the second event", || {
+        let published = events(&file);
+        (published.len() == 2).then_some(published)
+    });
+    assert_eq!(published[1]["sequence"], 2);
+    assert_eq!(published[1]["type"], "created");
+    assert_eq!(server.request("GET", &format!("/api/users/{id}"), None).status, 200);
+}
+
+#[test]
+fn purges_wait_for_every_sink_to_take_the_changes() {
+    let server = Server::start(&["--soft-delete-retention-hours", "0"]);
+    let id = server.create_user(user("Mickiewicz"));
+    let deleted = server.request("DELETE", &format!("/api/users/{id}"), Some(&json!({"requestHeader": header()})));
+    assert_eq!(deleted.status, 204);
+    let workdir = server.crash();
+
+    // A sink that cannot take the changes keeps the user at the startup purge.
+    let args = webhook_sink(workdir.path(), &closed_port());
+    let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+    args.extend(["--soft-delete-retention-hours", "0"]);
+    let server = Server::start_in(workdir, &args);
+    // The purge runs right after startup; give it time to have run.
+    std::thread::sleep(Duration::from_millis(500));
+    let listed = server.request("GET", "/api/users?includeDeleted=true", None).json();
+    assert_eq!(listed["usersList"].as_array().unwrap().len(), 1, "{listed}");
+
+    // Without it, nothing holds the purge back.
+    let server = Server::start_in(server.crash(), &["--soft-delete-retention-hours", "0"]);
+    let listed = eventually("the purge", || {
+        let listed = server.request("GET", "/api/users?includeDeleted=true", None).json();
+        listed["usersList"].as_array()?.is_empty().then_some(listed)
+    });
+    assert_eq!(listed["usersList"], json!([]));
+}
+
+#[test]
+fn the_outbox_file_never_holds_personal_data() {
+    let workdir = common::Workdir::new();
+    let file = workdir.path().join("events.ndjson");
+    let (addr, received) = receiver(vec![200]);
+    let mut args = webhook_sink(workdir.path(), &addr);
+    args.extend(["--outbox-include-personal-data".into(), "true".into(), "--outbox-file".into()]);
+    args.push(file.to_str().unwrap().into());
+    let server = Server::start_in(workdir, &args.iter().map(String::as_str).collect::<Vec<_>>());
+    server.create_user(user("Mickiewicz"));
+
+    let delivery = received.recv_timeout(Duration::from_secs(10)).expect("delivered");
+    let delivered: Value = serde_json::from_str(&delivery.body).unwrap();
+    assert_eq!(delivered["events"][0]["user"]["personalId"], "98122412345");
+    let written = eventually("the event in the file", || events(&file).into_iter().next());
+    assert_eq!(written["user"]["personalId"], "*******2345", "{written}");
+    assert_eq!(written["user"]["surname"], "M***");
+}
@@ -0,0 +1,48 @@
+//! Rate limits: every request counts against its source IP whatever key it
+//! carries, and requests are classified by the route they matched.
+
+mod common;
+
+use common::{Server, ADMIN_KEY, AUDITOR_KEY, READER_KEY, WRITER_KEY};
+use serde_json::json;
+
+fn seeded(args: &[&str]) -> (Server, String) {
+    let mut all = vec!["--rate-limit-enabled", "true"];
+    all.extend_from_slice(args);
+    let server = Server::start(&all);
+    let id = server.create_user(json!({
+        "name": "Adam", "surname": "Mickiewicz", "age": 30, "personalId": "98122412345", "citizenship": "PL"
+    }));
+    (server, id)
+}
+
+#[test]
+fn cycling_keys_does_not_lift_the_limit_of_a_source_ip() {
+    let (server, id) = seeded(&["--rate-limit-read-per-minute", "3"]);
+    let path = format!("/api/users/{id}");
+
+    for key in [ADMIN_KEY, WRITER_KEY, READER_KEY] {
+        assert_eq!(server.request_as(Some(key), "GET", &path, None).status, 200);
+    }
+    let limited = server.request_as(Some(AUDITOR_KEY), "GET", &path, None);
+    assert_eq!(limited.status, 429, "{}", limited.text);
+    assert_eq!(limited.json()["code"], "RATE_LIMITED");
+    assert!(limited.header("retry-after").is_some());
+    assert_eq!(server.request_as(None, "GET", &path, None).status, 429);
+}
+
+#[test]
+fn requests_are_classified_by_the_matched_route() {
+    let (server, id) = seeded(&["--rate-limit-list-per-minute", "1", "--rate-limit-read-per-minute", "100"]);
+
+    let revisions = format!("/api/users/{id}/revisions");
+    assert_eq!(server.request("GET", &revisions, None).status, 200);
+    // Listing the revisions of another user draws on the same list allowance.
+    let other = format!("/api/users/{}/revisions", uuid::Uuid::new_v4());
+    assert_eq!(server.request("GET", &other, None).status, 429);
+    assert_eq!(server.request("GET", "/api/users", None).status, 429);
+
+    // A single revision, or the user, is a read.
+    assert_eq!(server.request("GET", &format!("{revisions}/1"), None).status, 200);
+    assert_eq!(server.request("GET", &format!("/api/users/{id}"), None).status, 200);
+}
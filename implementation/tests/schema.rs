@@ -0,0 +1,98 @@
+//! Runs the server with strict schema validation: requests breaking the OpenAPI
+//! document are refused with the violations listed and counted, and the
+//! responses of the user operations match the document.
+
+mod common;
+
+use common::{header, Server};
+use serde_json::{json, Value};
+
+fn user(citizenship: &str) -> Value {
+    json!({
+        "name": "Adam",
+        "surname": "Mickiewicz",
+        "age": 30,
+        "personalId": "98122412345",
+        "citizenship": citizenship,
+        "email": "adam@example.com"
+    })
+}
+
+fn keywords(body: &Value) -> Vec<(String, String)> {
+    body["violations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| (v["path"].as_str().unwrap().to_owned(), v["keyword"].as_str().unwrap().to_owned()))
+        .collect()
+}
+
+#[test]
+fn requests_breaking_the_document_are_refused() {
+    let server = Server::start(&["--validation-mode", "strict"]);
+
+    let answer = server.request("POST", "/api/users", Some(&json!({"requestHeader": header(), "user": user("FR")})));
+    let (status, body) = (answer.status, answer.json());
+    assert_eq!(status, 400);
+    assert_eq!(body["code"], "SCHEMA_VIOLATION");
+    assert_eq!(keywords(&body), [("/user/citizenship".to_owned(), "enum".to_owned())]);
+    assert!(!body.to_string().contains("FR"), "violations repeat the value sent");
+
+    let answer = server.request("POST", "/api/users", Some(&json!({"user": {"age": "thirty"}})));
+    let (status, body) = (answer.status, answer.json());
+    assert_eq!(status, 400);
+    let found = keywords(&body);
+    for expected in [("/requestHeader", "required"), ("/user/age", "type"), ("/user/name", "required")] {
+        assert!(found.contains(&(expected.0.to_owned(), expected.1.to_owned())), "{expected:?} not in {found:?}");
+    }
+
+    let answer = server.request("GET", "/api/users?includeDeleted=maybe", None);
+    let (status, body) = (answer.status, answer.json());
+    assert_eq!(status, 400);
+    assert_eq!(keywords(&body), [("includeDeleted".to_owned(), "type".to_owned())]);
+    let answer = server.request("GET", "/api/users/not-a-uuid", None);
+    let (status, body) = (answer.status, answer.json());
+    assert_eq!(status, 400);
+    assert_eq!(keywords(&body), [("id".to_owned(), "format".to_owned())]);
+
+    let metrics = server.request("GET", "/metrics", None).text;
+    let counted = metrics
+        .lines()
+        .find(|line| {
+            line.starts_with("users_schema_violations_total")
+                && line.contains(r#"direction="request""#)
+                && line.contains(r#"keyword="enum""#)
+                && line.contains(r#"operation="CreateUser""#)
+        })
+        .unwrap_or_else(|| panic!("violation not counted:\n{metrics}"));
+    assert!(counted.ends_with(" 1"), "{counted}");
+}
+
+#[test]
+fn user_operations_answer_as_documented() {
+    let server = Server::start(&["--validation-mode", "strict"]);
+
+    let id = server.create_user(user("PL"));
+
+    let mut updated = user("DE");
+    updated["id"] = json!(id);
+    let checks: Vec<(&str, String, Option<Value>)> = vec![
+        ("GET", "/api/users".into(), None),
+        ("GET", format!("/api/users/{id}"), None),
+        ("PUT", format!("/api/users/{id}"), Some(json!({"requestHeader": header(), "user": updated}))),
+        ("GET", format!("/api/users/{id}/revisions"), None),
+        ("GET", format!("/api/users/{id}/revisions/2"), None),
+        ("GET", format!("/api/users/{id}/revisions/diff?from=1&to=2"), None),
+        ("POST", "/api/users/search".into(), Some(json!({"requestHeader": header(), "name": "Adam"}))),
+        ("GET", "/api/users/duplicates".into(), None),
+        ("GET", "/api/audit?limit=10".into(), None),
+        ("GET", "/api/users/00000000-0000-4000-8000-000000000000".into(), None),
+        ("DELETE", format!("/api/users/{id}"), Some(json!({"requestHeader": header()}))),
+        ("GET", "/health/ready".into(), None),
+    ];
+    for (method, path, body) in checks {
+        let answer = server.request(method, &path, body.as_ref());
+        assert!(answer.status < 500, "{method} {path} answered {}: {}", answer.status, answer.text);
+        assert!(!answer.text.contains("SCHEMA_VIOLATION"), "{method} {path}: {}", answer.text);
+    }
+}
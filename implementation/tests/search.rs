@@ -0,0 +1,82 @@
+//! User search: exact personal id and email, and names matched ignoring case
+//! and Polish diacritics with a typo or two, down to a similarity of 0.75. Hits
+//! are masked without the pii scope.
+
+mod common;
+
+use common::{header, Server, AUDITOR_KEY, READER_KEY};
+use serde_json::{json, Value};
+
+fn seeded() -> Server {
+    let server = Server::start(&[]);
+    for (name, surname, personal_id) in [
+        ("Adam", "Mickiewicz", "98122412345"),
+        ("Juliusz", "Słowacki", "09240112345"),
+        ("Stanisław", "Żółkiewski", "47031512345"),
+    ] {
+        server.create_user(json!({
+            "name": name, "surname": surname, "age": 40, "personalId": personal_id, "citizenship": "PL"
+        }));
+    }
+    server
+}
+
+/// Surnames and scores of the hits, best first.
+fn search(server: &Server, criteria: Value) -> Vec<(String, f64)> {
+    let mut body = criteria;
+    body["requestHeader"] = header();
+    let answer = server.request_as(Some(AUDITOR_KEY), "POST", "/api/users/search", Some(&body));
+    assert_eq!(answer.status, 200, "{}", answer.text);
+    answer.json()["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hit| (hit["user"]["surname"].as_str().unwrap().to_owned(), hit["score"].as_f64().unwrap()))
+        .collect()
+}
+
+#[test]
+fn names_match_despite_a_missing_letter() {
+    let server = seeded();
+    assert_eq!(search(&server, json!({"surname": "Mickiewic"})), [("Mickiewicz".to_owned(), 0.9)]);
+    assert_eq!(search(&server, json!({"surname": "Mickiewicz"})), [("Mickiewicz".to_owned(), 1.0)]);
+}
+
+#[test]
+fn names_scoring_below_three_quarters_do_not_match() {
+    let server = seeded();
+    // One edit in four letters is exactly the threshold, two are below it.
+    assert_eq!(search(&server, json!({"name": "Adan"})), [("Mickiewicz".to_owned(), 0.75)]);
+    assert!(search(&server, json!({"name": "Edan"})).is_empty());
+    // Three of ten letters missing scores 0.7.
+    assert!(search(&server, json!({"surname": "Mickiew"})).is_empty());
+    // Each criterion given has to match.
+    assert!(search(&server, json!({"name": "Adam", "surname": "Słowacki"})).is_empty());
+}
+
+#[test]
+fn case_and_diacritics_are_ignored() {
+    let server = seeded();
+    assert_eq!(search(&server, json!({"surname": "slowacki"})), [("Słowacki".to_owned(), 1.0)]);
+    assert_eq!(search(&server, json!({"surname": "ZOLKIEWSKI"})), [("Żółkiewski".to_owned(), 1.0)]);
+    assert_eq!(search(&server, json!({"name": "stanislaw", "surname": "żółkiewski"})).len(), 1);
+}
+
+#[test]
+fn searching_needs_the_read_scope() {
+    let server = seeded();
+    let body = json!({"requestHeader": header(), "personalId": "98122412345"});
+
+    assert_eq!(server.request_as(None, "POST", "/api/users/search", Some(&body)).status, 401);
+    assert_eq!(server.request_as(Some("not-a-key"), "POST", "/api/users/search", Some(&body)).status, 401);
+    let found = server.request_as(Some(READER_KEY), "POST", "/api/users/search", Some(&body));
+    assert_eq!(found.status, 200);
+    let hit = &found.json()["results"][0]["user"];
+    assert_eq!((hit["surname"].as_str(), hit["personalId"].as_str()), (Some("M***"), Some("*******2345")), "{hit}");
+    assert!(!found.text.contains("98122412345"), "{}", found.text);
+    let found = server.request_as(Some(AUDITOR_KEY), "POST", "/api/users/search", Some(&body));
+    assert_eq!(found.json()["results"][0]["user"]["personalId"], "98122412345");
+
+    let empty = server.request_as(Some(READER_KEY), "POST", "/api/users/search", Some(&json!({"requestHeader": header()})));
+    assert_eq!(empty.status, 400);
+}
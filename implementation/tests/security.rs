@@ -0,0 +1,30 @@
+//! Security headers: HSTS only on requests that came in over HTTPS.
+
+mod common;
+
+use common::Server;
+
+fn hsts(server: &Server, headers: &[(&str, &str)]) -> Option<String> {
+    let answer = server.send(None, "GET", "/health/live", headers, None);
+    assert_eq!(answer.status, 200, "{}", answer.text);
+    assert_eq!(answer.header("x-content-type-options"), Some("nosniff"));
+    answer.header("strict-transport-security").map(str::to_owned)
+}
+
+#[test]
+fn hsts_is_sent_over_https_only() {
+    let server = Server::start(&["--hsts-max-age-secs", "600"]);
+
+    assert_eq!(hsts(&server, &[]), None);
+    assert_eq!(hsts(&server, &[("X-Forwarded-Proto", "http")]), None);
+    assert_eq!(
+        hsts(&server, &[("X-Forwarded-Proto", "https")]).as_deref(),
+        Some("max-age=600; includeSubDomains")
+    );
+    // The first proxy, nearest the client, decides.
+    assert!(hsts(&server, &[("X-Forwarded-Proto", "HTTPS, http")]).is_some());
+    assert_eq!(hsts(&server, &[("X-Forwarded-Proto", "http, https")]), None);
+
+    let server = Server::start(&["--hsts-max-age-secs", "0"]);
+    assert_eq!(hsts(&server, &[("X-Forwarded-Proto", "https")]), None);
+}
@@ -0,0 +1,52 @@
+//! The accept loop: running out of file descriptors slows it down instead of
+//! spinning, and it recovers once descriptors are available again.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use common::Server;
+
+fn limit_open_files(pid: u32, soft: usize) {
+    let status = Command::new("prlimit")
+        .arg(format!("--pid={pid}"))
+        .arg(format!("--nofile={soft}:"))
+        .status()
+        .expect("prlimit runs");
+    assert!(status.success());
+}
+
+fn failed_accepts(server: &Server) -> usize {
+    server
+        .logs()
+        .iter()
+        .filter(|entry| entry["fields"]["message"].as_str().is_some_and(|m| m.starts_with("accept failed")))
+        .count()
+}
+
+#[test]
+fn failed_accepts_are_retried_after_a_pause() {
+    let server = Server::start(&[]);
+    let pid = server.pid();
+    let open = std::fs::read_dir(format!("/proc/{pid}/fd")).unwrap().count();
+
+    // No room for another descriptor: connections wait in the backlog and every
+    // accept fails.
+    limit_open_files(pid, open);
+    let mut waiting: Vec<TcpStream> = (0..3).map(|_| TcpStream::connect(&server.addr).unwrap()).collect();
+    thread::sleep(Duration::from_secs(1));
+    let failed = failed_accepts(&server);
+    assert!((1..=20).contains(&failed), "{failed} failed accepts in a second");
+
+    limit_open_files(pid, open + 64);
+    let stream = &mut waiting[0];
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    write!(stream, "GET /health/live HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+}
@@ -0,0 +1,56 @@
+//! Sends SIGTERM to a running server while a create request is half written and
+//! checks that the request still completes and reaches the journal.
+#![cfg(unix)]
+
+mod common;
+
+use std::io::{Read, Write};
+use std::thread;
+use std::time::Duration;
+
+use common::{Server, ADMIN_KEY};
+
+const BODY: &str = r#"{"requestHeader":{"requestId":"6ba7b810-9dad-11d1-80b4-00c04fd430c8","sendDate":"2024-01-01T00:00:00Z"},"user":{"name":"Juliusz","surname":"Slowacki","age":40,"personalId":"09240112345","citizenship":"PL"}}"#;
+
+/// Status line of a GET, or None if the server does not answer.
+fn get(server: &Server, path: &str) -> Option<String> {
+    let response = server.raw(&format!("GET {path} HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n"))?;
+    Some(status_line(&response))
+}
+
+fn status_line(response: &str) -> String {
+    response.lines().next().unwrap_or_default().to_owned()
+}
+
+#[test]
+fn sigterm_mid_write_completes_the_request_and_persists_it() {
+    let mut server = Server::start(&["--shutdown-grace-period-secs", "1", "--shutdown-drain-timeout-secs", "5"]);
+
+    let mut writer = server.connect().unwrap();
+    let (head, tail) = BODY.split_at(BODY.len() / 2);
+    write!(
+        writer,
+        "POST /api/users HTTP/1.1\r\nHost: test\r\nAuthorization: Bearer {ADMIN_KEY}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{head}",
+        BODY.len()
+    )
+    .unwrap();
+    writer.flush().unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    server.signal("TERM");
+    thread::sleep(Duration::from_millis(300));
+    let ready = get(&server, "/health/ready").expect("still serving during the grace period");
+    assert!(ready.contains(" 503 "), "readiness during shutdown: {ready}");
+
+    writer.write_all(tail.as_bytes()).unwrap();
+    let mut response = String::new();
+    let _ = writer.read_to_string(&mut response);
+    assert!(status_line(&response).contains(" 201 "), "create response: {response}");
+
+    let status = server.wait(Duration::from_secs(15));
+    assert!(status.success(), "exit status: {status}");
+    assert!(get(&server, "/health/live").is_none(), "listener still open");
+
+    let journal = std::fs::read_to_string(server.workdir.journal()).unwrap();
+    assert!(journal.contains("Slowacki"), "user missing from the journal");
+}
@@ -0,0 +1,206 @@
+mod common;
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process::Command;
+
+use serde_json::{json, Value};
+
+use common::{header, Server, AUDITOR_KEY};
+
+fn user(surname: &str) -> Value {
+    json!({"name": "Adam", "surname": surname, "age": 30, "personalId": "98122412345", "citizenship": "PL"})
+}
+
+fn update(server: &Server, id: &str, surname: &str) {
+    let mut user = user(surname);
+    user["id"] = json!(id);
+    let updated = server.request("PUT", &format!("/api/users/{id}"), Some(&json!({"requestHeader": header(), "user": user})));
+    assert_eq!(updated.status, 200, "update: {}", updated.text);
+}
+
+fn journal_lines(server: &Server) -> Vec<Value> {
+    std::fs::read_to_string(server.workdir.journal())
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn journal_storage_is_the_default() {
+    let output = common::run(&["--auth-open", "true", "--print-config"]);
+    assert!(output.status.success());
+    let config = String::from_utf8_lossy(&output.stdout);
+    assert!(config.contains("backend = \"journal\""), "{config}");
+}
+
+#[test]
+fn commits_write_only_the_revisions_they_add() {
+    let server = Server::start(&[]);
+    let id = server.create_user(user("Mickiewicz"));
+    update(&server, &id, "Słowacki");
+
+    let lines = journal_lines(&server);
+    assert_eq!(lines.len(), 2, "{lines:?}");
+    let entry = &lines[1][0];
+    assert!(entry.get("history").is_none(), "{entry}");
+    let added = entry["added"].as_array().unwrap();
+    assert_eq!(added.len(), 1);
+    assert_eq!(added[0]["revision"], 2);
+}
+
+#[test]
+fn replay_restores_users_history_and_feed() {
+    let server = Server::start(&[]);
+    let id = server.create_user(user("Mickiewicz"));
+    update(&server, &id, "Słowacki");
+    let gone = server.create_user(user("Norwid"));
+    assert_eq!(
+        server.request("DELETE", &format!("/api/users/{gone}"), Some(&json!({"requestHeader": header()}))).status,
+        204
+    );
+
+    let server = Server::start_in(server.crash(), &[]);
+    let found = server.request_as(Some(AUDITOR_KEY), "GET", &format!("/api/users/{id}"), None);
+    assert_eq!(found.json()["user"]["surname"], "Słowacki");
+    assert_eq!(server.request("GET", &format!("/api/users/{gone}"), None).status, 404);
+    assert_eq!(sequences(&server, &id), [1, 2]);
+
+    // New changes continue the feed after the replayed ones.
+    update(&server, &id, "Krasiński");
+    assert_eq!(sequences(&server, &id), [1, 2, 5]);
+}
+
+#[test]
+fn the_audit_chain_continues_after_a_restart() {
+    let server = Server::start(&[]);
+    let id = server.create_user(user("Mickiewicz"));
+    let server = Server::start_in(server.crash(), &[]);
+    update(&server, &id, "Słowacki");
+
+    let audit = server.request("GET", "/api/audit", None).json();
+    let entries = audit["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 2, "{audit}");
+    assert_eq!(entries[1]["seq"], 2);
+    assert_eq!(entries[1]["prevHash"], entries[0]["hash"]);
+    let written = std::fs::read_to_string(server.workdir.audit()).unwrap();
+    assert_eq!(written.lines().count(), 2, "{written}");
+}
+
+/// Change feed positions of the user's revisions.
+fn sequences(server: &Server, id: &str) -> Vec<u64> {
+    let revisions = server.request("GET", &format!("/api/users/{id}/revisions"), None).json();
+    revisions["revisions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["sequence"].as_u64().unwrap())
+        .collect()
+}
+
+#[test]
+fn an_incomplete_last_line_is_dropped_at_replay() {
+    let server = Server::start(&[]);
+    let id = server.create_user(user("Mickiewicz"));
+    let workdir = server.crash();
+    let mut journal = OpenOptions::new().append(true).open(workdir.journal()).unwrap();
+    journal.write_all(br#"[{"id":"0b0e4f53-7d3c-4c6e-9d3b-2f#"#).unwrap();
+    drop(journal);
+
+    let server = Server::start_in(workdir, &[]);
+    assert_eq!(server.request("GET", &format!("/api/users/{id}"), None).status, 200);
+    assert!(server
+        .logs()
+        .iter()
+        .any(|l| l["fields"]["message"] == "dropped an incomplete last line from the journal"));
+    let other = server.create_user(user("Norwid"));
+
+    // The next commit starts a line of its own.
+    let server = Server::start_in(server.crash(), &[]);
+    assert_eq!(server.request("GET", &format!("/api/users/{id}"), None).status, 200);
+    assert_eq!(server.request("GET", &format!("/api/users/{other}"), None).status, 200);
+}
+
+#[test]
+fn an_append_that_fails_partway_is_cut_off() {
+    let workdir = common::Workdir::new();
+    // Files cannot grow past 1 KiB: a write crossing that stops partway and
+    // fails with EFBIG, rather than killing the server.
+    let server = Server::command(&workdir, &[]);
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg(r#"trap '' XFSZ; ulimit -f 2; exec "$0" "$@""#)
+        .arg(server.get_program())
+        .args(server.get_args())
+        .env_clear();
+    let server = Server::spawn(workdir, command);
+
+    let mut created = Vec::new();
+    let failed = loop {
+        let mut user = user("Mickiewicz");
+        user["personalId"] = json!(format!("9812241234{}", created.len()));
+        let answer = server.request("POST", "/api/users", Some(&json!({"requestHeader": header(), "user": user})));
+        if answer.status != 201 {
+            break answer;
+        }
+        created.push(answer.json()["user"]["id"].as_str().unwrap().to_owned());
+    };
+    assert_eq!(failed.status, 503, "{}", failed.text);
+    let journal = std::fs::read_to_string(server.workdir.journal()).unwrap();
+    assert!(journal.ends_with('\n'), "{journal}");
+    assert_eq!(journal.lines().count(), created.len());
+
+    let server = Server::start_in(server.crash(), &[]);
+    for id in &created {
+        assert_eq!(server.request("GET", &format!("/api/users/{id}"), None).status, 200);
+    }
+    let other = server.create_user(user("Norwid"));
+    let server = Server::start_in(server.crash(), &[]);
+    assert_eq!(server.request("GET", &format!("/api/users/{other}"), None).status, 200);
+}
+
+#[test]
+fn a_corrupt_line_before_the_last_one_stops_startup() {
+    let server = Server::start(&[]);
+    server.create_user(user("Mickiewicz"));
+    let workdir = server.crash();
+    let journal = std::fs::read_to_string(workdir.journal()).unwrap();
+    std::fs::write(workdir.journal(), format!("{{not json\n{journal}")).unwrap();
+
+    let output = common::run(&[
+        "--storage-path",
+        workdir.journal().to_str().unwrap(),
+        "--api-keys-file",
+        workdir.keys().to_str().unwrap(),
+        "--signing-key-file",
+        workdir.signing_key().to_str().unwrap(),
+        "--pseudonym-key-file",
+        workdir.pseudonym_key().to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    let printed = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    assert!(printed.contains("users.journal:1"), "{printed}");
+}
+
+#[test]
+fn an_erasure_that_cannot_compact_the_journal_changes_nothing() {
+    let server = Server::start(&[]);
+    let id = server.create_user(user("Mickiewicz"));
+    // The journal is rewritten through a temporary file next to it.
+    let blocker = server.workdir.path().join("users.compact");
+    std::fs::create_dir(&blocker).unwrap();
+
+    let failed = server.request("POST", &format!("/api/users/{id}/erasure"), None);
+    assert_eq!(failed.status, 503);
+    assert_eq!(failed.json()["code"], "SERVICE_UNAVAILABLE");
+    assert_eq!(server.request("GET", &format!("/api/users/{id}"), None).status, 200);
+
+    std::fs::remove_dir(&blocker).unwrap();
+    assert_eq!(server.request("POST", &format!("/api/users/{id}/erasure"), None).status, 200);
+    let server = Server::start_in(server.crash(), &[]);
+    assert_eq!(server.request("POST", &format!("/api/users/{id}/erasure"), None).status, 410);
+    let journal = std::fs::read_to_string(server.workdir.journal()).unwrap();
+    assert!(!journal.contains("Mickiewicz"), "{journal}");
+}
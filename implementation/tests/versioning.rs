@@ -0,0 +1,175 @@
+//! Serves the same users through v1 and v2: what one version writes the other
+//! reads back converted, what only v2 carries reaches history, the change feed
+//! and exports, and v1 answers announce their v2 successor.
+
+mod common;
+
+use chrono::Datelike;
+use common::{eventually, header, Server, AUDITOR_KEY, READER_KEY};
+use serde_json::{json, Value};
+
+const ARGS: &[&str] = &[
+    "--validation-mode",
+    "strict",
+    "--v1-deprecated-at",
+    "2026-10-01T00:00:00Z",
+    "--v1-sunset-at",
+    "2027-04-01T00:00:00Z",
+];
+
+/// Age of someone born on 1 January 1990, the birth date used below.
+fn age_now() -> u64 {
+    (chrono::Utc::now().year() - 1990) as u64
+}
+
+#[test]
+fn users_written_through_one_version_read_through_the_other() {
+    let server = Server::start(ARGS);
+
+    let created = server.request(
+        "POST",
+        "/api/v2/users",
+        Some(&json!({"requestHeader": header(), "user": {
+            "givenNames": ["Adam", "Bernard"],
+            "familyName": "Mickiewicz",
+            "birthDate": "1990-01-01",
+            "personalId": "90010112345",
+            "citizenship": "PL",
+            "addresses": [{"kind": "home", "street": "Mickiewicza 1", "city": "Kraków", "postalCode": "30-001", "country": "PL"}]
+        }})),
+    );
+    assert_eq!(created.status, 201, "{}", created.json());
+    assert!(created.header("deprecation").is_none(), "v2 is not deprecated");
+    let id = created.json()["user"]["id"].as_str().unwrap().to_owned();
+
+    let v1 = server.request_as(Some(AUDITOR_KEY), "GET", &format!("/api/users/{id}"), None);
+    assert_eq!(v1.status, 200);
+    assert_eq!(v1.json()["user"]["name"], "Adam Bernard");
+    assert_eq!(v1.json()["user"]["surname"], "Mickiewicz");
+    assert_eq!(v1.json()["user"]["age"], age_now());
+    assert_eq!(v1.header("deprecation"), Some("@1790812800"));
+    assert_eq!(v1.header("sunset"), Some("Thu, 01 Apr 2027 00:00:00 GMT"));
+    assert_eq!(
+        v1.header("link"),
+        Some(format!("</api/v2/users/{id}>; rel=\"successor-version\"").as_str())
+    );
+    let list = server.request("GET", "/api/users", None);
+    assert_eq!(list.header("link"), Some("</api/v2/users>; rel=\"successor-version\""));
+    let audit = server.request("GET", "/api/audit", None);
+    assert!(audit.header("deprecation").is_none(), "only replaced operations are deprecated");
+
+    // A v1 write keeps what it does not contradict: the same name keeps the
+    // given names, a new age drops the birth date.
+    let mut user = v1.json()["user"].clone();
+    user["age"] = json!(age_now() + 5);
+    let updated = server.request(
+        "PUT",
+        &format!("/api/users/{id}"),
+        Some(&json!({"requestHeader": header(), "user": user})),
+    );
+    assert_eq!(updated.status, 200, "{}", updated.json());
+    let v2 = server.request_as(Some(AUDITOR_KEY), "GET", &format!("/api/v2/users/{id}"), None);
+    assert_eq!(v2.status, 200);
+    assert_eq!(v2.json()["user"]["givenNames"], json!(["Adam", "Bernard"]));
+    assert!(v2.json()["user"].get("birthDate").is_none(), "{}", v2.json());
+    assert_eq!(v2.json()["user"]["addresses"][0]["city"], "Kraków");
+
+    // Without a birth date, a v2 write keeps the age v1 set.
+    let mut user = v2.json()["user"].clone();
+    user["givenNames"] = json!(["Adam"]);
+    let updated = server.request(
+        "PUT",
+        &format!("/api/v2/users/{id}"),
+        Some(&json!({"requestHeader": header(), "user": user})),
+    );
+    assert_eq!(updated.status, 200, "{}", updated.json());
+    let v1 = server.request_as(Some(AUDITOR_KEY), "GET", &format!("/api/users/{id}"), None);
+    assert_eq!(v1.json()["user"]["name"], "Adam");
+    assert_eq!(v1.json()["user"]["age"], age_now() + 5);
+
+    let listed = server.request("GET", "/api/v2/users", None);
+    assert_eq!(listed.status, 200);
+    assert_eq!(listed.json()["users"].as_array().unwrap().len(), 1);
+
+    assert_eq!(server.request("DELETE", &format!("/api/v2/users/{id}"), None).status, 204);
+    assert_eq!(server.request("GET", &format!("/api/users/{id}"), None).status, 404);
+    assert_eq!(server.request("GET", &format!("/api/v2/users/{id}"), None).status, 404);
+}
+
+#[test]
+fn v2_refuses_users_v1_could_not_store() {
+    let server = Server::start(ARGS);
+    let user = json!({
+        "givenNames": ["Adam"],
+        "familyName": "Mickiewicz",
+        "personalId": "90010112345",
+        "citizenship": "PL"
+    });
+
+    let created = server.request("POST", "/api/v2/users", Some(&json!({"requestHeader": header(), "user": user})));
+    assert_eq!(created.status, 400);
+    assert_eq!(created.json()["code"], "INVALID_USER");
+    assert!(created.json()["message"].as_str().unwrap().contains("birth_date"));
+
+    let mut unborn = user.clone();
+    unborn["birthDate"] = json!("2999-01-01");
+    let created = server.request("POST", "/api/v2/users", Some(&json!({"requestHeader": header(), "user": unborn})));
+    assert_eq!(created.status, 400);
+    assert_eq!(created.json()["code"], "INVALID_USER");
+
+    assert_eq!(server.request("GET", "/api/v2/users", None).json()["users"], json!([]));
+}
+
+#[test]
+fn v2_profiles_reach_history_the_change_feed_and_exports() {
+    let workdir = common::Workdir::new();
+    let file = workdir.path().join("events.ndjson");
+    let server = Server::start_in(workdir, &["--outbox-file", file.to_str().unwrap()]);
+    let mut user = json!({
+        "givenNames": ["Adam", "Bernard"],
+        "familyName": "Mickiewicz",
+        "birthDate": "1990-01-01",
+        "personalId": "90010112345",
+        "citizenship": "PL",
+        "addresses": [{"kind": "home", "street": "Mickiewicza 1", "city": "Kraków", "postalCode": "30-001", "country": "PL"}]
+    });
+    let created = server.request("POST", "/api/v2/users", Some(&json!({"requestHeader": header(), "user": user})));
+    assert_eq!(created.status, 201, "{}", created.json());
+    let id = created.json()["user"]["id"].as_str().unwrap().to_owned();
+    user["addresses"][0]["city"] = json!("Gdańsk");
+    let updated = server.request("PUT", &format!("/api/v2/users/{id}"), Some(&json!({"requestHeader": header(), "user": user})));
+    assert_eq!(updated.status, 200, "{}", updated.json());
+
+    let revision = server.request("GET", &format!("/api/users/{id}/revisions/1"), None).json();
+    assert_eq!(revision["revision"]["profile"]["birthDate"], "1990-01-01", "{revision}");
+    assert_eq!(revision["revision"]["profile"]["addresses"][0]["city"], "Kraków");
+    let diff = server.request("GET", &format!("/api/users/{id}/revisions/diff?from=1&to=2"), None).json();
+    assert_eq!(diff["changes"][0]["field"], "addresses", "{diff}");
+    assert_eq!(diff["changes"].as_array().unwrap().len(), 1, "{diff}");
+
+    let events: Vec<Value> = eventually("both changes in the outbox", || {
+        let text = std::fs::read_to_string(&file).ok()?;
+        let events: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        (events.len() == 2).then_some(events)
+    });
+    assert_eq!(events[1]["profile"]["givenNames"], json!(["A***", "B***"]), "{}", events[1]);
+    assert!(events[1]["profile"].get("addresses").is_none(), "{}", events[1]);
+
+    let exported = server.send(Some(AUDITOR_KEY), "GET", "/api/users/export", &[], None);
+    let row: Value = serde_json::from_str(exported.text.lines().next().unwrap()).unwrap();
+    assert_eq!(row["birthDate"], "1990-01-01", "{row}");
+    assert_eq!(row["addresses"][0]["city"], "Gdańsk");
+    let csv = server.send(Some(AUDITOR_KEY), "GET", "/api/users/export?format=csv", &[], None);
+    assert!(csv.text.contains("Adam Bernard,1990-01-01,") && csv.text.contains("Gdańsk"), "{}", csv.text);
+    let masked = server.send(Some(READER_KEY), "GET", "/api/users/export", &[], None);
+    let row: Value = serde_json::from_str(masked.text.lines().next().unwrap()).unwrap();
+    assert_eq!(row["givenNames"], json!(["A***", "B***"]), "{row}");
+    assert!(row.get("birthDate").is_none() && row.get("addresses").is_none(), "{row}");
+
+    // Erasure takes the profile out of the history, and v2 no longer finds the user.
+    assert_eq!(server.request("POST", &format!("/api/users/{id}/erasure"), None).status, 200);
+    let revision = server.request("GET", &format!("/api/users/{id}/revisions/1"), None).json();
+    assert!(revision["revision"].get("profile").is_none(), "{revision}");
+    let updated = server.request("PUT", &format!("/api/v2/users/{id}"), Some(&json!({"requestHeader": header(), "user": user})));
+    assert_eq!(updated.status, 404);
+}
@@ -0,0 +1,114 @@
+//! Subscribes a local HTTP receiver to user changes, fails its first delivery and
+//! checks that the retry arrives signed and shows up in the delivery log.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{eventually, receiver, silent, Server};
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+
+const SECRET: &str = "receiver-secret-0123456789";
+const HEADER: &str = r#""requestHeader":{"requestId":"6ba7b810-9dad-11d1-80b4-00c04fd430c8","sendDate":"2024-01-01T00:00:00Z"}"#;
+
+/// Status code and JSON body of a request sent with the admin key.
+fn request(server: &Server, method: &str, path: &str, body: Option<&str>) -> (u16, Value) {
+    let answer = server.send(Some(common::ADMIN_KEY), method, path, &[], body);
+    (answer.status, answer.json())
+}
+
+fn expected_signature(timestamp: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(format!("{timestamp}.{body}").as_bytes());
+    format!("v1={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[test]
+fn failed_delivery_is_retried_signed_and_logged() {
+    let (receiver_addr, received) = receiver(vec![500, 200]);
+    let server = Server::start(&["--webhooks-initial-backoff-secs", "1"]);
+
+    let (status, created) = request(
+        &server,
+        "POST",
+        "/api/webhooks",
+        Some(&format!(
+            r#"{{{HEADER},"url":"http://{receiver_addr}/hooks/users","events":["created"],"secret":"{SECRET}"}}"#
+        )),
+    );
+    assert_eq!(status, 201, "subscription: {created}");
+    let subscription = created["subscription"]["id"].as_str().unwrap().to_owned();
+
+    let (status, user) = request(
+        &server,
+        "POST",
+        "/api/users",
+        Some(&format!(
+            r#"{{{HEADER},"user":{{"name":"Juliusz","surname":"Slowacki","age":40,"personalId":"09240112345","citizenship":"PL"}}}}"#
+        )),
+    );
+    assert_eq!(status, 201, "create: {user}");
+    let user_id = user["user"]["id"].as_str().unwrap();
+
+    let first = received.recv_timeout(Duration::from_secs(10)).expect("first attempt");
+    let retry = received.recv_timeout(Duration::from_secs(10)).expect("retry after backoff");
+    assert_eq!(first.headers["webhook-id"], retry.headers["webhook-id"]);
+    for request in [&first, &retry] {
+        let timestamp = &request.headers["webhook-timestamp"];
+        assert_eq!(request.headers["webhook-signature"], expected_signature(timestamp, &request.body));
+    }
+
+    let payload: Value = serde_json::from_str(&retry.body).unwrap();
+    assert_eq!(payload["subscriptionId"], subscription.as_str());
+    assert_eq!(payload["event"]["type"], "created");
+    assert_eq!(payload["event"]["userId"], user_id);
+    assert_eq!(payload["event"]["user"]["personalId"], "*******2345", "personal data is masked by default");
+
+    let delivery = eventually("the delivery to be recorded", || {
+        let (status, log) = request(&server, "GET", &format!("/api/webhooks/{subscription}/deliveries"), None);
+        assert_eq!(status, 200, "deliveries: {log}");
+        let delivery = log["deliveries"][0].clone();
+        (delivery["status"] == "delivered").then_some(delivery)
+    });
+    assert_eq!(delivery["id"], first.headers["webhook-id"].as_str());
+    assert_eq!(delivery["attempts"], 2);
+    assert_eq!(delivery["lastAttempt"]["statusCode"], 200);
+
+    let (_, listed) = request(&server, "GET", "/api/webhooks", None);
+    assert!(listed["subscriptions"][0].get("secret").is_none(), "secret listed: {listed}");
+}
+
+#[test]
+fn a_silent_receiver_holds_up_only_its_own_deliveries() {
+    let (silent_addr, _) = silent();
+    let (receiver_addr, received) = receiver(vec![200, 200]);
+    let server = Server::start(&["--webhooks-timeout-secs", "60"]);
+    for addr in [silent_addr, receiver_addr] {
+        let (status, created) = request(
+            &server,
+            "POST",
+            "/api/webhooks",
+            Some(&format!(r#"{{{HEADER},"url":"http://{addr}/hooks/users","events":["created"],"secret":"{SECRET}"}}"#)),
+        );
+        assert_eq!(status, 201, "subscription: {created}");
+    }
+
+    for personal_id in ["09240112345", "98122412345"] {
+        let (status, user) = request(
+            &server,
+            "POST",
+            "/api/users",
+            Some(&format!(
+                r#"{{{HEADER},"user":{{"name":"Juliusz","surname":"Slowacki","age":40,"personalId":"{personal_id}","citizenship":"PL"}}}}"#
+            )),
+        );
+        assert_eq!(status, 201, "create: {user}");
+        let delivery = received
+            .recv_timeout(Duration::from_secs(5))
+            .expect("delivered while the other receiver keeps silent");
+        let payload: Value = serde_json::from_str(&delivery.body).unwrap();
+        assert_eq!(payload["event"]["userId"], user["user"]["id"]);
+    }
+}
@@ -1,8 +1,10 @@
 use async_trait::async_trait;
+use axum::body::Body;
 use axum::extract::*;
+use axum::response::Response;
 use axum_extra::extract::{CookieJar, Multipart};
 use bytes::Bytes;
-use http::Method;
+use http::{Method, StatusCode};
 use serde::{Deserialize, Serialize};
 
 use crate::{models, types::*};
@@ -127,9 +129,21 @@ pub enum UpdateUserResponse {
 /// Users
 #[async_trait]
 #[allow(clippy::ptr_arg)]
-pub trait Users {
+pub trait Users: Send + Sync {
   type Claims;
 
+  /// Returned by an operation that cannot answer with one of its documented responses.
+  type Error: std::fmt::Debug + Send + 'static;
+
+    /// Answers for an operation that returned an error. By default the error is
+    /// logged and the answer is a 500 without a body.
+    async fn handle_error(&self, error: Self::Error) -> Response {
+        tracing::error!(?error, "unhandled error");
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        response
+    }
+
     /// Create.
     ///
     /// CreateUser - POST /api/users
@@ -138,8 +152,9 @@ pub trait Users {
     method: Method,
     host: Host,
     cookies: CookieJar,
+        claims: Self::Claims,
             body: models::CreateRequest,
-    ) -> Result<CreateUserResponse, ()>;
+    ) -> Result<CreateUserResponse, Self::Error>;
 
     /// Delete user.
     ///
@@ -151,7 +166,7 @@ pub trait Users {
     cookies: CookieJar,
         claims: Self::Claims,
       path_params: models::DeleteUserPathParams,
-    ) -> Result<DeleteUserResponse, ()>;
+    ) -> Result<DeleteUserResponse, Self::Error>;
 
     /// Get users list.
     ///
@@ -161,7 +176,9 @@ pub trait Users {
     method: Method,
     host: Host,
     cookies: CookieJar,
-    ) -> Result<GetAllUsersResponse, ()>;
+        claims: Self::Claims,
+      query_params: models::GetAllUsersQueryParams,
+    ) -> Result<GetAllUsersResponse, Self::Error>;
 
     /// Get user.
     ///
@@ -171,8 +188,9 @@ pub trait Users {
     method: Method,
     host: Host,
     cookies: CookieJar,
+        claims: Self::Claims,
       path_params: models::GetUserByIdPathParams,
-    ) -> Result<GetUserByIdResponse, ()>;
+    ) -> Result<GetUserByIdResponse, Self::Error>;
 
     /// Update user.
     ///
@@ -185,5 +203,5 @@ pub trait Users {
         claims: Self::Claims,
       path_params: models::UpdateUserPathParams,
             body: models::UpdateRequest,
-    ) -> Result<UpdateUserResponse, ()>;
+    ) -> Result<UpdateUserResponse, Self::Error>;
 }
@@ -0,0 +1,342 @@
+//! Async client for the users API, returning the response enums of the `Users`
+//! trait, so callers match on the same variants the server produces.
+
+use std::fmt;
+use std::time::Duration;
+
+use bytes::Bytes;
+use http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
+use http::{Method, Request, StatusCode, Uri};
+use http_body_util::{BodyExt, Full};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::apis::users::{
+    CreateUserResponse, DeleteUserResponse, GetAllUsersResponse, GetUserByIdResponse, UpdateUserResponse,
+};
+use crate::{models, BASE_PATH};
+
+/// Statuses after which an idempotent call is tried again.
+const RETRY_STATUSES: [StatusCode; 4] = [
+    StatusCode::TOO_MANY_REQUESTS,
+    StatusCode::BAD_GATEWAY,
+    StatusCode::SERVICE_UNAVAILABLE,
+    StatusCode::GATEWAY_TIMEOUT,
+];
+
+/// HTTP/1 client for http and https URLs, trusting the webpki roots.
+pub type HttpClient = Client<HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+/// A new `HttpClient`; the users client and the server's webhooks share it.
+pub fn http_client() -> HttpClient {
+    let connector = HttpsConnectorBuilder::new()
+        .with_webpki_roots()
+        .https_or_http()
+        .enable_http1()
+        .build();
+    Client::builder(TokioExecutor::new()).build(connector)
+}
+
+/// Credentials sent with every request.
+#[derive(Clone)]
+pub enum Auth {
+    /// An API key, sent in the `Bearer` header the API key scheme names.
+    ApiKey(String),
+    /// A token, sent as `Authorization: Bearer <token>`.
+    Bearer(String),
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Auth::ApiKey(_) => f.write_str("ApiKey(<redacted>)"),
+            Auth::Bearer(_) => f.write_str("Bearer(<redacted>)"),
+        }
+    }
+}
+
+/// A call that produced no response of the operation.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The base URL is not an http or https URL.
+    InvalidUrl(String),
+    /// The request could not be sent or the response not read.
+    Transport(String),
+    /// No complete response within the configured timeout.
+    Timeout(Duration),
+    /// A status the operation does not define, e.g. 403, 429 or 500.
+    UnexpectedStatus { status: StatusCode, body: Bytes },
+    /// A success status whose body is not the documented model.
+    Decode { status: StatusCode, error: String },
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::InvalidUrl(url) => write!(f, "`{url}` is not an http or https URL"),
+            ClientError::Transport(e) => write!(f, "transport error: {e}"),
+            ClientError::Timeout(timeout) => write!(f, "no response within {}ms", timeout.as_millis()),
+            ClientError::UnexpectedStatus { status, .. } => write!(f, "unexpected status {status}"),
+            ClientError::Decode { status, error } => write!(f, "cannot decode {status} response: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+/// Client of `/api/users`. Cheap to clone; clones share connections.
+///
+/// Each call sends a new `RequestHeader`. Idempotent calls (GET, PUT, DELETE)
+/// are tried again after transport errors, timeouts and 429, 502, 503 or 504
+/// answers, with the same request id and a doubling pause; creates are sent
+/// once. A retried delete may answer 404 when an earlier attempt went through.
+#[derive(Clone, Debug)]
+pub struct UsersClient {
+    base_url: String,
+    auth: Option<Auth>,
+    timeout: Duration,
+    retries: u32,
+    backoff: Duration,
+    client: HttpClient,
+}
+
+impl UsersClient {
+    /// A client of the server at `base_url`, e.g. `https://users.example.com`,
+    /// without credentials, with a 10s timeout and 2 retries 200ms apart.
+    pub fn new(base_url: &str) -> Result<Self, ClientError> {
+        let base_url = base_url.trim_end_matches('/');
+        let valid = base_url.parse::<Uri>().is_ok_and(|uri| {
+            matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some_and(|h| !h.is_empty())
+        });
+        if !valid {
+            return Err(ClientError::InvalidUrl(base_url.to_owned()));
+        }
+        Ok(UsersClient {
+            base_url: base_url.to_owned(),
+            auth: None,
+            timeout: Duration::from_secs(10),
+            retries: 2,
+            backoff: Duration::from_millis(200),
+            client: http_client(),
+        })
+    }
+
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Limit on each attempt, from sending the request to reading the whole body.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Attempts after the first one for idempotent calls, and the pause before
+    /// the first of them; later pauses double.
+    pub fn with_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Create.
+    ///
+    /// CreateUser - POST /api/users
+    pub async fn create_user(&self, user: models::User) -> Result<CreateUserResponse, ClientError> {
+        let header = request_header();
+        let request_id = header.request_id;
+        let body = encode(&models::CreateRequest::new(header, user))?;
+        let (status, body) = self.send(Method::POST, "/users".into(), Some(body), false).await?;
+        Ok(match status {
+            StatusCode::CREATED => CreateUserResponse::Status201_UserCreatedSuccessfully(decode(status, &body)?),
+            StatusCode::BAD_REQUEST => CreateUserResponse::Status400_BadRequest(error(status, &body, request_id)),
+            StatusCode::UNAUTHORIZED => CreateUserResponse::Status401_Unauthorized(error(status, &body, request_id)),
+            StatusCode::UNPROCESSABLE_ENTITY => {
+                CreateUserResponse::Status422_UnprocessableEntity(error(status, &body, request_id))
+            }
+            _ => return Err(ClientError::UnexpectedStatus { status, body }),
+        })
+    }
+
+    /// Delete user.
+    ///
+    /// DeleteUser - DELETE /api/users/{id}
+    pub async fn delete_user(&self, id: uuid::Uuid) -> Result<DeleteUserResponse, ClientError> {
+        let request_id = uuid::Uuid::new_v4();
+        let (status, body) = self.send(Method::DELETE, format!("/users/{id}"), None, true).await?;
+        Ok(match status {
+            StatusCode::NO_CONTENT => DeleteUserResponse::Status204_NoContent,
+            StatusCode::BAD_REQUEST => DeleteUserResponse::Status400_BadRequest(error(status, &body, request_id)),
+            StatusCode::UNAUTHORIZED => DeleteUserResponse::Status401_Unauthorized(error(status, &body, request_id)),
+            StatusCode::NOT_FOUND => DeleteUserResponse::Status404_UserNotFound(error(status, &body, request_id)),
+            StatusCode::UNPROCESSABLE_ENTITY => {
+                DeleteUserResponse::Status422_UnprocessableEntity(error(status, &body, request_id))
+            }
+            _ => return Err(ClientError::UnexpectedStatus { status, body }),
+        })
+    }
+
+    /// Get users list.
+    ///
+    /// GetAllUsers - GET /api/users
+    pub async fn get_all_users(
+        &self,
+        query_params: &models::GetAllUsersQueryParams,
+    ) -> Result<GetAllUsersResponse, ClientError> {
+        let request_id = uuid::Uuid::new_v4();
+        let query = serde_urlencoded::to_string(query_params).map_err(|e| ClientError::Transport(e.to_string()))?;
+        let path = if query.is_empty() { "/users".into() } else { format!("/users?{query}") };
+        let (status, body) = self.send(Method::GET, path, None, true).await?;
+        Ok(match status {
+            StatusCode::OK => GetAllUsersResponse::Status200_Success(decode(status, &body)?),
+            StatusCode::BAD_REQUEST => GetAllUsersResponse::Status400_BadRequest(error(status, &body, request_id)),
+            StatusCode::UNAUTHORIZED => GetAllUsersResponse::Status401_Unauthorized(error(status, &body, request_id)),
+            StatusCode::UNPROCESSABLE_ENTITY => {
+                GetAllUsersResponse::Status422_UnprocessableEntity(error(status, &body, request_id))
+            }
+            _ => return Err(ClientError::UnexpectedStatus { status, body }),
+        })
+    }
+
+    /// Get user.
+    ///
+    /// GetUserById - GET /api/users/{id}
+    pub async fn get_user_by_id(&self, id: uuid::Uuid) -> Result<GetUserByIdResponse, ClientError> {
+        let request_id = uuid::Uuid::new_v4();
+        let (status, body) = self.send(Method::GET, format!("/users/{id}"), None, true).await?;
+        Ok(match status {
+            StatusCode::OK => GetUserByIdResponse::Status200_Success(decode(status, &body)?),
+            StatusCode::BAD_REQUEST => GetUserByIdResponse::Status400_BadRequest(error(status, &body, request_id)),
+            StatusCode::UNAUTHORIZED => GetUserByIdResponse::Status401_Unauthorized(error(status, &body, request_id)),
+            StatusCode::NOT_FOUND => GetUserByIdResponse::Status404_UserNotFound(error(status, &body, request_id)),
+            StatusCode::UNPROCESSABLE_ENTITY => {
+                GetUserByIdResponse::Status422_UnprocessableEntity(error(status, &body, request_id))
+            }
+            _ => return Err(ClientError::UnexpectedStatus { status, body }),
+        })
+    }
+
+    /// Update user.
+    ///
+    /// UpdateUser - PUT /api/users/{id}
+    pub async fn update_user(&self, id: uuid::Uuid, user: models::User) -> Result<UpdateUserResponse, ClientError> {
+        let header = request_header();
+        let request_id = header.request_id;
+        let body = encode(&models::UpdateRequest::new(header, user))?;
+        let (status, body) = self.send(Method::PUT, format!("/users/{id}"), Some(body), true).await?;
+        Ok(match status {
+            StatusCode::OK => UpdateUserResponse::Status200_Success(decode(status, &body)?),
+            StatusCode::BAD_REQUEST => UpdateUserResponse::Status400_BadRequest(error(status, &body, request_id)),
+            StatusCode::UNAUTHORIZED => UpdateUserResponse::Status401_Unauthorized(error(status, &body, request_id)),
+            StatusCode::NOT_FOUND => UpdateUserResponse::Status404_UserNotFound(error(status, &body, request_id)),
+            StatusCode::UNPROCESSABLE_ENTITY => {
+                UpdateUserResponse::Status422_UnprocessableEntity(error(status, &body, request_id))
+            }
+            _ => return Err(ClientError::UnexpectedStatus { status, body }),
+        })
+    }
+
+    /// Sends the request, trying idempotent ones again as documented on the type.
+    async fn send(
+        &self,
+        method: Method,
+        path: String,
+        body: Option<Bytes>,
+        idempotent: bool,
+    ) -> Result<(StatusCode, Bytes), ClientError> {
+        let uri = format!("{}{BASE_PATH}{path}", self.base_url);
+        let mut backoff = self.backoff;
+        let mut retries = if idempotent { self.retries } else { 0 };
+        loop {
+            let result = self.attempt(&method, &uri, body.clone()).await;
+            let retry = match &result {
+                Ok((status, _)) => RETRY_STATUSES.contains(status),
+                Err(e) => matches!(e, ClientError::Transport(_) | ClientError::Timeout(_)),
+            };
+            if !retry || retries == 0 {
+                return result;
+            }
+            tracing::debug!(%method, %uri, retries, "retrying users API call");
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            retries -= 1;
+        }
+    }
+
+    async fn attempt(&self, method: &Method, uri: &str, body: Option<Bytes>) -> Result<(StatusCode, Bytes), ClientError> {
+        let mut request = Request::builder().method(method).uri(uri).header(ACCEPT, "application/json");
+        if body.is_some() {
+            request = request.header(CONTENT_TYPE, "application/json");
+        }
+        request = match &self.auth {
+            Some(Auth::ApiKey(key)) => request.header("Bearer", key),
+            Some(Auth::Bearer(token)) => request.header(AUTHORIZATION, format!("Bearer {token}")),
+            None => request,
+        };
+        let request = request
+            .body(Full::new(body.unwrap_or_default()))
+            .map_err(|e| ClientError::Transport(e.to_string()))?;
+        let exchange = async {
+            let response = self.client.request(request).await.map_err(|e| ClientError::Transport(error_chain(&e)))?;
+            let status = response.status();
+            let body = response
+                .into_body()
+                .collect()
+                .await
+                .map_err(|e| ClientError::Transport(error_chain(&e)))?
+                .to_bytes();
+            Ok((status, body))
+        };
+        tokio::time::timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| ClientError::Timeout(self.timeout))?
+    }
+}
+
+fn request_header() -> models::RequestHeader {
+    models::RequestHeader::new(uuid::Uuid::new_v4(), chrono::Utc::now())
+}
+
+fn encode(body: &impl Serialize) -> Result<Bytes, ClientError> {
+    serde_json::to_vec(body)
+        .map(Bytes::from)
+        .map_err(|e| ClientError::Transport(e.to_string()))
+}
+
+fn decode<T: DeserializeOwned>(status: StatusCode, body: &[u8]) -> Result<T, ClientError> {
+    serde_json::from_slice(body).map_err(|e| ClientError::Decode {
+        status,
+        error: e.to_string(),
+    })
+}
+
+/// The `Error` in the body. Some errors come without one, e.g. a 401 for a
+/// missing key, or as plain text, e.g. a 400 for an invalid path; those get
+/// the status as code and the text as message.
+fn error(status: StatusCode, body: &[u8], request_id: uuid::Uuid) -> models::Error {
+    serde_json::from_slice(body).unwrap_or_else(|_| {
+        let mut error = models::Error::new(
+            models::ResponseHeader::new(request_id, chrono::Utc::now()),
+            status.as_u16().to_string(),
+        );
+        error.message = Some(String::from_utf8_lossy(body).into_owned()).filter(|m| !m.is_empty());
+        error
+    })
+}
+
+/// The error and its sources, which carry the useful part for connection errors.
+pub fn error_chain(error: &(dyn std::error::Error + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+    message
+}
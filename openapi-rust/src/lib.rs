@@ -20,9 +20,13 @@ pub const API_VERSION: &str = "1.0.0";
 #[cfg(feature = "server")]
 pub mod server;
 
+#[cfg(feature = "client")]
+pub mod client;
+
 pub mod models;
 pub mod types;
 pub mod apis;
+pub mod spec;
 
 #[cfg(feature = "server")]
 pub(crate) mod header;
@@ -13,6 +13,15 @@ pub struct DeleteUserPathParams {
     pub id: uuid::Uuid,
 }
 
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, validator::Validate)]
+#[cfg_attr(feature = "conversion", derive(frunk::LabelledGeneric))]
+pub struct GetAllUsersQueryParams {
+    /// Include soft-deleted users in the list; needs the pii or admin scope
+    #[serde(rename = "includeDeleted")]
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub include_deleted: Option<bool>,
+}
+
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, validator::Validate)]
 #[cfg_attr(feature = "conversion", derive(frunk::LabelledGeneric))]
 pub struct GetUserByIdPathParams {
@@ -867,7 +876,7 @@ lazy_static::lazy_static! {
     static ref RE_USER_CITIZENSHIP: regex::Regex = regex::Regex::new(r"^[A-Z]{2}$").unwrap();
 }
 lazy_static::lazy_static! {
-    static ref RE_USER_EMAIL: regex::Regex = regex::Regex::new(r"^[\\w-\\.]+@([\\w-]+\\.)+[\\w-]{2,4}$").unwrap();
+    static ref RE_USER_EMAIL: regex::Regex = regex::Regex::new(r"^[\w.-]+@([\w-]+\.)+[\w-]{2,4}$").unwrap();
 }
 
 impl User {
@@ -59,13 +59,25 @@ async fn create_user<I, A, C>(
   method: Method,
   host: Host,
   cookies: CookieJar,
+  headers: HeaderMap,
  State(api_impl): State<I>,
           Json(body): Json<models::CreateRequest>,
 ) -> Result<Response, StatusCode>
 where
     I: AsRef<A> + Send + Sync,
-    A: apis::users::Users<Claims = C>,
+    A: apis::users::Users<Claims = C>+ apis::ApiKeyAuthHeader<Claims = C>,
 {
+    // Authentication
+    let claims_in_header = api_impl.as_ref().extract_claims_from_header(&headers, "Bearer").await;
+    let claims = None
+             .or(claims_in_header)
+          ;
+    let Some(claims) = claims else {
+        return Response::builder()
+                        .status(StatusCode::UNAUTHORIZED)
+                        .body(Body::empty())
+                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    };
 
 
       #[allow(clippy::redundant_closure)]
@@ -88,6 +100,7 @@ where
       method,
       host,
       cookies,
+        claims,
               body,
   ).await;
 
@@ -168,10 +181,9 @@ where
                                                   response.body(Body::from(body_content))
                                                 },
                                             },
-                                            Err(_) => {
-                                                // Application code returned an error. This should not happen, as the implementation should
-                                                // return a valid response.
-                                                response.status(500).body(Body::empty())
+                                            Err(why) => {
+                                                // Application code returned an error; it decides how to answer.
+                                                Ok(api_impl.as_ref().handle_error(why).await)
                                             },
                                         };
 
@@ -325,10 +337,9 @@ where
                                                   response.body(Body::from(body_content))
                                                 },
                                             },
-                                            Err(_) => {
-                                                // Application code returned an error. This should not happen, as the implementation should
-                                                // return a valid response.
-                                                response.status(500).body(Body::empty())
+                                            Err(why) => {
+                                                // Application code returned an error; it decides how to answer.
+                                                Ok(api_impl.as_ref().handle_error(why).await)
                                             },
                                         };
 
@@ -338,11 +349,15 @@ where
 
 #[tracing::instrument(skip_all)]
 fn get_all_users_validation(
+  query_params: models::GetAllUsersQueryParams,
 ) -> std::result::Result<(
+  models::GetAllUsersQueryParams,
 ), ValidationErrors>
 {
+  query_params.validate()?;
 
 Ok((
+  query_params,
 ))
 }
 /// GetAllUsers - GET /api/users
@@ -351,21 +366,36 @@ async fn get_all_users<I, A, C>(
   method: Method,
   host: Host,
   cookies: CookieJar,
+  headers: HeaderMap,
+  Query(query_params): Query<models::GetAllUsersQueryParams>,
  State(api_impl): State<I>,
 ) -> Result<Response, StatusCode>
 where
     I: AsRef<A> + Send + Sync,
-    A: apis::users::Users<Claims = C>,
+    A: apis::users::Users<Claims = C>+ apis::ApiKeyAuthHeader<Claims = C>,
 {
+    // Authentication
+    let claims_in_header = api_impl.as_ref().extract_claims_from_header(&headers, "Bearer").await;
+    let claims = None
+             .or(claims_in_header)
+          ;
+    let Some(claims) = claims else {
+        return Response::builder()
+                        .status(StatusCode::UNAUTHORIZED)
+                        .body(Body::empty())
+                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    };
 
 
       #[allow(clippy::redundant_closure)]
       let validation = tokio::task::spawn_blocking(move ||
     get_all_users_validation(
+        query_params,
     )
   ).await.unwrap();
 
   let Ok((
+    query_params,
   )) = validation else {
     return Response::builder()
             .status(StatusCode::BAD_REQUEST)
@@ -377,6 +407,8 @@ where
       method,
       host,
       cookies,
+        claims,
+        query_params,
   ).await;
 
   let mut response = Response::builder();
@@ -456,10 +488,9 @@ where
                                                   response.body(Body::from(body_content))
                                                 },
                                             },
-                                            Err(_) => {
-                                                // Application code returned an error. This should not happen, as the implementation should
-                                                // return a valid response.
-                                                response.status(500).body(Body::empty())
+                                            Err(why) => {
+                                                // Application code returned an error; it decides how to answer.
+                                                Ok(api_impl.as_ref().handle_error(why).await)
                                             },
                                         };
 
@@ -486,13 +517,25 @@ async fn get_user_by_id<I, A, C>(
   method: Method,
   host: Host,
   cookies: CookieJar,
+  headers: HeaderMap,
   Path(path_params): Path<models::GetUserByIdPathParams>,
  State(api_impl): State<I>,
 ) -> Result<Response, StatusCode>
 where
     I: AsRef<A> + Send + Sync,
-    A: apis::users::Users<Claims = C>,
+    A: apis::users::Users<Claims = C>+ apis::ApiKeyAuthHeader<Claims = C>,
 {
+    // Authentication
+    let claims_in_header = api_impl.as_ref().extract_claims_from_header(&headers, "Bearer").await;
+    let claims = None
+             .or(claims_in_header)
+          ;
+    let Some(claims) = claims else {
+        return Response::builder()
+                        .status(StatusCode::UNAUTHORIZED)
+                        .body(Body::empty())
+                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    };
 
 
       #[allow(clippy::redundant_closure)]
@@ -515,6 +558,7 @@ where
       method,
       host,
       cookies,
+        claims,
         path_params,
   ).await;
 
@@ -613,10 +657,9 @@ where
                                                   response.body(Body::from(body_content))
                                                 },
                                             },
-                                            Err(_) => {
-                                                // Application code returned an error. This should not happen, as the implementation should
-                                                // return a valid response.
-                                                response.status(500).body(Body::empty())
+                                            Err(why) => {
+                                                // Application code returned an error; it decides how to answer.
+                                                Ok(api_impl.as_ref().handle_error(why).await)
                                             },
                                         };
 
@@ -799,10 +842,9 @@ where
                                                   response.body(Body::from(body_content))
                                                 },
                                             },
-                                            Err(_) => {
-                                                // Application code returned an error. This should not happen, as the implementation should
-                                                // return a valid response.
-                                                response.status(500).body(Body::empty())
+                                            Err(why) => {
+                                                // Application code returned an error; it decides how to answer.
+                                                Ok(api_impl.as_ref().handle_error(why).await)
                                             },
                                         };
 
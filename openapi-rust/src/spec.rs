@@ -0,0 +1,27 @@
+//! The OpenAPI document of the API, embedded for serving and for checks against
+//! it. `api/openapi.json` is the source; the YAML form is rendered from it.
+
+use serde_json::Value;
+
+/// The document as written in `api/openapi.json`.
+pub const JSON: &str = include_str!("../api/openapi.json");
+
+lazy_static::lazy_static! {
+    static ref DOCUMENT: Value = serde_json::from_str(JSON).expect("api/openapi.json is valid JSON");
+    static ref YAML: String = to_yaml(&DOCUMENT);
+}
+
+/// The parsed document.
+pub fn document() -> &'static Value {
+    &DOCUMENT
+}
+
+/// The document as YAML.
+pub fn yaml() -> &'static str {
+    &YAML
+}
+
+/// Renders a JSON value as YAML, keeping the order of its keys.
+pub fn to_yaml(value: &Value) -> String {
+    serde_yaml::to_string(value).expect("JSON values are representable in YAML")
+}